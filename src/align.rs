@@ -0,0 +1,335 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MdOp {
+    Match(u32),
+    Mismatch(u8),
+    Deletion(Vec<u8>),
+}
+
+// MD:Z strings such as "8G61" or "5^AC1C3"
+pub fn parse_md(md: &str) -> Option<Vec<MdOp>> {
+    let bytes = md.as_bytes();
+    let mut ops = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            let n: u32 = md[start..i].parse().ok()?;
+            if n > 0 {
+                ops.push(MdOp::Match(n));
+            }
+        } else if b == b'^' {
+            i += 1;
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            if start == i {
+                return None;
+            }
+            ops.push(MdOp::Deletion(bytes[start..i].to_vec()));
+        } else if b.is_ascii_alphabetic() {
+            ops.push(MdOp::Mismatch(b));
+            i += 1;
+        } else {
+            return None;
+        }
+    }
+    Some(ops)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BaseKind {
+    Match,
+    Mismatch,
+    Insertion,
+    Deletion,
+    SoftClip,
+}
+
+// one column of a read laid against the reference
+#[derive(Clone, Copy, Debug)]
+pub struct AlignedBase {
+    pub read: Option<u8>,
+    // only known when an MD tag was available
+    pub reference: Option<u8>,
+    // 1-based reference coordinate for columns that consume the reference
+    pub ref_pos: Option<u64>,
+    pub kind: BaseKind,
+}
+
+struct MdCursor {
+    ops: VecDeque<MdOp>,
+}
+
+impl MdCursor {
+    // reference base for the next aligned read base: None when it matches
+    fn next_aligned(&mut self) -> Option<Option<u8>> {
+        match self.ops.pop_front()? {
+            MdOp::Match(n) => {
+                if n > 1 {
+                    self.ops.push_front(MdOp::Match(n - 1));
+                }
+                Some(None)
+            }
+            MdOp::Mismatch(b) => Some(Some(b)),
+            MdOp::Deletion(_) => None,
+        }
+    }
+
+    fn take_deletion(&mut self, len: usize) -> Option<Vec<u8>> {
+        match self.ops.pop_front()? {
+            MdOp::Deletion(bases) if bases.len() == len => Some(bases),
+            _ => None,
+        }
+    }
+}
+
+// lay SEQ out along the reference starting at POS; with an MD tag the
+// reference bases under the read are rebuilt too, without needing a FASTA
+pub fn align_read(
+    seq: &str,
+    cigar: &[(u32, char)],
+    pos: u64,
+    md: Option<&str>,
+) -> Option<Vec<AlignedBase>> {
+    let seq = seq.as_bytes();
+    let mut cursor = match md {
+        Some(md) => Some(MdCursor {
+            ops: parse_md(md)?.into(),
+        }),
+        None => None,
+    };
+    let mut out = Vec::new();
+    let mut q = 0usize;
+    let mut r = pos;
+    for &(len, op) in cigar {
+        let len = len as usize;
+        match op {
+            'M' | '=' | 'X' => {
+                for _ in 0..len {
+                    let read = *seq.get(q)?;
+                    q += 1;
+                    // without MD, = and X still say whether the base matches
+                    let (reference, kind) = match cursor.as_mut().map(|c| c.next_aligned()) {
+                        None if op == '=' => (Some(read), BaseKind::Match),
+                        None if op == 'X' => (None, BaseKind::Mismatch),
+                        None => (None, BaseKind::Match),
+                        Some(None) => return None,
+                        Some(Some(None)) => (Some(read), BaseKind::Match),
+                        Some(Some(Some(b))) => (Some(b), BaseKind::Mismatch),
+                    };
+                    out.push(AlignedBase {
+                        read: Some(read),
+                        reference,
+                        ref_pos: Some(r),
+                        kind,
+                    });
+                    r += 1;
+                }
+            }
+            'I' | 'S' => {
+                let kind = if op == 'I' {
+                    BaseKind::Insertion
+                } else {
+                    BaseKind::SoftClip
+                };
+                for _ in 0..len {
+                    out.push(AlignedBase {
+                        read: Some(*seq.get(q)?),
+                        reference: None,
+                        ref_pos: None,
+                        kind,
+                    });
+                    q += 1;
+                }
+            }
+            'D' => {
+                let deleted = match cursor.as_mut() {
+                    Some(c) => c.take_deletion(len)?.into_iter().map(Some).collect(),
+                    None => vec![None; len],
+                };
+                for reference in deleted {
+                    out.push(AlignedBase {
+                        read: None,
+                        reference,
+                        ref_pos: Some(r),
+                        kind: BaseKind::Deletion,
+                    });
+                    r += 1;
+                }
+            }
+            // N skips reference that MD does not describe
+            'N' => r += len as u64,
+            // H and P carry no bases
+            _ => {}
+        }
+    }
+    Some(out)
+}
+
+// where reference position `target` falls in a read aligned at `pos`: Some(Some(query
+// offset)) on a base, Some(None) inside a deletion or skip, None outside the read
+pub fn query_offset_at(cigar: &[(u32, char)], pos: u64, target: u64) -> Option<Option<usize>> {
+    let (mut q, mut r) = (0usize, pos);
+    for &(len, op) in cigar {
+        let len = len as u64;
+        let covers = (r..r + len).contains(&target);
+        match op {
+            'M' | '=' | 'X' if covers => return Some(Some(q + (target - r) as usize)),
+            'M' | '=' | 'X' => {
+                q += len as usize;
+                r += len;
+            }
+            'I' | 'S' => q += len as usize,
+            'D' | 'N' if covers => return Some(None),
+            'D' | 'N' => r += len,
+            _ => {}
+        }
+    }
+    None
+}
+
+// position labels every 10 reference bases and a tick line under them
+pub fn ruler(aligned: &[AlignedBase]) -> (String, String) {
+    let mut numbers = vec![b' '; aligned.len()];
+    let mut ticks = vec![b' '; aligned.len()];
+    let mut free_from = 0;
+    for (col, base) in aligned.iter().enumerate() {
+        let Some(p) = base.ref_pos else { continue };
+        if p % 10 == 0 {
+            ticks[col] = b'|';
+            let label = p.to_string();
+            // skip a label that would run into the previous one or off the end
+            if col >= free_from && col + label.len() <= aligned.len() {
+                numbers[col..col + label.len()].copy_from_slice(label.as_bytes());
+                free_from = col + label.len() + 1;
+            }
+        } else if p % 5 == 0 {
+            ticks[col] = b'.';
+        }
+    }
+    (
+        String::from_utf8_lossy(&numbers).into_owned(),
+        String::from_utf8_lossy(&ticks).into_owned(),
+    )
+}
+
+// one CIGAR operation with the read bases it covers
+pub struct CigarBlock {
+    pub len: u32,
+    pub op: char,
+    // SEQ bases for ops that consume the query, gap markers for deletions,
+    // empty for H, P and N
+    pub bases: String,
+}
+
+// walk CIGAR and SEQ together, splitting the read into its operations
+pub fn cigar_blocks(seq: &str, cigar: &[(u32, char)]) -> Option<Vec<CigarBlock>> {
+    let mut q = 0usize;
+    let mut blocks = Vec::with_capacity(cigar.len());
+    for &(len, op) in cigar {
+        let bases = match op {
+            'M' | '=' | 'X' | 'I' | 'S' => {
+                let end = q + len as usize;
+                let bases = seq.get(q..end)?.to_string();
+                q = end;
+                bases
+            }
+            // the label carries the length, so long deletions get a short marker
+            'D' => "-".repeat(len.min(10) as usize),
+            _ => String::new(),
+        };
+        blocks.push(CigarBlock { len, op, bases });
+    }
+    if q != seq.len() {
+        return None;
+    }
+    Some(blocks)
+}
+
+// (read offset, reference offset) of every k-mer the two sequences share, ignoring
+// case so soft-masked reference bases still match; the points of a dotplot
+pub fn kmer_dots(read: &[u8], reference: &[u8], k: usize) -> Vec<(usize, usize)> {
+    if k == 0 || read.len() < k || reference.len() < k {
+        return vec![];
+    }
+    let reference = reference.to_ascii_uppercase();
+    let mut at: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (j, kmer) in reference.windows(k).enumerate() {
+        at.entry(kmer).or_default().push(j);
+    }
+    let read = read.to_ascii_uppercase();
+    let mut dots = Vec::new();
+    for (i, kmer) in read.windows(k).enumerate() {
+        if let Some(hits) = at.get(kmer) {
+            dots.extend(hits.iter().map(|&j| (i, j)));
+        }
+    }
+    dots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(aligned: &[AlignedBase]) -> Vec<BaseKind> {
+        aligned.iter().map(|b| b.kind).collect()
+    }
+
+    #[test]
+    fn extended_cigar_marks_matches_and_mismatches_without_md() {
+        let aligned = align_read("ACGTA", &[(2, '='), (1, 'X'), (2, '=')], 100, None).unwrap();
+        use BaseKind::{Match, Mismatch};
+        assert_eq!(kinds(&aligned), vec![Match, Match, Mismatch, Match, Match]);
+        let positions: Vec<_> = aligned.iter().map(|b| b.ref_pos).collect();
+        assert_eq!(positions, (100..105).map(Some).collect::<Vec<_>>());
+        // = bases are the reference; the base under an X is unknown without MD
+        assert_eq!(aligned[0].reference, Some(b'A'));
+        assert_eq!(aligned[2].reference, None);
+    }
+
+    #[test]
+    fn plain_m_without_md_is_a_match() {
+        let aligned = align_read("ACGT", &[(4, 'M')], 1, None).unwrap();
+        assert!(aligned.iter().all(|b| b.kind == BaseKind::Match));
+        assert!(aligned.iter().all(|b| b.reference.is_none()));
+    }
+
+    #[test]
+    fn cigar_blocks_split_the_read() {
+        let cigar = [(2, 'S'), (3, 'M'), (1, 'I'), (2, 'D'), (2, '='), (5, 'H')];
+        let blocks = cigar_blocks("TTACGGAC", &cigar).unwrap();
+        let split: Vec<_> = blocks
+            .iter()
+            .map(|b| (b.len, b.op, b.bases.as_str()))
+            .collect();
+        assert_eq!(
+            split,
+            vec![
+                (2, 'S', "TT"),
+                (3, 'M', "ACG"),
+                (1, 'I', "G"),
+                (2, 'D', "--"),
+                (2, '=', "AC"),
+                (5, 'H', ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn cigar_blocks_reject_a_cigar_that_does_not_fit_seq() {
+        assert!(cigar_blocks("ACGT", &[(5, 'M')]).is_none());
+        assert!(cigar_blocks("ACGT", &[(3, 'M')]).is_none());
+    }
+}
@@ -0,0 +1,767 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::{
+    bgzf::{BgzfReader, BgzfWriter},
+    sam::{self, LineFormat, ParsedSam, SAM_MANDATORY},
+    tabix,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+// 4-bit base codes, by position
+const BASES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+const CIGAR_OPS: &[u8; 9] = b"MIDNSHP=X";
+// the BAI bin holding a reference's offsets and mapped/unmapped read counts
+const PSEUDO_BIN: u32 = 37450;
+
+// a regular file named .bam, or any other BGZF file starting with "BAM\1"
+pub fn is_bam(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("bam"))
+    {
+        return true;
+    }
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic[..2]).is_err() || magic[..2] != [31, 139] {
+        return false;
+    }
+    let _ = file.rewind();
+    let mut reader = BgzfReader::new(BufReader::new(file));
+    reader.read_exact(&mut magic).is_ok() && &magic == b"BAM\x01"
+}
+
+type Reader = BgzfReader<BufReader<File>>;
+
+// the header as SAM text lines and every record decoded into the fields of a SAM
+// line, trimmed to `format.keep`; offsets are BGZF virtual offsets. A file cut
+// short keeps the records before the cut
+pub fn read(path: &Path, format: &LineFormat) -> io::Result<ParsedSam> {
+    let mut reader = BgzfReader::new(BufReader::new(File::open(path)?));
+    let (mut parsed, names) = read_header(&mut reader)?;
+    read_records(&mut reader, &names, format, u64::MAX, &mut parsed)?;
+    Ok(parsed)
+}
+
+// the header and only the records in the blocks FILE.bai lists for `region`,
+// some of which may lie outside it; None without an index
+pub fn read_region(
+    path: &Path,
+    region: &sam::Region,
+    format: &LineFormat,
+) -> io::Result<Option<ParsedSam>> {
+    let index_path = index_path(path);
+    if !index_path.is_file() {
+        return Ok(None);
+    }
+    let index = tabix::Index::load(&index_path)
+        .map_err(|e| io::Error::new(e.kind(), format!("index {}: {}", index_path.display(), e)))?;
+    let mut reader = BgzfReader::new(BufReader::new(File::open(path)?));
+    let (mut parsed, names) = read_header(&mut reader)?;
+    if let Some(tid) = names.iter().position(|n| *n == region.name) {
+        for (start, end) in index.tid_chunks(tid, region.start, region.end) {
+            reader.seek_virtual(start)?;
+            read_records(&mut reader, &names, format, end, &mut parsed)?;
+        }
+    }
+    Ok(Some(parsed))
+}
+
+// the header text (with @SQ lines from the reference list when it has none) and
+// the reference names that records refer to by number
+fn read_header(reader: &mut Reader) -> io::Result<(ParsedSam, Vec<String>)> {
+    let cut_short = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => invalid("the BAM header is cut short".to_string()),
+        _ => e,
+    };
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic).map_err(cut_short)?;
+    if &magic != b"BAM\x01" {
+        return Err(invalid("not a BAM file".to_string()));
+    }
+    let text_len = read_u32(reader).map_err(cut_short)? as usize;
+    let mut text = vec![0u8; text_len];
+    reader.read_exact(&mut text).map_err(cut_short)?;
+    let mut header: Vec<String> = String::from_utf8_lossy(&text)
+        .trim_end_matches('\0')
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect();
+    let n_ref = read_u32(reader).map_err(cut_short)? as usize;
+    let mut refs = Vec::with_capacity(n_ref);
+    for _ in 0..n_ref {
+        let name_len = read_u32(reader).map_err(cut_short)? as usize;
+        let mut name = vec![0u8; name_len];
+        reader.read_exact(&mut name).map_err(cut_short)?;
+        let len = read_u32(reader).map_err(cut_short)?;
+        let name = String::from_utf8_lossy(&name)
+            .trim_end_matches('\0')
+            .to_string();
+        refs.push((name, len));
+    }
+    // the binary reference list stands in for a text header without @SQ lines
+    if !header.iter().any(|l| l.starts_with("@SQ")) {
+        header.extend(
+            refs.iter()
+                .map(|(name, len)| format!("@SQ\tSN:{}\tLN:{}", name, len)),
+        );
+    }
+    let names: Vec<String> = refs.into_iter().map(|(name, _)| name).collect();
+    let parsed = ParsedSam {
+        header,
+        ..ParsedSam::default()
+    };
+    Ok((parsed, names))
+}
+
+// decode records into `parsed` until the virtual offset `end` or the end of the file
+fn read_records(
+    reader: &mut Reader,
+    names: &[String],
+    format: &LineFormat,
+    end: u64,
+    parsed: &mut ParsedSam,
+) -> io::Result<()> {
+    let mut data = Vec::new();
+    loop {
+        let start = reader.virtual_offset();
+        if start >= end {
+            break;
+        }
+        let record = match reader.fill_buf() {
+            Ok([]) => break,
+            Ok(_) => read_u32(reader).and_then(|len| {
+                data.resize(len as usize, 0);
+                reader.read_exact(&mut data)
+            }),
+            Err(e) => Err(e),
+        };
+        match record {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        match decode(&data, names) {
+            Some(mut fields) => {
+                sam::drop_unkept(&mut fields, format.keep);
+                parsed.rows.push(fields);
+                parsed.offsets.push(start);
+            }
+            None => parsed.malformed += 1,
+        }
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// little-endian reads over one record, None past its end
+struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.data.len() {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.array().map(i32::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    // text up to a NUL, which is consumed
+    fn text(&mut self) -> Option<String> {
+        let end = self.data.iter().position(|&b| b == 0)?;
+        let text = String::from_utf8_lossy(&self.data[..end]).into_owned();
+        self.data = &self.data[end + 1..];
+        Some(text)
+    }
+}
+
+// one alignment record (after block_size) as SAM fields; None when it is malformed
+fn decode(data: &[u8], names: &[String]) -> Option<Vec<String>> {
+    let mut r = Bytes { data };
+    let tid = r.i32()?;
+    let pos = r.i32()?;
+    let name_len = r.u8()? as usize;
+    let mapq = r.u8()?;
+    let _bin = r.u16()?;
+    let n_cigar = r.u16()? as usize;
+    let flag = r.u16()?;
+    let seq_len = r.u32()? as usize;
+    let next_tid = r.i32()?;
+    let next_pos = r.i32()?;
+    let tlen = r.i32()?;
+    let qname = r.take(name_len)?;
+    let qname = String::from_utf8_lossy(qname.strip_suffix(&[0]).unwrap_or(qname)).into_owned();
+    let reference = |tid: i32| match usize::try_from(tid) {
+        Ok(t) => names.get(t).cloned(),
+        Err(_) => Some("*".to_string()),
+    };
+    let mut cigar = String::new();
+    for _ in 0..n_cigar {
+        let op = r.u32()?;
+        cigar.push_str(&(op >> 4).to_string());
+        cigar.push(*CIGAR_OPS.get((op & 0xf) as usize)? as char);
+    }
+    let packed = r.take(seq_len.div_ceil(2))?;
+    let seq: String = (0..seq_len)
+        .map(|i| {
+            let code = packed[i / 2] >> if i % 2 == 0 { 4 } else { 0 };
+            BASES[(code & 0xf) as usize] as char
+        })
+        .collect();
+    let qual = r.take(seq_len)?;
+    let mut fields = vec![
+        qname,
+        flag.to_string(),
+        reference(tid)?,
+        (pos as i64 + 1).to_string(),
+        mapq.to_string(),
+        if cigar.is_empty() {
+            "*".to_string()
+        } else {
+            cigar
+        },
+        if next_tid >= 0 && next_tid == tid {
+            "=".to_string()
+        } else {
+            reference(next_tid)?
+        },
+        (next_pos as i64 + 1).to_string(),
+        tlen.to_string(),
+        if seq_len == 0 { "*".to_string() } else { seq },
+        // 0xff throughout means the qualities are missing
+        if seq_len == 0 || qual[0] == 0xff {
+            "*".to_string()
+        } else {
+            qual.iter()
+                .map(|&q| (q.saturating_add(33)) as char)
+                .collect()
+        },
+    ];
+    while !r.data.is_empty() {
+        fields.push(decode_tag(&mut r)?);
+    }
+    Some(fields)
+}
+
+// TAG:TYPE:VALUE from its binary form; every integer type reads back as `i`
+fn decode_tag(r: &mut Bytes) -> Option<String> {
+    let name = String::from_utf8_lossy(r.take(2)?).into_owned();
+    let kind = r.u8()?;
+    let value = match kind {
+        b'A' => format!("A:{}", r.u8()? as char),
+        b'Z' | b'H' => format!("{}:{}", kind as char, r.text()?),
+        b'B' => {
+            let sub = r.u8()?;
+            let count = r.u32()? as usize;
+            let mut value = format!("B:{}", sub as char);
+            for _ in 0..count {
+                value.push(',');
+                value.push_str(&number(r, sub)?);
+            }
+            value
+        }
+        b'f' => format!("f:{}", number(r, kind)?),
+        _ => format!("i:{}", number(r, kind)?),
+    };
+    Some(format!("{}:{}", name, value))
+}
+
+// one number of a tag or B array, by its type letter
+fn number(r: &mut Bytes, kind: u8) -> Option<String> {
+    Some(match kind {
+        b'c' => (r.u8()? as i8).to_string(),
+        b'C' => r.u8()?.to_string(),
+        b's' => (r.u16()? as i16).to_string(),
+        b'S' => r.u16()?.to_string(),
+        b'i' => r.i32()?.to_string(),
+        b'I' => r.u32()?.to_string(),
+        b'f' => f32::from_le_bytes(r.array()?).to_string(),
+        _ => return None,
+    })
+}
+
+// where the index of `path` is written, next to it as samtools names it
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bai");
+    PathBuf::from(name)
+}
+
+// write `rows` as a BGZF-compressed BAM at `path` with a BAI index next to it;
+// the records are written in coordinate order so the index can be built, and
+// `progress` hears the number written so far now and then
+pub fn write(
+    path: &Path,
+    header: &[String],
+    rows: &[Vec<String>],
+    mut progress: impl FnMut(usize),
+) -> io::Result<()> {
+    let refs: Vec<(String, u64)> = header
+        .iter()
+        .filter(|l| l.starts_with("@SQ"))
+        .filter_map(|l| {
+            let name = sam::header_field(l, "SN")?;
+            let len = sam::header_field(l, "LN")?.parse().ok()?;
+            Some((name.to_string(), len))
+        })
+        .collect();
+    let tids: HashMap<&str, i32> = refs
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.as_str(), i as i32))
+        .collect();
+    let mut records = rows
+        .iter()
+        .map(|fields| encode(fields, &tids))
+        .collect::<io::Result<Vec<Record>>>()?;
+    // unplaced reads (no reference) go last
+    records.sort_by_key(|r| (r.tid as u32, r.pos));
+
+    let mut out = BgzfWriter::new(BufWriter::new(File::create(path)?));
+    let text = coordinate_sorted(header);
+    out.write_all(b"BAM\x01")?;
+    out.write_all(&(text.len() as i32).to_le_bytes())?;
+    out.write_all(text.as_bytes())?;
+    out.write_all(&(refs.len() as i32).to_le_bytes())?;
+    for (name, len) in &refs {
+        out.write_all(&(name.len() as i32 + 1).to_le_bytes())?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(&[0])?;
+        out.write_all(&(*len as i32).to_le_bytes())?;
+    }
+    let mut index = Index::new(refs.len());
+    for (n, record) in records.iter().enumerate() {
+        let start = out.virtual_offset();
+        out.write_all(&record.data)?;
+        index.add(record, start, out.virtual_offset());
+        if n % 10_000 == 0 {
+            progress(n);
+        }
+    }
+    out.finish()?;
+    index.write(&index_path(path))?;
+    progress(records.len());
+    Ok(())
+}
+
+// the header text with @HD saying the records are sorted by coordinate
+fn coordinate_sorted(header: &[String]) -> String {
+    let mut lines: Vec<String> = header
+        .iter()
+        .filter(|l| !l.starts_with("@HD"))
+        .cloned()
+        .collect();
+    let version = header
+        .iter()
+        .find(|l| l.starts_with("@HD"))
+        .and_then(|l| sam::header_field(l, "VN"))
+        .unwrap_or("1.6");
+    lines.insert(0, format!("@HD\tVN:{}\tSO:coordinate", version));
+    lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+// one encoded alignment with what the index needs of it
+struct Record {
+    tid: i32,
+    // 0-based start and exclusive end on the reference
+    pos: i64,
+    end: i64,
+    bin: u32,
+    unmapped: bool,
+    data: Vec<u8>,
+}
+
+fn encode(fields: &[String], tids: &HashMap<&str, i32>) -> io::Result<Record> {
+    if fields.len() < SAM_MANDATORY {
+        return Err(invalid(format!("{}: too few columns", fields[0])));
+    }
+    let qname = &fields[0];
+    let tid_of = |name: &str| match name {
+        "*" => Ok(-1),
+        name => tids
+            .get(name)
+            .copied()
+            .ok_or_else(|| invalid(format!("{}: {} is not an @SQ reference", qname, name))),
+    };
+    let number = |col: usize| {
+        fields[col]
+            .parse::<i64>()
+            .map_err(|_| invalid(format!("{}: bad {}", qname, fields[col])))
+    };
+    let flag = number(1)? as u16;
+    let tid = tid_of(&fields[2])?;
+    let pos = number(3)? - 1;
+    let mapq = number(4)? as u8;
+    let cigar = sam::parse_cigar(&fields[5]).unwrap_or_default();
+    let next_tid = if fields[6] == "=" {
+        tid
+    } else {
+        tid_of(&fields[6])?
+    };
+    let next_pos = number(7)? - 1;
+    let tlen = number(8)? as i32;
+    let seq = if fields[9] == "*" {
+        ""
+    } else {
+        fields[9].as_str()
+    };
+    // reads without reference bases still take one position for the bin
+    let end = if pos < 0 {
+        0
+    } else {
+        sam::reference_end(pos as u64 + 1, fields) as i64
+    };
+    let bin = reg2bin(pos, end);
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&tid.to_le_bytes());
+    data.extend_from_slice(&(pos as i32).to_le_bytes());
+    data.push(qname.len() as u8 + 1);
+    data.push(mapq);
+    data.extend_from_slice(&(bin as u16).to_le_bytes());
+    data.extend_from_slice(&(cigar.len() as u16).to_le_bytes());
+    data.extend_from_slice(&flag.to_le_bytes());
+    data.extend_from_slice(&(seq.len() as u32).to_le_bytes());
+    data.extend_from_slice(&next_tid.to_le_bytes());
+    data.extend_from_slice(&(next_pos as i32).to_le_bytes());
+    data.extend_from_slice(&tlen.to_le_bytes());
+    data.extend_from_slice(qname.as_bytes());
+    data.push(0);
+    for (len, op) in &cigar {
+        let code = CIGAR_OPS.iter().position(|&c| c == *op as u8).unwrap_or(0) as u32;
+        data.extend_from_slice(&(len << 4 | code).to_le_bytes());
+    }
+    let code = |b: u8| {
+        BASES
+            .iter()
+            .position(|&c| c == b.to_ascii_uppercase())
+            .unwrap_or(15) as u8
+    };
+    for pair in seq.as_bytes().chunks(2) {
+        let low = pair.get(1).map_or(0, |&b| code(b));
+        data.push(code(pair[0]) << 4 | low);
+    }
+    if fields[10] == "*" {
+        data.extend(std::iter::repeat_n(0xff, seq.len()));
+    } else if fields[10].len() != seq.len() {
+        return Err(invalid(format!("{}: QUAL and SEQ differ in length", qname)));
+    } else {
+        data.extend(fields[10].bytes().map(|q| q.saturating_sub(33)));
+    }
+    for tag in &fields[SAM_MANDATORY..] {
+        encode_tag(tag, &mut data).ok_or_else(|| invalid(format!("{}: bad tag {}", qname, tag)))?;
+    }
+    let mut block = (data.len() as u32).to_le_bytes().to_vec();
+    block.append(&mut data);
+    Ok(Record {
+        tid,
+        pos,
+        end,
+        bin,
+        unmapped: flag & 0x4 != 0,
+        data: block,
+    })
+}
+
+// TAG:TYPE:VALUE in its binary form; integers take the smallest type that holds them
+fn encode_tag(tag: &str, out: &mut Vec<u8>) -> Option<()> {
+    let mut parts = tag.splitn(3, ':');
+    let (name, kind, value) = (parts.next()?, parts.next()?, parts.next()?);
+    if name.len() != 2 {
+        return None;
+    }
+    out.extend_from_slice(name.as_bytes());
+    match kind {
+        "A" => {
+            out.push(b'A');
+            out.push(*value.as_bytes().first()?);
+        }
+        "i" => {
+            let v: i64 = value.parse().ok()?;
+            match v {
+                0..=0xff => out.extend_from_slice(&[b'C', v as u8]),
+                0x100..=0xffff => {
+                    out.push(b'S');
+                    out.extend_from_slice(&(v as u16).to_le_bytes());
+                }
+                0x10000.. => {
+                    out.push(b'I');
+                    out.extend_from_slice(&(v as u32).to_le_bytes());
+                }
+                -0x80..=-1 => out.extend_from_slice(&[b'c', v as i8 as u8]),
+                -0x8000..=-0x81 => {
+                    out.push(b's');
+                    out.extend_from_slice(&(v as i16).to_le_bytes());
+                }
+                _ => {
+                    out.push(b'i');
+                    out.extend_from_slice(&(v as i32).to_le_bytes());
+                }
+            }
+        }
+        "f" => {
+            out.push(b'f');
+            out.extend_from_slice(&value.parse::<f32>().ok()?.to_le_bytes());
+        }
+        "Z" | "H" => {
+            out.push(kind.as_bytes()[0]);
+            out.extend_from_slice(value.as_bytes());
+            out.push(0);
+        }
+        "B" => {
+            let mut items = value.split(',');
+            let sub = items.next()?;
+            let items: Vec<&str> = items.collect();
+            out.push(b'B');
+            out.push(*sub.as_bytes().first()?);
+            out.extend_from_slice(&(items.len() as u32).to_le_bytes());
+            for item in items {
+                match sub {
+                    "c" => out.push(item.parse::<i8>().ok()? as u8),
+                    "C" => out.push(item.parse::<u8>().ok()?),
+                    "s" => out.extend_from_slice(&item.parse::<i16>().ok()?.to_le_bytes()),
+                    "S" => out.extend_from_slice(&item.parse::<u16>().ok()?.to_le_bytes()),
+                    "i" => out.extend_from_slice(&item.parse::<i32>().ok()?.to_le_bytes()),
+                    "I" => out.extend_from_slice(&item.parse::<u32>().ok()?.to_le_bytes()),
+                    "f" => out.extend_from_slice(&item.parse::<f32>().ok()?.to_le_bytes()),
+                    _ => return None,
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+// the smallest UCSC bin holding 0-based beg..end, as in the SAM specification
+fn reg2bin(beg: i64, end: i64) -> u32 {
+    let end = end - 1;
+    for (shift, first) in [(14, 4681), (17, 585), (20, 73), (23, 9), (26, 1)] {
+        if beg >> shift == end >> shift {
+            return (first + (beg >> shift)) as u32;
+        }
+    }
+    0
+}
+
+// BAI contents per reference: chunks of virtual offsets per bin, the first
+// offset in each 16 kb window, and the pseudo-bin's totals
+#[derive(Default)]
+struct RefIndex {
+    bins: BTreeMap<u32, Vec<(u64, u64)>>,
+    linear: Vec<u64>,
+    span: Option<(u64, u64)>,
+    mapped: u64,
+    unmapped: u64,
+}
+
+struct Index {
+    refs: Vec<RefIndex>,
+    // reads without a reference
+    no_coordinate: u64,
+}
+
+impl Index {
+    fn new(n_ref: usize) -> Index {
+        Index {
+            refs: (0..n_ref).map(|_| RefIndex::default()).collect(),
+            no_coordinate: 0,
+        }
+    }
+
+    fn add(&mut self, record: &Record, start: u64, end: u64) {
+        let Some(r) = usize::try_from(record.tid)
+            .ok()
+            .and_then(|t| self.refs.get_mut(t))
+        else {
+            self.no_coordinate += 1;
+            return;
+        };
+        let chunks = r.bins.entry(record.bin).or_default();
+        match chunks.last_mut() {
+            Some(last) if last.1 == start => last.1 = end,
+            _ => chunks.push((start, end)),
+        }
+        let first = (record.pos.max(0) >> 14) as usize;
+        let last = ((record.end.max(record.pos + 1) - 1).max(0) >> 14) as usize;
+        if r.linear.len() <= last {
+            r.linear.resize(last + 1, 0);
+        }
+        for window in &mut r.linear[first..=last] {
+            if *window == 0 {
+                *window = start;
+            }
+        }
+        r.span = Some((r.span.map_or(start, |(s, _)| s), end));
+        if record.unmapped {
+            r.unmapped += 1;
+        } else {
+            r.mapped += 1;
+        }
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"BAI\x01")?;
+        out.write_all(&(self.refs.len() as i32).to_le_bytes())?;
+        for r in &self.refs {
+            let pseudo = r.span.is_some() as i32;
+            out.write_all(&(r.bins.len() as i32 + pseudo).to_le_bytes())?;
+            for (bin, chunks) in &r.bins {
+                out.write_all(&bin.to_le_bytes())?;
+                out.write_all(&(chunks.len() as i32).to_le_bytes())?;
+                for (start, end) in chunks {
+                    out.write_all(&start.to_le_bytes())?;
+                    out.write_all(&end.to_le_bytes())?;
+                }
+            }
+            if let Some((start, end)) = r.span {
+                out.write_all(&PSEUDO_BIN.to_le_bytes())?;
+                out.write_all(&2i32.to_le_bytes())?;
+                for v in [start, end, r.mapped, r.unmapped] {
+                    out.write_all(&v.to_le_bytes())?;
+                }
+            }
+            // empty windows repeat the offset of the window before them
+            out.write_all(&(r.linear.len() as i32).to_le_bytes())?;
+            let mut previous = 0;
+            for &offset in &r.linear {
+                previous = if offset == 0 { previous } else { offset };
+                out.write_all(&previous.to_le_bytes())?;
+            }
+        }
+        out.write_all(&self.no_coordinate.to_le_bytes())?;
+        out.flush()
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bgzf;
+
+    fn fields(line: &str) -> Vec<String> {
+        line.split('\t').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn written_records_read_back_unchanged() {
+        let header = vec![
+            "@HD\tVN:1.6\tSO:coordinate".to_string(),
+            "@SQ\tSN:chr1\tLN:100000".to_string(),
+            "@SQ\tSN:chr2\tLN:5000".to_string(),
+        ];
+        let rows = vec![
+            fields(
+                "r1\t99\tchr1\t100\t60\t3S5M1I2M2D4M\t=\t300\t210\tTTACGTAGACGTAC\tIIIIIIIIIIIIII\tNM:i:3\tRG:Z:grpA",
+            ),
+            fields("r2\t16\tchr1\t20000\t0\t4M\t*\t0\t0\tACGT\t*\tXA:A:y"),
+            fields("r3\t0\tchr2\t1\t255\t2=1X1=\t*\t0\t0\tACGN\t#5I+"),
+            fields("r4\t4\t*\t0\t0\t*\t*\t0\t0\tNNNN\t!!!!"),
+        ];
+        let path =
+            std::env::temp_dir().join(format!("varview-{}-roundtrip.bam", std::process::id()));
+        write(&path, &header, &rows, |_| {}).unwrap();
+
+        assert!(is_bam(&path));
+        assert!(!bgzf::missing_eof_marker(&path).unwrap());
+        let mut magic = [0u8; 4];
+        BgzfReader::new(BufReader::new(File::open(&path).unwrap()))
+            .read_exact(&mut magic)
+            .unwrap();
+        assert_eq!(&magic, b"BAM\x01");
+
+        let parsed = read(&path, &LineFormat::sam(false, None)).unwrap();
+        assert_eq!(parsed.header, header);
+        assert_eq!(parsed.rows, rows);
+        assert_eq!(parsed.malformed, 0);
+        // virtual offsets of the records, in file order
+        assert!(parsed.offsets.windows(2).all(|w| w[0] < w[1]));
+        assert!(index_path(&path).is_file());
+
+        // through the BAI, only the blocks of the region's bins are read
+        let format = LineFormat::sam(false, None);
+        let region = |name: &str, start, end| sam::Region {
+            name: name.to_string(),
+            start,
+            end,
+        };
+        let found = read_region(&path, &region("chr1", 19000, 21000), &format)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.header, header);
+        assert_eq!(found.rows, vec![rows[1].clone()]);
+        assert_eq!(found.offsets, vec![parsed.offsets[1]]);
+        let found = read_region(&path, &region("chr2", 1, 10), &format)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.rows, vec![rows[2].clone()]);
+        let found = read_region(&path, &region("chrX", 1, 10), &format)
+            .unwrap()
+            .unwrap();
+        assert!(found.rows.is_empty());
+
+        let _ = std::fs::remove_file(index_path(&path));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reg2bin_matches_the_specification() {
+        // level 5 bins are 16 kb wide and start at 4681, level 4 at 585 (128 kb), ...
+        assert_eq!(reg2bin(0, 1), 4681);
+        assert_eq!(reg2bin(0, 1 << 14), 4681);
+        assert_eq!(reg2bin(1 << 14, (1 << 14) + 1), 4682);
+        assert_eq!(reg2bin(0, (1 << 14) + 1), 585);
+        assert_eq!(reg2bin(1 << 17, (1 << 17) + 100), 4681 + 8);
+        assert_eq!(reg2bin(0, (1 << 17) + 1), 73);
+        assert_eq!(reg2bin(0, 1 << 26), 1);
+        assert_eq!(reg2bin(0, (1 << 26) + 1), 0);
+        assert_eq!(reg2bin(0, 1 << 29), 0);
+    }
+}
@@ -0,0 +1,453 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::{bgzf::BgzfReader, sam::ParsedSam};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek},
+    path::Path,
+};
+
+const MAGIC: &[u8; 3] = b"BCF";
+
+// typed value kinds of the BCF2 encoding
+const MISSING: u8 = 0;
+const INT8: u8 = 1;
+const INT16: u8 = 2;
+const INT32: u8 = 3;
+const FLOAT: u8 = 5;
+const CHAR: u8 = 7;
+
+const FLOAT_MISSING: u32 = 0x7F80_0001;
+const FLOAT_END: u32 = 0x7F80_0002;
+
+// binary VCF: a .bcf file, or any other bgzipped (or, from `bcftools view -Ou`,
+// plain) file starting with "BCF\2"
+pub fn is_bcf(path: &Path) -> bool {
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("bcf"))
+    {
+        return path.is_file();
+    }
+    let mut magic = [0u8; 4];
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    if file.read_exact(&mut magic[..2]).is_err() {
+        return false;
+    }
+    if magic[..2] == [31, 139] {
+        let _ = file.rewind();
+        let mut reader = BgzfReader::new(BufReader::new(file));
+        return reader.read_exact(&mut magic).is_ok() && &magic[..3] == MAGIC && magic[3] == 2;
+    }
+    file.read_exact(&mut magic[2..]).is_ok() && &magic[..3] == MAGIC && magic[3] == 2
+}
+
+// the header as VCF text lines and every record decoded into the fields of a VCF
+// line; offsets are BGZF virtual offsets, or byte offsets for uncompressed BCF
+pub fn read(path: &Path) -> io::Result<ParsedSam> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 2];
+    file.read_exact(&mut magic)?;
+    file.rewind()?;
+    if magic == [31, 139] {
+        let mut reader = BgzfReader::new(BufReader::new(file));
+        parse(&mut reader, |r| Ok(r.virtual_offset()))
+    } else {
+        let mut reader = BufReader::new(file);
+        parse(&mut reader, |r| r.stream_position())
+    }
+}
+
+fn parse<R: BufRead>(
+    reader: &mut R,
+    offset: impl Fn(&mut R) -> io::Result<u64>,
+) -> io::Result<ParsedSam> {
+    let cut_short = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => invalid("the BCF header is cut short"),
+        _ => e,
+    };
+    let mut magic = [0u8; 5];
+    reader.read_exact(&mut magic).map_err(cut_short)?;
+    if &magic[..3] != MAGIC || magic[3] != 2 {
+        return Err(invalid("not a BCF2 file"));
+    }
+    let text_len = read_u32(reader).map_err(cut_short)? as usize;
+    let mut text = vec![0u8; text_len];
+    reader.read_exact(&mut text).map_err(cut_short)?;
+    let text = String::from_utf8_lossy(&text);
+    let mut parsed = ParsedSam {
+        header: text
+            .trim_end_matches('\0')
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect(),
+        ..ParsedSam::default()
+    };
+    let dict = Dictionaries::from_header(&parsed.header);
+    loop {
+        let start = offset(reader)?;
+        // a file cut short keeps the records before the cut
+        let record = match reader.fill_buf() {
+            Ok([]) => break,
+            Ok(_) => read_record(reader),
+            Err(e) => Err(e),
+        };
+        let (shared, indiv) = match record {
+            Ok(record) => record,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        match decode(&shared, &indiv, &dict) {
+            Some(fields) => {
+                parsed.rows.push(fields);
+                parsed.offsets.push(start);
+            }
+            None => parsed.malformed += 1,
+        }
+    }
+    Ok(parsed)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// the shared (site) and individual (per-sample) blocks of one record
+fn read_record<R: Read>(reader: &mut R) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let shared_len = read_u32(reader)? as usize;
+    let indiv_len = read_u32(reader)? as usize;
+    let mut shared = vec![0u8; shared_len];
+    reader.read_exact(&mut shared)?;
+    let mut indiv = vec![0u8; indiv_len];
+    reader.read_exact(&mut indiv)?;
+    Ok((shared, indiv))
+}
+
+// records refer to contigs and to FILTER/INFO/FORMAT IDs by number
+struct Dictionaries {
+    contigs: HashMap<i64, String>,
+    strings: HashMap<i64, String>,
+}
+
+impl Dictionaries {
+    // numbered in header order unless a line gives IDX; PASS is always 0 and a key
+    // shared by INFO and FORMAT lines has one number
+    fn from_header(header: &[String]) -> Dictionaries {
+        let mut contigs = HashMap::new();
+        let mut strings = HashMap::from([(0, "PASS".to_string())]);
+        let mut next_contig = 0;
+        let mut next_string = 1;
+        for line in header {
+            let Some((key, fields)) = line
+                .strip_prefix("##")
+                .and_then(|l| l.split_once("=<"))
+                .map(|(k, v)| (k, structured_fields(v.trim_end_matches('>'))))
+            else {
+                continue;
+            };
+            let Some(id) = fields.iter().find(|(k, _)| k == "ID").map(|(_, v)| v) else {
+                continue;
+            };
+            let idx = fields
+                .iter()
+                .find(|(k, _)| k == "IDX")
+                .and_then(|(_, v)| v.parse::<i64>().ok());
+            match key {
+                "contig" => {
+                    let idx = idx.unwrap_or(next_contig);
+                    contigs.insert(idx, id.clone());
+                    next_contig = idx + 1;
+                }
+                "FILTER" | "INFO" | "FORMAT" => {
+                    if strings.values().any(|s| s == id) {
+                        continue;
+                    }
+                    let idx = idx.unwrap_or(next_string);
+                    strings.insert(idx, id.clone());
+                    next_string = next_string.max(idx + 1);
+                }
+                _ => {}
+            }
+        }
+        Dictionaries { contigs, strings }
+    }
+
+    fn string(&self, idx: i64) -> String {
+        self.strings
+            .get(&idx)
+            .cloned()
+            .unwrap_or_else(|| format!("?{}", idx))
+    }
+}
+
+// KEY=VALUE pairs of a structured header line, with quoted values kept whole
+fn structured_fields(body: &str) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    let mut part = String::new();
+    let mut quoted = false;
+    for c in body.chars().chain(std::iter::once(',')) {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                part.push(c);
+            }
+            ',' if !quoted => {
+                if let Some((k, v)) = part.split_once('=') {
+                    fields.push((k.trim().to_string(), v.trim().to_string()));
+                }
+                part.clear();
+            }
+            _ => part.push(c),
+        }
+    }
+    fields
+}
+
+// a value of the typed encoding: ints (None when missing), floats as raw bits so
+// the missing and end-of-vector NaNs stay apart, or characters
+enum Typed {
+    Ints(Vec<Option<i64>>),
+    Floats(Vec<u32>),
+    Chars(Vec<u8>),
+}
+
+impl Typed {
+    fn ints(&self) -> Vec<i64> {
+        match self {
+            Typed::Ints(v) => v.iter().flatten().copied().collect(),
+            _ => vec![],
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Typed::Ints(v) => join(v.iter().map(|i| i.map(|i| i.to_string()))),
+            Typed::Floats(v) => join(
+                v.iter()
+                    .take_while(|&&bits| bits != FLOAT_END)
+                    .map(|&bits| (bits != FLOAT_MISSING).then(|| f32::from_bits(bits).to_string())),
+            ),
+            Typed::Chars(v) => {
+                let end = v.iter().position(|&b| b == 0).unwrap_or(v.len());
+                String::from_utf8_lossy(&v[..end]).into_owned()
+            }
+        }
+    }
+}
+
+// comma list with "." for missing values
+fn join(values: impl Iterator<Item = Option<String>>) -> String {
+    let parts: Vec<String> = values
+        .map(|v| v.unwrap_or_else(|| ".".to_string()))
+        .collect();
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join(",")
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(self.u32()? as i32)
+    }
+
+    // a type descriptor: value kind and count, long counts following as a typed int
+    fn descriptor(&mut self) -> Option<(u8, usize)> {
+        let byte = *self.take(1)?.first()?;
+        let kind = byte & 0x0f;
+        let mut count = (byte >> 4) as usize;
+        if count == 15 {
+            let (kind, n) = self.descriptor()?;
+            count = usize::try_from(*self.values(kind, n)?.ints().first()?).ok()?;
+        }
+        Some((kind, count))
+    }
+
+    fn values(&mut self, kind: u8, count: usize) -> Option<Typed> {
+        // the two lowest values of each width mean missing and end of vector; the
+        // end-of-vector padding is dropped
+        let int = |bytes: &[u8]| -> Option<Option<i64>> {
+            let (value, missing) = match bytes.len() {
+                1 => (bytes[0] as i8 as i64, i8::MIN as i64),
+                2 => (
+                    i16::from_le_bytes([bytes[0], bytes[1]]) as i64,
+                    i16::MIN as i64,
+                ),
+                _ => (
+                    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+                    i32::MIN as i64,
+                ),
+            };
+            match value - missing {
+                0 => Some(None),
+                1 => None,
+                _ => Some(Some(value)),
+            }
+        };
+        match kind {
+            MISSING => Some(Typed::Ints(vec![])),
+            INT8 | INT16 | INT32 => {
+                let width = 1 << (kind - 1);
+                let bytes = self.take(width * count)?;
+                Some(Typed::Ints(bytes.chunks(width).filter_map(int).collect()))
+            }
+            FLOAT => {
+                let bytes = self.take(4 * count)?;
+                Some(Typed::Floats(
+                    bytes
+                        .chunks(4)
+                        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                ))
+            }
+            CHAR => Some(Typed::Chars(self.take(count)?.to_vec())),
+            _ => None,
+        }
+    }
+
+    fn typed(&mut self) -> Option<Typed> {
+        let (kind, count) = self.descriptor()?;
+        self.values(kind, count)
+    }
+
+    // integers with missing values kept as None
+    fn raw_ints(&mut self, kind: u8, count: usize) -> Option<Vec<Option<i64>>> {
+        match self.values(kind, count)? {
+            Typed::Ints(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+// CHROM POS ID REF ALT QUAL FILTER INFO, then FORMAT and one column per sample
+fn decode(shared: &[u8], indiv: &[u8], dict: &Dictionaries) -> Option<Vec<String>> {
+    let mut c = Cursor {
+        data: shared,
+        pos: 0,
+    };
+    let chrom = c.i32()? as i64;
+    let pos = c.i32()? as i64 + 1;
+    let _rlen = c.i32()?;
+    let qual = c.u32()?;
+    let info_allele = c.u32()?;
+    let (n_info, n_allele) = (info_allele & 0xffff, info_allele >> 16);
+    let fmt_sample = c.u32()?;
+    let (n_sample, n_fmt) = ((fmt_sample & 0xff_ffff) as usize, fmt_sample >> 24);
+
+    let id = c.typed()?.text();
+    let mut alleles = vec![];
+    for _ in 0..n_allele {
+        alleles.push(c.typed()?.text());
+    }
+    let filters: Vec<String> = c
+        .typed()?
+        .ints()
+        .into_iter()
+        .map(|i| dict.string(i))
+        .collect();
+    let mut info = vec![];
+    for _ in 0..n_info {
+        let key = dict.string(*c.typed()?.ints().first()?);
+        let (kind, count) = c.descriptor()?;
+        if kind == MISSING || count == 0 {
+            info.push(key);
+        } else {
+            info.push(format!("{}={}", key, c.values(kind, count)?.text()));
+        }
+    }
+
+    let or_dot = |s: String| if s.is_empty() { ".".to_string() } else { s };
+    let mut fields = vec![
+        dict.contigs
+            .get(&chrom)
+            .cloned()
+            .unwrap_or_else(|| chrom.to_string()),
+        pos.to_string(),
+        or_dot(id),
+        alleles.first().cloned().unwrap_or_else(|| ".".to_string()),
+        or_dot(alleles.get(1..).unwrap_or_default().join(",")),
+        if qual == FLOAT_MISSING {
+            ".".to_string()
+        } else {
+            f32::from_bits(qual).to_string()
+        },
+        or_dot(filters.join(";")),
+        or_dot(info.join(";")),
+    ];
+    if n_sample == 0 && n_fmt == 0 {
+        return Some(fields);
+    }
+
+    let mut c = Cursor {
+        data: indiv,
+        pos: 0,
+    };
+    let mut keys = vec![];
+    let mut columns: Vec<Vec<String>> = vec![vec![]; n_sample];
+    for _ in 0..n_fmt {
+        let key = dict.string(*c.typed()?.ints().first()?);
+        let (kind, count) = c.descriptor()?;
+        for column in columns.iter_mut() {
+            let value = if key == "GT" && kind != CHAR && kind != FLOAT {
+                genotype(&c.raw_ints(kind, count)?)
+            } else {
+                let text = c.values(kind, count)?.text();
+                if text.is_empty() {
+                    ".".to_string()
+                } else {
+                    text
+                }
+            };
+            column.push(value);
+        }
+        keys.push(key);
+    }
+    fields.push(or_dot(keys.join(":")));
+    fields.extend(columns.into_iter().map(|c| or_dot(c.join(":"))));
+    Some(fields)
+}
+
+// alleles are stored as (index + 1) << 1 | phased, 0 for a missing call
+fn genotype(values: &[Option<i64>]) -> String {
+    let mut gt = String::new();
+    for (i, value) in values.iter().enumerate() {
+        let v = value.unwrap_or(0);
+        if i > 0 {
+            gt.push(if v & 1 == 1 { '|' } else { '/' });
+        }
+        match (v >> 1) - 1 {
+            -1 => gt.push('.'),
+            allele => gt.push_str(&allele.to_string()),
+        }
+    }
+    if gt.is_empty() { ".".to_string() } else { gt }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,249 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use flate2::{Compression, Crc, read::DeflateDecoder, write::DeflateEncoder};
+use std::{
+    fs::File,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+// uncompressed bytes per written block, small enough that even incompressible
+// data fits the 64 KiB block limit
+const BLOCK_DATA: usize = 0xff00;
+
+// the empty block bgzip writes last; a BGZF file without it was most likely cut short
+const EOF_MARKER: [u8; 28] = [
+    31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, 66, 67, 2, 0, 27, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+// blocked gzip as written by bgzip: a series of gzip members of at most 64 KiB,
+// addressed by virtual offsets (compressed block start << 16 | offset in block)
+pub struct BgzfReader<R> {
+    inner: R,
+    block: Vec<u8>,
+    pos: usize,
+    // compressed offsets of the current block and of the one after it
+    block_start: u64,
+    next_block: u64,
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    pub fn new(inner: R) -> BgzfReader<R> {
+        BgzfReader {
+            inner,
+            block: Vec::new(),
+            pos: 0,
+            block_start: 0,
+            next_block: 0,
+        }
+    }
+
+    pub fn virtual_offset(&self) -> u64 {
+        if self.pos >= self.block.len() {
+            self.next_block << 16
+        } else {
+            (self.block_start << 16) | self.pos as u64
+        }
+    }
+
+    pub fn seek_virtual(&mut self, voffset: u64) -> io::Result<()> {
+        let start = voffset >> 16;
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.next_block = start;
+        self.block.clear();
+        self.pos = 0;
+        self.load_block()?;
+        self.pos = ((voffset & 0xffff) as usize).min(self.block.len());
+        Ok(())
+    }
+
+    // read the block at next_block; false at end of file
+    fn load_block(&mut self) -> io::Result<bool> {
+        let Some((data, size)) = read_block(&mut self.inner)? else {
+            return Ok(false);
+        };
+        self.block_start = self.next_block;
+        self.next_block += size as u64;
+        self.block = data;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> Read for BgzfReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // empty blocks (such as the EOF marker) are skipped
+        while self.pos >= self.block.len() {
+            if !self.load_block()? {
+                return Ok(&[]);
+            }
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.block.len());
+    }
+}
+
+// writes BGZF blocks, tracking virtual offsets for an index
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    // compressed bytes written so far
+    written: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> BgzfWriter<W> {
+        BgzfWriter {
+            inner,
+            buf: Vec::with_capacity(BLOCK_DATA),
+            written: 0,
+        }
+    }
+
+    // where the next byte written will be
+    pub fn virtual_offset(&self) -> u64 {
+        (self.written << 16) | self.buf.len() as u64
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buf)?;
+        let cdata = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.buf);
+        let block_size = 18 + cdata.len() + 8;
+        let mut header = [
+            31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0, 0, 0,
+        ];
+        header[16..].copy_from_slice(&((block_size - 1) as u16).to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&cdata)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner
+            .write_all(&(self.buf.len() as u32).to_le_bytes())?;
+        self.written += block_size as u64;
+        self.buf.clear();
+        Ok(())
+    }
+
+    // write what is buffered and the EOF marker
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buf.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.write_all(&EOF_MARKER)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(BLOCK_DATA - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == BLOCK_DATA {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// one block: its inflated data and its compressed size; None at a clean end of file
+pub fn read_block<R: Read>(reader: &mut R) -> io::Result<Option<(Vec<u8>, usize)>> {
+    let mut header = [0u8; 12];
+    match reader.read_exact(&mut header[..1]) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        other => other?,
+    }
+    reader.read_exact(&mut header[1..])?;
+    if header[0] != 31 || header[1] != 139 || header[2] != 8 || header[3] & 4 == 0 {
+        return Err(invalid("not a BGZF block"));
+    }
+    let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+    let mut extra = vec![0u8; xlen];
+    reader.read_exact(&mut extra)?;
+    let block_size = bsize(&extra).ok_or_else(|| invalid("BGZF block without BC field"))? + 1;
+    let Some(rest) = block_size.checked_sub(12 + xlen) else {
+        return Err(invalid("bad BGZF block size"));
+    };
+    let mut body = vec![0u8; rest];
+    reader.read_exact(&mut body)?;
+    let Some(cdata_len) = rest.checked_sub(8) else {
+        return Err(invalid("bad BGZF block size"));
+    };
+    let mut data = Vec::new();
+    DeflateDecoder::new(&body[..cdata_len]).read_to_end(&mut data)?;
+    Ok(Some((data, block_size)))
+}
+
+// true for a BGZF file that does not end with the EOF marker block; plain gzip and
+// other files are never reported
+pub fn missing_eof_marker(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 12];
+    if file.read_exact(&mut header).is_err() || header[..4] != [31, 139, 8, 4] {
+        return Ok(false);
+    }
+    let mut extra = vec![0u8; u16::from_le_bytes([header[10], header[11]]) as usize];
+    if file.read_exact(&mut extra).is_err() || bsize(&extra).is_none() {
+        return Ok(false);
+    }
+    if file.seek(SeekFrom::End(0))? < EOF_MARKER.len() as u64 {
+        return Ok(true);
+    }
+    file.seek(SeekFrom::End(-(EOF_MARKER.len() as i64)))?;
+    let mut tail = [0u8; 28];
+    file.read_exact(&mut tail)?;
+    Ok(tail != EOF_MARKER)
+}
+
+// ends the input quietly where the compressed data stops short, so a truncated
+// file still yields the records before the cut
+pub struct StopAtTruncation<R>(pub R);
+
+impl<R: Read> Read for StopAtTruncation<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(out) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            other => other,
+        }
+    }
+}
+
+// BSIZE from the BC subfield of the gzip extra field
+fn bsize(extra: &[u8]) -> Option<usize> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let data = rest.get(4..4 + len)?;
+        if rest[0] == b'B' && rest[1] == b'C' && len == 2 {
+            return Some(u16::from_le_bytes([data[0], data[1]]) as usize);
+        }
+        rest = &rest[4 + len..];
+    }
+    None
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,86 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::sam::ParsedSam;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// a file's parsed records, kept after switching to another file
+pub struct Entry {
+    pub parsed: ParsedSam,
+    pub truncated: bool,
+    mtime: SystemTime,
+    bytes: usize,
+}
+
+impl Entry {
+    pub fn new(parsed: ParsedSam, truncated: bool, mtime: SystemTime) -> Entry {
+        let bytes = estimate_bytes(&parsed);
+        Entry {
+            parsed,
+            truncated,
+            mtime,
+            bytes,
+        }
+    }
+}
+
+// recently closed files, least recently used first, within a memory budget;
+// an entry only comes back while its file's mtime is unchanged
+#[derive(Default)]
+pub struct FileCache {
+    entries: VecDeque<(PathBuf, Entry)>,
+    limit: usize,
+}
+
+impl FileCache {
+    pub fn new(limit: usize) -> FileCache {
+        FileCache {
+            entries: VecDeque::new(),
+            limit,
+        }
+    }
+
+    pub fn put(&mut self, path: PathBuf, entry: Entry) {
+        self.entries.retain(|(p, _)| *p != path);
+        if entry.bytes > self.limit {
+            return;
+        }
+        self.entries.push_back((path, entry));
+        let mut used: usize = self.entries.iter().map(|(_, e)| e.bytes).sum();
+        while used > self.limit {
+            let Some((_, old)) = self.entries.pop_front() else {
+                break;
+            };
+            used -= old.bytes;
+        }
+    }
+
+    // the entry for `path` if the file has not changed since it was read; a stale
+    // entry is dropped
+    pub fn take(&mut self, path: &Path, mtime: SystemTime) -> Option<Entry> {
+        let pos = self.entries.iter().position(|(p, _)| p == path)?;
+        let (_, entry) = self.entries.remove(pos)?;
+        (entry.mtime == mtime).then_some(entry)
+    }
+}
+
+// rough heap size: field text plus String and Vec headers
+fn estimate_bytes(parsed: &ParsedSam) -> usize {
+    const HEADER: usize = std::mem::size_of::<String>();
+    let rows: usize = parsed
+        .rows
+        .iter()
+        .map(|fields| {
+            std::mem::size_of::<Vec<String>>()
+                + fields.iter().map(|f| f.len() + HEADER).sum::<usize>()
+        })
+        .sum();
+    let header: usize = parsed.header.iter().map(|l| l.len() + HEADER).sum();
+    rows + header + parsed.offsets.len() * std::mem::size_of::<u64>()
+}
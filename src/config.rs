@@ -0,0 +1,330 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+// user settings kept as `key = value` lines in ~/.config/varview/config
+#[derive(Default)]
+pub struct Config {
+    pub column_order: Vec<String>,
+    pub vcf_column_order: Vec<String>,
+    // manual widths as (column name, width)
+    pub column_widths: Vec<(String, u16)>,
+    // cap for auto-sized columns, overall and per column name
+    pub max_column_width: Option<u16>,
+    pub column_max_widths: Vec<(String, u16)>,
+    // INFO key and thresholds for the allele-frequency colors in VCF mode
+    pub af_field: Option<String>,
+    pub af_rare: Option<f64>,
+    pub af_common: Option<f64>,
+    // alternate row shading: on at start, and the shade (a color name, #rrggbb or 0-255)
+    pub stripes: bool,
+    pub stripe_color: Option<String>,
+    // 16, 256 or truecolor, instead of guessing from COLORTERM and TERM
+    pub color_depth: Option<String>,
+    // `load_summary = false` skips the stats popup after a file loads
+    pub load_summary: Option<bool>,
+    // thousands separators and right alignment in numeric columns (POS, TLEN, ...)
+    pub group_digits: bool,
+    // what a table narrower than the terminal does with the rest: left (the
+    // default), fill or center
+    pub table_layout: Option<String>,
+    // fixed seed for the random row picks (?), so a spot check can be repeated
+    pub random_seed: Option<u64>,
+    // `event_loop = blocking` waits on input and loader messages instead of
+    // waking every 250ms (the default `poll`)
+    pub event_loop: Option<String>,
+    // where unmapped reads go in the view: inline (the default), last or hidden
+    pub unmapped: Option<String>,
+    // `decode_labels = short` writes decoded FLAG bits, CIGAR operations and
+    // alignment kinds as short codes (sec, sup, dup) instead of words
+    pub decode_labels: Option<String>,
+    // `picker_parent = false` drops the .. entry from the file picker
+    pub picker_parent: Option<bool>,
+    // cells moved per Left/Right press
+    pub hscroll_step: Option<u16>,
+    // memory (MB) for keeping closed files parsed; 0 turns the cache off
+    pub cache_mb: Option<usize>,
+    // threads for parsing an uncompressed file; all cores unless set, 1 reads serially
+    pub parse_threads: Option<usize>,
+    // SAM columns to store, e.g. QNAME,RNAME,POS, like --columns
+    pub parse_columns: Option<String>,
+    // read uncompressed files through a memory map instead of a buffered reader
+    pub mmap: bool,
+    // `cell_tooltip = false` stops showing a cut-off cell in full under the selection
+    pub cell_tooltip: Option<bool>,
+    // `base_colors = false` draws the bases in the detail pane without the
+    // per-nucleotide palette
+    pub base_colors: Option<bool>,
+    // line endings of exported files: lf (the default) or crlf
+    pub export_line_ending: Option<String>,
+    // a column header line in exported tables; CSV has one and TSV none unless set
+    pub export_header: Option<bool>,
+    // seconds without a key before the session is saved (30 by default); 0 turns
+    // sessions off
+    pub session_idle: Option<u64>,
+    // size (MB) above which opening a file asks first (4096 by default); 0 never asks
+    pub large_file_mb: Option<u64>,
+    // what Enter does in the table: detail (the default), copy or mate
+    pub enter_action: Option<String>,
+    // open the startup directory's only supported file, like --auto-open
+    pub auto_open: bool,
+    // `mouse = false` leaves mouse capture off, like --no-mouse
+    pub mouse: Option<bool>,
+    // view settings applied when a file with the extension opens, from
+    // `view.vcf = color,sort=POS` lines
+    pub views: Vec<(String, Vec<String>)>,
+    // confirmed search queries, oldest first; one line each since queries may hold commas
+    pub search_history: Vec<String>,
+    // regions applied with r, oldest first
+    pub region_history: Vec<String>,
+}
+
+pub const HISTORY_LIMIT: usize = 50;
+
+// move `entry` to the newest end of a history list, dropping duplicates and old entries
+pub fn push_history(history: &mut Vec<String>, entry: &str) {
+    history.retain(|h| h != entry);
+    history.push(entry.to_string());
+    if history.len() > HISTORY_LIMIT {
+        history.drain(..history.len() - HISTORY_LIMIT);
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(base.join("varview").join("config"))
+    }
+
+    pub fn load() -> Config {
+        let mut config = Config::default();
+        let Some(text) = Config::path().and_then(|p| fs::read_to_string(p).ok()) else {
+            return config;
+        };
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "column_order" => config.column_order = split_list(value),
+                "vcf_column_order" => config.vcf_column_order = split_list(value),
+                "column_widths" => config.column_widths = split_pairs(value),
+                "max_column_width" => config.max_column_width = value.parse().ok(),
+                "column_max_widths" => config.column_max_widths = split_pairs(value),
+                "af_field" => config.af_field = Some(value.to_string()),
+                "af_rare" => config.af_rare = value.parse().ok(),
+                "af_common" => config.af_common = value.parse().ok(),
+                "stripes" => config.stripes = value == "true",
+                "stripe_color" => config.stripe_color = Some(value.to_string()),
+                "color_depth" => config.color_depth = Some(value.to_string()),
+                "group_digits" => config.group_digits = value == "true",
+                "mouse" => config.mouse = value.parse().ok(),
+                "auto_open" => config.auto_open = value == "true",
+                "enter_action" => config.enter_action = Some(value.to_string()),
+                "hscroll_step" => config.hscroll_step = value.parse().ok(),
+                "unmapped" => config.unmapped = Some(value.to_string()),
+                "decode_labels" => config.decode_labels = Some(value.to_string()),
+                "picker_parent" => config.picker_parent = value.parse().ok(),
+                "event_loop" => config.event_loop = Some(value.to_string()),
+                "random_seed" => config.random_seed = value.parse().ok(),
+                "table_layout" => config.table_layout = Some(value.to_string()),
+                "cache_mb" => config.cache_mb = value.parse().ok(),
+                "session_idle" => config.session_idle = value.parse().ok(),
+                "large_file_mb" => config.large_file_mb = value.parse().ok(),
+                "cell_tooltip" => config.cell_tooltip = value.parse().ok(),
+                "base_colors" => config.base_colors = value.parse().ok(),
+                "export_line_ending" => config.export_line_ending = Some(value.to_lowercase()),
+                "export_header" => config.export_header = value.parse().ok(),
+                "parse_threads" => config.parse_threads = value.parse().ok(),
+                "parse_columns" => config.parse_columns = Some(value.to_string()),
+                "mmap" => config.mmap = value == "true",
+                "load_summary" => config.load_summary = value.parse().ok(),
+                "search_history" => config.search_history.push(value.to_string()),
+                "region_history" => config.region_history.push(value.to_string()),
+                k if k.starts_with("view.") => {
+                    let ext = k["view.".len()..].trim_start_matches('.').to_lowercase();
+                    config.views.retain(|(e, _)| *e != ext);
+                    config.views.push((ext, split_list(value)));
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Config::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = fs::File::create(path)?;
+        if !self.column_order.is_empty() {
+            writeln!(out, "column_order = {}", self.column_order.join(","))?;
+        }
+        if !self.vcf_column_order.is_empty() {
+            writeln!(
+                out,
+                "vcf_column_order = {}",
+                self.vcf_column_order.join(",")
+            )?;
+        }
+        if !self.column_widths.is_empty() {
+            let pairs: Vec<String> = self
+                .column_widths
+                .iter()
+                .map(|(name, width)| format!("{}:{}", name, width))
+                .collect();
+            writeln!(out, "column_widths = {}", pairs.join(","))?;
+        }
+        if let Some(max) = self.max_column_width {
+            writeln!(out, "max_column_width = {}", max)?;
+        }
+        if !self.column_max_widths.is_empty() {
+            let pairs: Vec<String> = self
+                .column_max_widths
+                .iter()
+                .map(|(name, width)| format!("{}:{}", name, width))
+                .collect();
+            writeln!(out, "column_max_widths = {}", pairs.join(","))?;
+        }
+        if let Some(field) = &self.af_field {
+            writeln!(out, "af_field = {}", field)?;
+        }
+        if let Some(rare) = self.af_rare {
+            writeln!(out, "af_rare = {}", rare)?;
+        }
+        if let Some(common) = self.af_common {
+            writeln!(out, "af_common = {}", common)?;
+        }
+        if self.stripes {
+            writeln!(out, "stripes = true")?;
+        }
+        if let Some(color) = &self.stripe_color {
+            writeln!(out, "stripe_color = {}", color)?;
+        }
+        if let Some(depth) = &self.color_depth {
+            writeln!(out, "color_depth = {}", depth)?;
+        }
+        if self.group_digits {
+            writeln!(out, "group_digits = true")?;
+        }
+        if let Some(summary) = self.load_summary {
+            writeln!(out, "load_summary = {}", summary)?;
+        }
+        if let Some(layout) = &self.table_layout {
+            writeln!(out, "table_layout = {}", layout)?;
+        }
+        if let Some(unmapped) = &self.unmapped {
+            writeln!(out, "unmapped = {}", unmapped)?;
+        }
+        if let Some(labels) = &self.decode_labels {
+            writeln!(out, "decode_labels = {}", labels)?;
+        }
+        if let Some(parent) = self.picker_parent {
+            writeln!(out, "picker_parent = {}", parent)?;
+        }
+        if let Some(mode) = &self.event_loop {
+            writeln!(out, "event_loop = {}", mode)?;
+        }
+        if let Some(seed) = self.random_seed {
+            writeln!(out, "random_seed = {}", seed)?;
+        }
+        if let Some(step) = self.hscroll_step {
+            writeln!(out, "hscroll_step = {}", step)?;
+        }
+        if let Some(mb) = self.cache_mb {
+            writeln!(out, "cache_mb = {}", mb)?;
+        }
+        if let Some(idle) = self.session_idle {
+            writeln!(out, "session_idle = {}", idle)?;
+        }
+        if let Some(mb) = self.large_file_mb {
+            writeln!(out, "large_file_mb = {}", mb)?;
+        }
+        if let Some(tooltip) = self.cell_tooltip {
+            writeln!(out, "cell_tooltip = {}", tooltip)?;
+        }
+        if let Some(colors) = self.base_colors {
+            writeln!(out, "base_colors = {}", colors)?;
+        }
+        if let Some(ending) = &self.export_line_ending {
+            writeln!(out, "export_line_ending = {}", ending)?;
+        }
+        if let Some(header) = self.export_header {
+            writeln!(out, "export_header = {}", header)?;
+        }
+        if let Some(threads) = self.parse_threads {
+            writeln!(out, "parse_threads = {}", threads)?;
+        }
+        if let Some(columns) = &self.parse_columns {
+            writeln!(out, "parse_columns = {}", columns)?;
+        }
+        if self.mmap {
+            writeln!(out, "mmap = true")?;
+        }
+        if let Some(action) = &self.enter_action {
+            writeln!(out, "enter_action = {}", action)?;
+        }
+        if self.auto_open {
+            writeln!(out, "auto_open = true")?;
+        }
+        if let Some(mouse) = self.mouse {
+            writeln!(out, "mouse = {}", mouse)?;
+        }
+        for (ext, settings) in &self.views {
+            writeln!(out, "view.{} = {}", ext, settings.join(","))?;
+        }
+        for query in &self.search_history {
+            writeln!(out, "search_history = {}", query)?;
+        }
+        for region in &self.region_history {
+            writeln!(out, "region_history = {}", region)?;
+        }
+        Ok(())
+    }
+
+    // the view settings for a file name, the longest matching extension winning
+    // so that `view.vcf.gz` can differ from `view.gz`
+    pub fn view_for(&self, file_name: &str) -> Option<&[String]> {
+        let name = file_name.to_lowercase();
+        self.views
+            .iter()
+            .filter(|(ext, _)| name.ends_with(&format!(".{}", ext)))
+            .max_by_key(|(ext, _)| ext.len())
+            .map(|(_, settings)| settings.as_slice())
+    }
+}
+
+// NAME:NUMBER pairs such as "CIGAR:20,SEQ:40"
+fn split_pairs<T: std::str::FromStr>(value: &str) -> Vec<(String, T)> {
+    split_list(value)
+        .iter()
+        .filter_map(|p| {
+            let (name, n) = p.split_once(':')?;
+            Some((name.trim().to_string(), n.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
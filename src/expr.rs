@@ -0,0 +1,370 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use std::{borrow::Cow, cmp::Ordering};
+
+// what a condition looks at: a table column or, for SAM, an optional tag
+#[derive(Clone, Debug)]
+pub enum Field {
+    Column(usize),
+    Tag(String),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Field, Op, String),
+    // FLAG has every bit of the mask
+    FlagHas(u16),
+}
+
+// a compound filter such as `MAPQ>=30 and RNAME==chr1 and FLAG has PROPER_PAIR`,
+// kept with the text it was parsed from
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub text: String,
+    root: Node,
+}
+
+impl Expr {
+    // `columns` are the names of the table columns; in `sam` mode two-character
+    // names stand for optional tags and FLAG takes `has`
+    pub fn parse(text: &str, columns: &[&str], sam: bool) -> Result<Expr, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            columns,
+            sam,
+        };
+        let root = parser.or()?;
+        if let Some(extra) = parser.tokens.get(parser.pos) {
+            return Err(format!("unexpected `{}`", extra));
+        }
+        Ok(Expr {
+            text: text.trim().to_string(),
+            root,
+        })
+    }
+
+    // `value` gives a row's text for a field, None when the row lacks it (an
+    // absent tag), which fails every condition on it
+    pub fn passes<'a>(&self, value: &impl Fn(&Field) -> Option<Cow<'a, str>>) -> bool {
+        self.root.passes(value)
+    }
+}
+
+impl Node {
+    fn passes<'a>(&self, value: &impl Fn(&Field) -> Option<Cow<'a, str>>) -> bool {
+        match self {
+            Node::And(a, b) => a.passes(value) && b.passes(value),
+            Node::Or(a, b) => a.passes(value) || b.passes(value),
+            Node::Not(a) => !a.passes(value),
+            Node::Compare(field, op, want) => value(field).is_some_and(|v| compare(&v, *op, want)),
+            Node::FlagHas(mask) => value(&Field::Column(1))
+                .and_then(|f| f.parse::<u16>().ok())
+                .is_some_and(|f| f & mask == *mask),
+        }
+    }
+}
+
+// numbers compare as numbers, anything else as text; ~ is a substring match
+// ignoring case
+fn compare(have: &str, op: Op, want: &str) -> bool {
+    let order = || match (have.parse::<f64>(), want.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b),
+        _ => Some(have.cmp(want)),
+    };
+    match op {
+        Op::Eq => order() == Some(Ordering::Equal),
+        Op::Ne => order().is_some_and(|o| o != Ordering::Equal),
+        Op::Lt => order() == Some(Ordering::Less),
+        Op::Le => matches!(order(), Some(Ordering::Less | Ordering::Equal)),
+        Op::Gt => order() == Some(Ordering::Greater),
+        Op::Ge => matches!(order(), Some(Ordering::Greater | Ordering::Equal)),
+        Op::Contains => have.to_lowercase().contains(&want.to_lowercase()),
+    }
+}
+
+// words, quoted strings, parentheses and comparison operators
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::from('"');
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => word.push(c),
+                    None => return Err("unclosed quote".to_string()),
+                }
+            }
+            tokens.push(word);
+        } else if "=!<>~&|".contains(c) {
+            let mut op = String::new();
+            while let Some(&c) = chars.peek()
+                && "=!<>~&|".contains(c)
+            {
+                op.push(c);
+                chars.next();
+            }
+            tokens.push(op);
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek()
+                && !c.is_whitespace()
+                && !"()\"=!<>~&|".contains(c)
+            {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        }
+    }
+    Ok(tokens)
+}
+
+// or := and (OR and)*, and := not (AND not)*, not := NOT not | ( or ) | condition
+struct Parser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    columns: &'a [&'a str],
+    sam: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, words: &[&str]) -> bool {
+        let found = self
+            .peek()
+            .is_some_and(|t| words.iter().any(|w| t.eq_ignore_ascii_case(w)));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.keyword(&["or", "||"]) {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.not()?;
+        while self.keyword(&["and", "&&"]) {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        if self.keyword(&["not", "!"]) {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        if self.keyword(&["("]) {
+            let node = self.or()?;
+            if !self.keyword(&[")"]) {
+                return Err("missing )".to_string());
+            }
+            return Ok(node);
+        }
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Node, String> {
+        let name = self.next().ok_or("a condition is missing at the end")?;
+        let field = self.field(&name)?;
+        let op = self.next().ok_or(format!("no operator after {}", name))?;
+        if op.eq_ignore_ascii_case("has") {
+            if !self.sam || !matches!(field, Field::Column(1)) {
+                return Err(format!("`has` only works on FLAG, not {}", name));
+            }
+            let bits = self.next().ok_or("no flag name after has")?;
+            return flag_mask(&bits).map(Node::FlagHas);
+        }
+        let op = match op.as_str() {
+            "=" | "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "~" => Op::Contains,
+            other => return Err(format!("unknown operator `{}`", other)),
+        };
+        let value = self
+            .next()
+            .ok_or(format!("no value after {} {}", name, op_text(op)))?;
+        let value = value.strip_prefix('"').unwrap_or(&value).to_string();
+        Ok(Node::Compare(field, op, value))
+    }
+
+    fn field(&self, name: &str) -> Result<Field, String> {
+        if let Some(col) = self
+            .columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+        {
+            return Ok(Field::Column(col));
+        }
+        let bytes = name.as_bytes();
+        if self.sam
+            && bytes.len() == 2
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1].is_ascii_alphanumeric()
+        {
+            return Ok(Field::Tag(name.to_string()));
+        }
+        Err(format!("no column named {}", name))
+    }
+}
+
+fn op_text(op: Op) -> &'static str {
+    match op {
+        Op::Eq => "==",
+        Op::Ne => "!=",
+        Op::Lt => "<",
+        Op::Le => "<=",
+        Op::Gt => ">",
+        Op::Ge => ">=",
+        Op::Contains => "~",
+    }
+}
+
+// a flag name (samtools' or the short label), several joined by commas, or a number
+fn flag_mask(bits: &str) -> Result<u16, String> {
+    if let Some(mask) = crate::filter::parse_flag(bits) {
+        return Ok(mask);
+    }
+    bits.split(',').try_fold(0, |mask, name| {
+        crate::sam::flag_bit(name.trim())
+            .map(|bit| mask | bit)
+            .ok_or(format!("unknown flag {}", name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLUMNS: [&str; 5] = ["QNAME", "FLAG", "RNAME", "POS", "MAPQ"];
+
+    // whether a record with these column values and optional tags passes
+    fn passes(text: &str, row: [&str; 5], tags: &[(&str, &str)]) -> bool {
+        let expr = Expr::parse(text, &COLUMNS, true).unwrap();
+        expr.passes(&|field: &Field| match field {
+            Field::Column(col) => Some(Cow::Owned(row[*col].to_string())),
+            Field::Tag(tag) => tags
+                .iter()
+                .find(|(t, _)| t == tag)
+                .map(|(_, v)| Cow::Owned(v.to_string())),
+        })
+    }
+
+    fn error(text: &str) -> String {
+        Expr::parse(text, &COLUMNS, true).unwrap_err()
+    }
+
+    const READ: [&str; 5] = ["read 1", "99", "chr1", "900", "40"];
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(passes("MAPQ>=30 or RNAME==chr2 and POS<100", READ, &[]));
+        assert!(!passes("(MAPQ>=30 or RNAME==chr2) and POS<100", READ, &[]));
+        assert!(passes("MAPQ<30 || RNAME==chr1 && POS>100", READ, &[]));
+        assert!(!passes("(MAPQ<30 || RNAME==chr1) && POS<100", READ, &[]));
+    }
+
+    #[test]
+    fn not_negates_the_next_condition_or_group() {
+        assert!(!passes("not MAPQ>=30", READ, &[]));
+        assert!(passes("! RNAME==chr2", READ, &[]));
+        assert!(passes("not (MAPQ<30 or RNAME==chr2) and POS>1", READ, &[]));
+        assert!(!passes("NOT NOT RNAME==chr2", READ, &[]));
+    }
+
+    #[test]
+    fn quoted_values_keep_spaces_and_operators() {
+        assert!(passes("QNAME == \"read 1\"", READ, &[]));
+        assert!(passes("QNAME ~ \"EAD \"", READ, &[]));
+        assert!(!passes("QNAME == \"read\"", READ, &[]));
+        assert!(passes("RG == \"grp A\"", READ, &[("RG", "grp A")]));
+    }
+
+    #[test]
+    fn flag_has_checks_every_named_bit() {
+        // 99 = PAIRED | PROPER_PAIR | MREVERSE | READ1
+        assert!(passes("FLAG has PROPER_PAIR,READ1", READ, &[]));
+        assert!(passes("FLAG has proper_pair,read1", READ, &[]));
+        assert!(!passes("FLAG has PROPER_PAIR,READ2", READ, &[]));
+        assert!(passes("FLAG has 0x41", READ, &[]));
+        assert!(passes("FLAG has proper,r1", READ, &[]));
+        assert_eq!(error("FLAG has SPLIT"), "unknown flag SPLIT");
+    }
+
+    #[test]
+    fn numbers_compare_as_numbers_and_text_as_text() {
+        // 900 < 1000 as numbers, but "900" > "1000" as text
+        assert!(passes("POS < 1000", READ, &[]));
+        assert!(passes("MAPQ == 40.0", READ, &[]));
+        assert!(passes("RNAME < chr2", READ, &[]));
+        assert!(passes("QNAME > read", READ, &[]));
+        // an absent tag fails every condition on it
+        assert!(!passes("NM >= 0", READ, &[]));
+        assert!(!passes("NM != 3", READ, &[]));
+    }
+
+    #[test]
+    fn errors_name_the_problem() {
+        assert_eq!(error("QNAME == \"read 1"), "unclosed quote");
+        assert_eq!(error("(MAPQ > 30 or POS < 5"), "missing )");
+        assert_eq!(error("MAPQ => 30"), "unknown operator `=>`");
+        assert_eq!(
+            error("MAPQ has READ1"),
+            "`has` only works on FLAG, not MAPQ"
+        );
+        assert_eq!(error("NM has READ1"), "`has` only works on FLAG, not NM");
+        assert_eq!(error("DEPTH > 3"), "no column named DEPTH");
+        assert_eq!(error("MAPQ > 30 POS"), "unexpected `POS`");
+    }
+
+    #[test]
+    fn has_is_sam_only() {
+        let err = Expr::parse("FLAG has READ1", &COLUMNS, false).unwrap_err();
+        assert_eq!(err, "`has` only works on FLAG, not FLAG");
+    }
+}
@@ -0,0 +1,162 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::{
+    expr::Expr,
+    sam::{self, Region},
+    vcf,
+};
+
+// record predicates shared by the table view and the headless --count mode
+#[derive(Clone, Default)]
+pub struct Filters {
+    pub min_mapq: Option<u32>,
+    pub region: Option<Region>,
+    // also let the region keep split reads with an SA part inside it
+    pub split_reads: bool,
+    // FLAG bits that must all be set (samtools -f) / must all be clear (-F)
+    pub require_flags: u16,
+    pub exclude_flags: u16,
+    // the exclude bits l added for its primary mapped reads, dropped again by L
+    pub locus_flags: u16,
+    // VCF variant types to keep, all when empty
+    pub variant_types: Vec<&'static str>,
+    pub genotype: Option<GenotypeFilter>,
+    pub tag: Option<TagFilter>,
+    // primary records whose QNAME (and read1/read2) occurs more than once; the
+    // counts live with the loaded rows, so the view checks this, not passes()
+    pub duplicates: bool,
+    // text of the quick-filter bar; like duplicates, only the view applies it
+    pub quick: String,
+    // compound filter built with e; it reads derived cells, so the view applies it
+    pub expr: Option<Expr>,
+    // the QNAME of the one template to show (J)
+    pub template: Option<String>,
+}
+
+// keep reads that carry (or, with `present` false, lack) an optional tag such as SA
+#[derive(Clone)]
+pub struct TagFilter {
+    pub tag: String,
+    pub present: bool,
+}
+
+impl TagFilter {
+    // "SA" keeps reads with an SA tag, "!SA" reads without one
+    pub fn parse(s: &str) -> Option<TagFilter> {
+        let (tag, present) = match s.strip_prefix('!') {
+            Some(tag) => (tag.trim(), false),
+            None => (s, true),
+        };
+        let valid = tag.len() == 2
+            && tag.as_bytes()[0].is_ascii_alphabetic()
+            && tag.as_bytes()[1].is_ascii_alphanumeric();
+        valid.then(|| TagFilter {
+            tag: tag.to_string(),
+            present,
+        })
+    }
+
+    pub fn text(&self) -> String {
+        format!("{}{}", if self.present { "" } else { "!" }, self.tag)
+    }
+}
+
+// keep variants where a sample (or any sample) has the given zygosity
+#[derive(Clone)]
+pub struct GenotypeFilter {
+    // index among the sample columns
+    pub sample: Option<usize>,
+    pub zygosity: vcf::Zygosity,
+}
+
+impl GenotypeFilter {
+    fn passes(&self, fields: &[String]) -> bool {
+        let samples = fields.len().saturating_sub(vcf::SAMPLES_COLUMN);
+        match self.sample {
+            Some(s) => vcf::sample_zygosity(fields, s) == Some(self.zygosity),
+            None => (0..samples).any(|s| vcf::sample_zygosity(fields, s) == Some(self.zygosity)),
+        }
+    }
+}
+
+impl Filters {
+    pub fn passes(&self, fields: &[String]) -> bool {
+        if let Some(min) = self.min_mapq {
+            let mapq = fields.get(4).and_then(|m| m.parse::<u32>().ok());
+            if mapq.is_none_or(|m| m < min) {
+                return false;
+            }
+        }
+        if let Some(region) = &self.region
+            && !region.overlaps(fields)
+            && !(self.split_reads && region.overlaps_split(fields))
+        {
+            return false;
+        }
+        if self.require_flags != 0 || self.exclude_flags != 0 {
+            let Some(flag) = fields.get(1).and_then(|f| f.parse::<u16>().ok()) else {
+                return false;
+            };
+            if flag & self.require_flags != self.require_flags || flag & self.exclude_flags != 0 {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag
+            && sam::find_tag(fields, &tag.tag).is_some() != tag.present
+        {
+            return false;
+        }
+        if let Some(qname) = &self.template
+            && fields.first() != Some(qname)
+        {
+            return false;
+        }
+        true
+    }
+
+    // VCF records filter on position and variant type; MAPQ and FLAG do not apply
+    pub fn passes_vcf(&self, fields: &[String]) -> bool {
+        if let Some(region) = &self.region
+            && !vcf::overlaps(region, fields)
+        {
+            return false;
+        }
+        if let Some(genotype) = &self.genotype
+            && !genotype.passes(fields)
+        {
+            return false;
+        }
+        self.variant_types.is_empty() || self.variant_types.contains(&vcf::variant_type(fields))
+    }
+
+    // every word of the quick filter occurs, ignoring ASCII case, in some field
+    pub fn passes_quick(&self, fields: &[String]) -> bool {
+        let query = self.quick.to_ascii_lowercase();
+        query.split_whitespace().all(|word| {
+            fields
+                .iter()
+                .any(|f| contains_lowercase(f.as_bytes(), word.as_bytes()))
+        })
+    }
+}
+
+// whether `haystack` contains `needle`, which is already lowercase, ignoring ASCII case
+fn contains_lowercase(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| {
+        window
+            .iter()
+            .zip(needle)
+            .all(|(a, b)| a.to_ascii_lowercase() == *b)
+    })
+}
+
+// FLAG values as decimal or 0x-prefixed hex, like samtools accepts
+pub fn parse_flag(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -0,0 +1,92 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::LoaderMsg;
+use crossterm::event::{self, Event};
+use std::{
+    sync::{Arc, Condvar, Mutex, mpsc::Sender},
+    thread,
+    time::Duration,
+};
+
+// how long a read waits before checking for a pause; bounds how long pause() takes
+const PAUSE_CHECK: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Running,
+    PauseRequested,
+    Paused,
+    Stopped,
+}
+
+// reads terminal events on its own thread and sends them down the loader channel,
+// so the blocking event loop can sleep on that one channel
+pub struct InputThread {
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+impl InputThread {
+    pub fn spawn(tx: Sender<LoaderMsg>) -> InputThread {
+        let state = Arc::new((Mutex::new(State::Running), Condvar::new()));
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            let (lock, cvar) = &*shared;
+            let stop = || {
+                *lock.lock().unwrap() = State::Stopped;
+                cvar.notify_all();
+            };
+            loop {
+                {
+                    let mut state = lock.lock().unwrap();
+                    if *state == State::PauseRequested {
+                        *state = State::Paused;
+                        cvar.notify_all();
+                    }
+                    while *state == State::Paused {
+                        state = cvar.wait(state).unwrap();
+                    }
+                }
+                match event::poll(PAUSE_CHECK) {
+                    Ok(true) => match event::read() {
+                        Ok(ev @ (Event::Key(_) | Event::Resize(..))) => {
+                            if tx.send(LoaderMsg::Input(ev)).is_err() {
+                                return stop();
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(_) => return stop(),
+                    },
+                    Ok(false) => {}
+                    Err(_) => return stop(),
+                }
+            }
+        });
+        InputThread { state }
+    }
+
+    // stop reading, e.g. while a pager owns the terminal; returns once the thread
+    // has let go of it
+    pub fn pause(&self) {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        if *state != State::Running {
+            return;
+        }
+        *state = State::PauseRequested;
+        while *state == State::PauseRequested {
+            state = cvar.wait(state).unwrap();
+        }
+    }
+
+    pub fn resume(&self) {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        if *state == State::Paused {
+            *state = State::Running;
+            cvar.notify_all();
+        }
+    }
+}
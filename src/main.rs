@@ -1,31 +1,61 @@
 use color_eyre::Result;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout, Margin, Rect},
-    style::{Color, Modifier, Style, Stylize},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Color, Modifier, Style},
     symbols,
-    text::Line,
+    text::{Line, Span},
     widgets::{
-        Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState,
+        Block, Borders, Cell, Clear, HighlightSpacing, List, ListItem, ListState, Paragraph, Row,
+        Table, TableState, Wrap,
+        canvas::{Canvas, Points},
     },
 };
 use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufReader, Write},
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver, Sender},
+    process::{Command, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
     thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 use walkdir::WalkDir;
 
+mod align;
+mod bam;
+mod bcf;
+mod bgzf;
+mod cache;
+mod config;
+mod expr;
+mod filter;
+mod input;
+mod notes;
+mod reference;
+mod rng;
+mod sam;
+mod session;
+mod tabix;
+mod theme;
+mod vcf;
+
 /*
 Gaurav Sablok
 codeprog@icloud.com
@@ -35,372 +65,5430 @@ codeprog@icloud.com
 struct App {
     picker_open: bool,
     picker_path: PathBuf,
-    picker_entries: Vec<PathBuf>,
+    picker_entries: Vec<PickerEntry>,
     picker_state: ListState,
+    // id of the latest directory scan; a scan stops once this moves past it
+    picker_scan: Arc<AtomicU64>,
+    picker_scanning: bool,
+    loaded_path: Option<PathBuf>,
+    kind: FileKind,
+    // bumped per load so messages from an abandoned stream are dropped
+    load_generation: u64,
+    loading: bool,
     table_rows: Vec<Vec<String>>,
+    row_offsets: Vec<u64>,
     table_state: TableState,
     table_scroll: (u16, u16),
+    // the other pane of the split view (v); the focused pane's state stays in the
+    // fields above, and Shift+Tab swaps the two
+    split: Option<Pane>,
+    // the focused pane is the lower one
+    split_bottom: bool,
     search_open: bool,
+    // the quick-filter bar (F) is taking keystrokes; its text is filters.quick
+    quick_filter_open: bool,
     search_input: String,
     search_results: Vec<usize>,
+    // last confirmed query, re-run when the view changes
+    search_query: String,
+    // search the whole file instead of only the rows left by the filters
+    search_all: bool,
+    // logical columns searched with /; empty means QNAME (SAM) or ID (VCF)
+    search_columns: Vec<usize>,
+    // position in config.search_history (or region_history in the region prompt)
+    // while browsing it with Up/Down
+    history_pos: Option<usize>,
+    header_lines: Vec<String>,
+    read_groups: Vec<String>,
+    ref_lengths: HashMap<String, u64>,
+    ref_ids: HashMap<String, usize>,
+    // rows whose alignment runs past the @SQ length of their contig
+    out_of_bounds: HashSet<usize>,
+    // original row indices of every record per QNAME, which the duplicates filter
+    // (u) counts primary records from, and the menu (A) listing those of the
+    // selected read
+    qname_rows: HashMap<String, Vec<usize>>,
+    alignment_menu: Option<(Vec<usize>, ListState)>,
+    // the search matches listed one per line to jump to (s), with those outside
+    // the view when searching all rows
+    results_menu: Option<(HashSet<usize>, ListState)>,
+    // color rows by read group (SAM) or allele frequency (VCF)
+    color_rows: bool,
+    malformed_lines: usize,
+    // quick stats shown once a newly opened file has loaded, closed by any key;
+    // want_summary marks a load that should end with it
+    summary: Option<Vec<String>>,
+    want_summary: bool,
+    // the loaded BGZF file lacks its EOF marker, so the rows may be incomplete
+    truncated: bool,
+    padded_lines: usize,
+    // keep records with fewer than 11 columns, padded with "*" (--lenient)
+    lenient: bool,
+    // the SAM fields stored when loading (--columns), all when None
+    parse_columns: Option<u32>,
+    dense: bool,
+    // gutter before the first column (N): off, record numbers, or distance from
+    // the selected row
+    row_numbers: RowNumbers,
+    // shade every other row, starting from the config's `stripes`
+    stripes: bool,
+    // original row indices currently shown, in display order
+    filtered_indices: Vec<usize>,
+    // logical column the view is sorted by, descending when true
+    sort: Option<(usize, bool)>,
+    // contig jump menu (C) and its (name, record count) list in file order, built
+    // on first open and dropped when the rows change
+    contig_menu: Option<ListState>,
+    contig_counts: Option<Vec<(String, usize)>>,
+    header_view: Option<HeaderView>,
+    // where unmapped reads (FLAG 0x4 or RNAME *) go in the view (M)
+    unmapped: Unmapped,
+    // words or short codes for decoded FLAG bits and CIGAR operations
+    labels: sam::Labels,
+    // draw QUAL as per-read quality sparklines (Q)
+    qual_sparkline: bool,
+    // original row indices kept above the table for comparison (K), in pin order
+    pinned: Vec<usize>,
+    // a BAM extraction (B) running in the background, shown in the status bar
+    progress: Option<String>,
+    // keys since the session was last saved, and when the last one came
+    session_dirty: bool,
+    last_key: Option<Instant>,
+    // the saved session found for the file just opened, restored with Y
+    saved_session: Option<Vec<(String, String)>>,
+    // position marked with X for measuring distances with W
+    mark: Option<(String, u64)>,
+    // a file over the size limit waiting for y before it is loaded, with its size
+    confirm_load: Option<(PathBuf, u64)>,
+    // the large file last confirmed, loaded without asking again
+    large_file_ok: Option<PathBuf>,
+    // the filters and inversion in place before J narrowed the view to one template
+    template_view: Option<(filter::Filters, bool)>,
+    // review notes by original row index, saved to the file's .notes sidecar; notes
+    // read from the sidecar wait by record offset until their rows arrive
+    notes: HashMap<usize, String>,
+    pending_notes: HashMap<u64, String>,
+    filters: filter::Filters,
+    filter_inverted: bool,
+    prompt: Option<Prompt>,
+    config: config::Config,
+    // logical column indices (into the kind's columns) in display order
+    column_order: Vec<usize>,
+    // display position of the focused column
+    focused_col: usize,
+    reorder_mode: bool,
+    // = turns +/- and ←/→ into resizing the focused column until = or Esc
+    resize_mode: bool,
+    // manual width per logical column, set in resize mode
+    width_overrides: HashMap<usize, u16>,
+    // widest value seen so far per logical column, for auto-sizing
+    content_widths: Vec<u16>,
+    show_offsets: bool,
+    // the TID column: RNAME's index among the @SQ lines (I)
+    show_tid: bool,
+    detail_open: bool,
+    // long detail lines run off to the right instead of wrapping, scrolled with { }
+    detail_nowrap: bool,
+    detail_hscroll: u16,
+    // list the CIGAR operations one per line with their coordinates instead of
+    // the SEQ blocks (k)
+    cigar_list: bool,
+    // PNEXT in the detail pane as a signed offset from POS rather than absolute (O)
+    mate_relative: bool,
+    // FASTAs given with --reference, shown under the read in the detail pane
+    references: Vec<reference::Reference>,
+    // k-mer dotplot of the selected read against its reference span (D)
+    dotplot_open: bool,
+    // opened on first copy and kept, since on X11 the contents live as long as it does
+    clipboard: Option<arboard::Clipboard>,
+    // parsed records of recently closed files; load_mtime is the mtime of the file
+    // being read when its rows can be cached, cache_ready the same once they are in
+    cache: cache::FileCache,
+    // picks the rows ? jumps to
+    rng: rng::Rng,
+    // the last SAM file opened, for V on a variant, and the VCF to go back to
+    // with its region filter and selected record
+    alignments_path: Option<PathBuf>,
+    // the record's byte offset, as row numbers change when the file is read again
+    variant_return: Option<(PathBuf, Option<sam::Region>, Option<u64>)>,
+    // byte offset of the record to select once the load reaches it
+    pending_offset: Option<u64>,
+    load_mtime: Option<SystemTime>,
+    cache_ready: Option<SystemTime>,
+    // rule behind the DERIVED column (R); the column is hidden without one
+    derived: Option<Derived>,
+    // colors the terminal can show; frames are fitted to it after drawing
+    color_depth: theme::ColorDepth,
+    theme: theme::Theme,
+
+    // transient status bar messages, the front one is shown until it expires
+    messages: VecDeque<String>,
+    message_since: Option<Instant>,
 
     loader_tx: Option<Sender<LoaderMsg>>,
     loader_rx: Option<Receiver<LoaderMsg>>,
 }
 
+// the DERIVED column: a regex applied to another column, e.g. a lane taken out
+// of QNAME with `QNAME ^[^:]+:[^:]+:([^:]+)`
+struct Derived {
+    source: usize,
+    pattern: regex::Regex,
+}
+
+impl Derived {
+    fn parse(input: &str, columns: &[&str]) -> Result<Derived, String> {
+        let (name, pattern) = input
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or("give a column name and a regex, e.g. QNAME ^([^:]+):")?;
+        let source = columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+            .filter(|&c| c < columns.len() - 2)
+            .ok_or_else(|| format!("no column named {}", name))?;
+        // the parser's message spans several lines, the reason is on the last
+        let pattern = regex::Regex::new(pattern.trim()).map_err(|e| {
+            let text = e.to_string();
+            let reason = text.lines().last().unwrap_or_default();
+            format!("bad regex: {}", reason.trim_start_matches("error: "))
+        })?;
+        Ok(Derived { source, pattern })
+    }
+
+    fn text(&self, columns: &[&str]) -> String {
+        format!("{} {}", columns[self.source], self.pattern.as_str())
+    }
+
+    // the first capture group, or the whole match for a pattern without one
+    fn extract<'a>(&self, value: &'a str) -> &'a str {
+        self.pattern
+            .captures(value)
+            .and_then(|c| c.get(1).or_else(|| c.get(0)))
+            .map(|m| m.as_str())
+            .unwrap_or("")
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum RowNumbers {
+    #[default]
+    Off,
+    Absolute,
+    Relative,
+}
+
+// selection, filters and order of one table pane over the shared rows
+#[derive(Default)]
+struct Pane {
+    table_state: TableState,
+    filtered_indices: Vec<usize>,
+    filters: filter::Filters,
+    filter_inverted: bool,
+    sort: Option<(usize, bool)>,
+    template_view: Option<(filter::Filters, bool)>,
+}
+
 enum LoaderMsg {
-    Files(Vec<PathBuf>),
-    SamRows(Vec<Vec<String>>),
-    Quit,
+    // directory entries as a scan finds them, each batch sorted, tagged with the scan id
+    Files(u64, Vec<PickerEntry>),
+    FilesDone(u64),
+    // a whole file, replacing the table; tagged with the load it belongs to
+    SamRows(u64, sam::ParsedSam),
+    // streamed input arrives as a first SamRows followed by chunks until done
+    SamChunk(u64, sam::ParsedSam),
+    SamDone(u64),
+    // the file of that load looks cut short; sent after its rows
+    Truncated(u64),
+    Error(String),
+    // a background write (B) reporting how far it got, then how it ended
+    Progress(String),
+    ProgressDone(String),
+    // terminal events from the input thread of the blocking event loop
+    Input(Event),
+}
+
+const STREAM_BATCH: usize = 5000;
+
+// whether a region filter decides what is read from `path`: a bgzipped VCF with
+// a tabix or CSI index, or a BAM with a BAI next to it
+fn reads_region(path: &Path) -> bool {
+    match FileKind::of(path) {
+        FileKind::Vcf => {
+            !bcf::is_bcf(path) && vcf::is_gzipped(path) && tabix::index_path(path).is_some()
+        }
+        FileKind::Sam => bam::is_bam(path) && bam::index_path(path).is_file(),
+    }
+}
+
+// stdin and named pipes are read incrementally instead of all at once
+fn is_stream(path: &Path) -> bool {
+    path == Path::new("-") || is_fifo(path)
+}
+
+fn is_fifo(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|m| is_fifo_type(m.file_type()))
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn is_fifo_type(file_type: std::fs::FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    file_type.is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo_type(_file_type: std::fs::FileType) -> bool {
+    false
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EntryKind {
+    Dir,
+    Fifo,
+    File,
+}
+
+// a picker line; the scan works out what it is once, so sorting and drawing the
+// list never touch the filesystem
+#[derive(Clone, PartialEq)]
+struct PickerEntry {
+    path: PathBuf,
+    kind: EntryKind,
+}
+
+// the picker order: the parent (..) on top, then directories, then files, by name
+fn picker_key(entry: &PickerEntry, parent: Option<&Path>) -> (bool, bool, String) {
+    (
+        Some(entry.path.as_path()) != parent,
+        entry.kind != EntryKind::Dir,
+        entry.path.to_string_lossy().to_lowercase(),
+    )
+}
+
+// files the picker lists and the viewer can open, by name
+fn is_supported_file(path: &Path) -> bool {
+    vcf::is_vcf(path)
+        || path
+            .extension()
+            .map(|e| e == "sam" || e == "bam")
+            .unwrap_or(false)
+}
+
+// the only supported regular file in `dir`, for --auto-open
+fn single_supported_file(dir: &Path) -> Option<PathBuf> {
+    let mut files = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && is_supported_file(p));
+    let first = files.next()?;
+    files.next().is_none().then_some(first)
+}
+
+const MESSAGE_TTL: Duration = Duration::from_secs(3);
+// quiet time after the last key before the session is saved, unless configured
+const SESSION_IDLE: Duration = Duration::from_secs(30);
+// files bigger than this (in MB) are only loaded after a confirmation, unless configured
+const LARGE_FILE_MB: u64 = 4096;
+
+enum PromptKind {
+    MinMapq,
+    Region,
+    VariantType,
+    Genotype,
+    Note,
+    Tag,
+    ExportCsv,
+    ExportPileup,
+    Derive,
+    Expression,
+    ExtractBam,
+}
+
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+// the header popup (H): its lines narrowed to one record type (@SQ, ##INFO, ...)
+// with ←/→ and to those containing `query`, typed after /
+#[derive(Default)]
+struct HeaderView {
+    // record types in order of first appearance; `kind` indexes it, None for all
+    types: Vec<String>,
+    kind: Option<usize>,
+    query: String,
+    typing: bool,
+    state: ListState,
+}
+
+impl PromptKind {
+    fn title(&self) -> &'static str {
+        match self {
+            PromptKind::MinMapq => "Minimum MAPQ (empty to clear, Enter to apply, Esc to cancel)",
+            PromptKind::Region => {
+                "Region RNAME:START-END, ↑/↓ for earlier ones (empty to clear, Esc to cancel)"
+            }
+            PromptKind::Genotype => {
+                "Genotype het, hom alt, hom ref, haploid, missing or SAMPLE=het (empty to clear)"
+            }
+            PromptKind::VariantType => {
+                "Variant types, e.g. SNV or indel or INS,DEL,SV (empty to clear, Esc to cancel)"
+            }
+            PromptKind::Note => "Note on this record (empty to remove, Esc to cancel)",
+            PromptKind::ExportCsv => {
+                "CSV or .tsv file, optionally with columns, e.g. view.csv QNAME,RNAME,POS; .sam copies the records, .fq writes FASTQ"
+            }
+            PromptKind::ExportPileup => {
+                "Pileup at RNAME:POS to a file (.tsv for tabs, else CSV), e.g. chr1:1000 site.tsv"
+            }
+            PromptKind::Tag => {
+                "Optional tag, e.g. SA to keep reads with it or !SA without (empty to clear)"
+            }
+            PromptKind::Derive => {
+                "DERIVED column: COLUMN REGEX, the first capture group is shown (empty to clear)"
+            }
+            PromptKind::ExtractBam => {
+                "Region and BAM file, e.g. chr1:1000-2000 locus.bam (indexed as locus.bam.bai)"
+            }
+            PromptKind::Expression => {
+                "Filter, e.g. MAPQ>=30 and (RNAME==chr1 or RNAME==chr2) and FLAG has PROPER_PAIR"
+            }
+        }
+    }
 }
 
+// what the loaded file holds; decides the columns and which filters apply
+#[derive(Clone, Copy, Default, PartialEq)]
+enum FileKind {
+    #[default]
+    Sam,
+    Vcf,
+}
+
+impl FileKind {
+    fn of(path: &Path) -> FileKind {
+        if vcf::is_vcf(path) {
+            FileKind::Vcf
+        } else {
+            FileKind::Sam
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FileKind::Sam => "SAM",
+            FileKind::Vcf => "VCF",
+        }
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            FileKind::Sam => &COLUMNS,
+            FileKind::Vcf => &vcf::COLUMNS,
+        }
+    }
+
+    // the column `table_layout = fill` widens: the long sequence or annotation text
+    fn wide_column(self) -> usize {
+        match self {
+            FileKind::Sam => 9,
+            FileKind::Vcf => 7,
+        }
+    }
+
+    // columns holding numbers, right-aligned and grouped with `group_digits`
+    fn is_numeric(self, col: usize) -> bool {
+        let name = self.columns()[col];
+        match self {
+            FileKind::Sam => matches!(name, "POS" | "MAPQ" | "PNEXT" | "TLEN" | "TID" | "OFFSET"),
+            FileKind::Vcf => matches!(name, "POS" | "QUAL" | "OFFSET"),
+        }
+    }
+}
+
+const QUAL_COLUMN: usize = 10;
+
+const COLUMNS: [&str; 15] = [
+    "QNAME", "FLAG", "RNAME", "POS", "MAPQ", "CIGAR", "RNEXT", "PNEXT", "TLEN", "SEQ", "QUAL",
+    "RG", "TID", "DERIVED", "OFFSET",
+];
+const RG_COLUMN: usize = 11;
+const TID_COLUMN: usize = 12;
+
+// unmapped (0x4), secondary (0x100) and supplementary (0x800) records, left out
+// of the selected read's locus (l)
+const LOCUS_EXCLUDE_FLAGS: u16 = 0x904;
+
+// resolve configured column names, appending any columns the config left out
+fn resolve_column_order(columns: &[&str], names: &[String]) -> Vec<usize> {
+    // a name listed twice keeps its first place
+    let mut order = Vec::new();
+    let named = names
+        .iter()
+        .filter_map(|n| columns.iter().position(|c| c.eq_ignore_ascii_case(n)));
+    for col in named.chain(0..columns.len()) {
+        if !order.contains(&col) {
+            order.push(col);
+        }
+    }
+    order
+}
+
+// the LineFormat keep mask for a list of SAM columns such as QNAME,RNAME,POS;
+// RG (or TAGS) keeps the optional tags, which the RG column is read from
+fn parse_column_mask(list: &str) -> std::result::Result<u32, String> {
+    let mut mask = 0;
+    for name in list.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if name.eq_ignore_ascii_case("TAGS") || name.eq_ignore_ascii_case("RG") {
+            mask |= sam::TAGS_BIT;
+            continue;
+        }
+        match COLUMNS[..sam::SAM_MANDATORY]
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+        {
+            Some(col) => mask |= 1 << col,
+            None => {
+                return Err(format!(
+                    "no column {} to parse, use {},TAGS",
+                    name,
+                    COLUMNS[..sam::SAM_MANDATORY].join(",")
+                ));
+            }
+        }
+    }
+    if mask == 0 {
+        return Err("no columns to parse".to_string());
+    }
+    Ok(mask)
+}
+
+// auto-sized columns stop growing here unless the config says otherwise
+const DEFAULT_MAX_WIDTH: u16 = 40;
+const DENSE_MAX_WIDTH: u16 = 8;
+
+const DEFAULT_HSCROLL_STEP: u16 = 5;
+
+const DEFAULT_CACHE_MB: usize = 256;
+
+// allele frequency bands for VCF row colors: rare, low frequency, common
+const DEFAULT_AF_RARE: f64 = 0.01;
+const DEFAULT_AF_COMMON: f64 = 0.05;
+
 impl App {
     fn new() -> Self {
+        let config = config::Config::load();
+        let color_depth = config
+            .color_depth
+            .as_deref()
+            .and_then(theme::ColorDepth::parse)
+            .unwrap_or_else(theme::ColorDepth::detect);
         let mut s = App {
             picker_path: std::env::current_dir().unwrap(),
             search_input: String::new(),
             search_results: Vec::new(),
+            stripes: config.stripes,
+            unmapped: config
+                .unmapped
+                .as_deref()
+                .and_then(Unmapped::parse)
+                .unwrap_or_default(),
+            labels: config
+                .decode_labels
+                .as_deref()
+                .and_then(sam::Labels::parse)
+                .unwrap_or_default(),
+            rng: rng::Rng::new(config.random_seed.unwrap_or_else(rng::Rng::clock_seed)),
+            cache: cache::FileCache::new(
+                config
+                    .cache_mb
+                    .unwrap_or(DEFAULT_CACHE_MB)
+                    .saturating_mul(1 << 20),
+            ),
+            color_depth,
+            theme: theme::Theme::new(color_depth),
+            config,
             ..Default::default()
         };
+        s.set_kind(FileKind::Sam);
         s.picker_state.select(Some(0));
         s.table_state.select(Some(0));
         s.spawn_loader();
         s
     }
 
+    // switch the table layout to another kind of file, with its saved column settings
+    fn set_kind(&mut self, kind: FileKind) {
+        // searched columns carry over by name where the new kind has them
+        let search_names: Vec<&str> = self
+            .search_columns
+            .iter()
+            .map(|&c| self.columns()[c])
+            .collect();
+        self.kind = kind;
+        let columns = self.columns();
+        self.search_columns = search_names
+            .iter()
+            .filter_map(|name| columns.iter().position(|c| c == name))
+            .collect();
+        let order = match kind {
+            FileKind::Sam => &self.config.column_order,
+            FileKind::Vcf => &self.config.vcf_column_order,
+        };
+        self.column_order = resolve_column_order(columns, order);
+        // the compound filter refers to columns by position, so look its names up again
+        if let Some(expr) = &self.filters.expr {
+            self.filters.expr = expr::Expr::parse(&expr.text, columns, kind == FileKind::Sam).ok();
+        }
+        self.width_overrides = self
+            .config
+            .column_widths
+            .iter()
+            .filter_map(|(name, w)| {
+                let col = columns.iter().position(|c| c.eq_ignore_ascii_case(name))?;
+                Some((col, *w))
+            })
+            .collect();
+        self.content_widths = columns.iter().map(|c| c.width() as u16).collect();
+        self.focused_col = 0;
+        self.sort = None;
+        self.derived = None;
+        if let Some(other) = self.split.as_mut() {
+            other.sort = None;
+        }
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        self.kind.columns()
+    }
+
+    // OFFSET is the last column of every kind
+    fn offset_column(&self) -> usize {
+        self.columns().len() - 1
+    }
+
+    // DERIVED, just before OFFSET, shows the regex rule's capture when one is set
+    fn derived_column(&self) -> usize {
+        self.columns().len() - 2
+    }
+
     fn spawn_loader(&mut self) {
         let (tx, rx) = mpsc::channel();
         self.loader_tx = Some(tx);
         self.loader_rx = Some(rx);
     }
 
-    fn send(&self, msg: LoaderMsg) {
-        if let Some(tx) = &self.loader_tx {
-            let _ = tx.send(msg);
+    // apply pending loader messages; true when there were any
+    fn recv(&mut self) -> bool {
+        let msgs: Vec<LoaderMsg> = match &self.loader_rx {
+            Some(rx) => rx.try_iter().collect(),
+            None => return false,
+        };
+        let any = !msgs.is_empty();
+        for msg in msgs {
+            self.handle_msg(msg);
         }
+        any
     }
 
-    fn recv(&mut self) {
-        if let Some(rx) = &self.loader_rx {
-            while let Ok(msg) = rx.try_recv() {
-                match msg {
-                    LoaderMsg::Files(list) => {
-                        self.picker_entries = list;
-                        self.picker_state.select(Some(0));
-                    }
-                    LoaderMsg::SamRows(rows) => {
-                        self.table_rows = rows;
-                        self.table_state.select(Some(0));
-                        self.table_scroll = (0, 0);
-                        self.search_results.clear(); // clear old search
-                    }
-                    LoaderMsg::Quit => {}
+    // blocking event loop: sleep until the next key, loader message or the shown
+    // message's expiry, applying loader messages on the way
+    fn wait_for_key(&mut self) -> Option<KeyEvent> {
+        let ttl = self
+            .message_since
+            .map(|since| MESSAGE_TTL.saturating_sub(since.elapsed()));
+        // wake up in time for the idle session save too
+        let idle = self
+            .session_idle()
+            .filter(|_| self.session_dirty)
+            .zip(self.last_key)
+            .map(|(idle, last)| idle.saturating_sub(last.elapsed()));
+        let timeout = match (ttl, idle) {
+            (Some(ttl), Some(idle)) => Some(ttl.min(idle)),
+            (ttl, idle) => ttl.or(idle),
+        };
+        let rx = self.loader_rx.as_ref()?;
+        let msg = match timeout {
+            Some(timeout) => rx.recv_timeout(timeout).ok(),
+            None => rx.recv().ok(),
+        };
+        self.expire_messages();
+        match msg? {
+            LoaderMsg::Input(Event::Key(key)) => Some(key),
+            msg => {
+                self.handle_msg(msg);
+                None
+            }
+        }
+    }
+
+    fn handle_msg(&mut self, msg: LoaderMsg) {
+        match msg {
+            LoaderMsg::Files(id, list) if id == self.picker_scan.load(Ordering::Relaxed) => {
+                self.add_picker_entries(list);
+            }
+            LoaderMsg::FilesDone(id) if id == self.picker_scan.load(Ordering::Relaxed) => {
+                self.picker_scanning = false;
+            }
+            LoaderMsg::Files(..) | LoaderMsg::FilesDone(_) => {}
+            LoaderMsg::SamRows(id, parsed) if id == self.load_generation => {
+                self.reset_table();
+                self.append_records(parsed);
+                self.select_pending();
+                self.cache_ready = self.load_mtime;
+                self.show_summary();
+            }
+            LoaderMsg::SamChunk(id, parsed) if id == self.load_generation => {
+                self.append_records(parsed);
+                self.select_pending();
+            }
+            LoaderMsg::SamDone(id) if id == self.load_generation => {
+                self.loading = false;
+                self.show_summary();
+                self.notify(format!(
+                    "finished reading {} records",
+                    self.table_rows.len()
+                ));
+            }
+            LoaderMsg::Truncated(id) if id == self.load_generation => {
+                self.truncated = true;
+                let name = self
+                    .loaded_path
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                self.notify(format!(
+                    "{} appears truncated (no BGZF EOF marker): showing the {} records that could be read",
+                    name,
+                    self.table_rows.len()
+                ));
+            }
+            LoaderMsg::SamRows(..)
+            | LoaderMsg::SamChunk(..)
+            | LoaderMsg::SamDone(_)
+            | LoaderMsg::Truncated(_)
+            | LoaderMsg::Input(_) => {}
+            LoaderMsg::Error(err) => self.notify(err),
+            LoaderMsg::Progress(text) => self.progress = Some(text),
+            LoaderMsg::ProgressDone(text) => {
+                self.progress = None;
+                self.notify(text);
+            }
+        }
+    }
+
+    fn show_summary(&mut self) {
+        if !std::mem::take(&mut self.want_summary) {
+            return;
+        }
+        let mut lines = vec![format!("records     {}", self.table_rows.len())];
+        match self.kind {
+            FileKind::Sam => {
+                lines.push(format!("references  {}", self.ref_lengths.len()));
+                lines.push(format!(
+                    "sort order  {}",
+                    sam::header_sort_order(&self.header_lines).unwrap_or("not given")
+                ));
+                if self.column_parsed(1) {
+                    let mapped = self
+                        .table_rows
+                        .iter()
+                        .filter(|f| f[1].parse::<u16>().is_ok_and(|flag| flag & 0x4 == 0))
+                        .count();
+                    lines.push(format!(
+                        "mapped      {} ({:.1}%)",
+                        mapped,
+                        100.0 * mapped as f64 / self.table_rows.len().max(1) as f64
+                    ));
+                } else {
+                    lines.push("mapped      FLAG not parsed".to_string());
+                }
+                lines.push(format!("read groups {}", self.read_groups.len()));
+            }
+            FileKind::Vcf => {
+                lines.push(format!(
+                    "contigs     {}",
+                    vcf::header_contigs(&self.header_lines).len()
+                ));
+                lines.push(format!(
+                    "samples     {}",
+                    vcf::sample_names(&self.header_lines).len()
+                ));
+                let pass = self.table_rows.iter().filter(|f| f[6] == "PASS").count();
+                lines.push(format!(
+                    "PASS        {} ({:.1}%)",
+                    pass,
+                    100.0 * pass as f64 / self.table_rows.len().max(1) as f64
+                ));
+            }
+        }
+        if self.malformed_lines > 0 {
+            lines.push(format!("malformed   {}", self.malformed_lines));
+        }
+        self.summary = Some(lines);
+    }
+
+    fn reset_table(&mut self) {
+        self.header_lines.clear();
+        self.table_rows.clear();
+        self.row_offsets.clear();
+        self.malformed_lines = 0;
+        self.padded_lines = 0;
+        self.truncated = false;
+        self.read_groups.clear();
+        self.ref_lengths.clear();
+        self.ref_ids.clear();
+        self.out_of_bounds.clear();
+        self.qname_rows.clear();
+        self.alignment_menu = None;
+        self.results_menu = None;
+        self.header_view = None;
+        self.pinned.clear();
+        self.contig_counts = None;
+        self.notes.clear();
+        self.filtered_indices.clear();
+        if let Some(other) = self.split.as_mut() {
+            other.filtered_indices.clear();
+            other.table_state.select(Some(0));
+        }
+        self.content_widths = self.columns().iter().map(|c| c.width() as u16).collect();
+        self.table_state.select(Some(0));
+        self.table_scroll = (0, 0);
+        self.search_results.clear(); // clear old search
+        self.search_query.clear();
+    }
+
+    // add newly read records, updating the derived state for just those rows
+    fn append_records(&mut self, parsed: sam::ParsedSam) {
+        let new_header = !parsed.header.is_empty();
+        self.header_lines.extend(parsed.header);
+        if self.kind == FileKind::Sam && new_header {
+            self.ref_lengths = sam::header_reference_lengths(&self.header_lines);
+            self.ref_ids = sam::header_reference_ids(&self.header_lines);
+            for rg in sam::header_read_groups(&self.header_lines) {
+                if !self.read_groups.contains(&rg) {
+                    self.read_groups.push(rg);
+                }
+            }
+        }
+        let first = self.table_rows.len();
+        // reads may carry RG tags that the header never declared
+        for fields in parsed.rows.iter().filter(|_| self.kind == FileKind::Sam) {
+            if let Some(rg) = sam::find_tag(fields, "RG")
+                && !self.read_groups.iter().any(|g| g == rg)
+            {
+                self.read_groups.push(rg.to_string());
+            }
+        }
+        for (i, fields) in parsed.rows.iter().enumerate() {
+            if self.kind == FileKind::Sam && sam::exceeds_reference(fields, &self.ref_lengths) {
+                self.out_of_bounds.insert(first + i);
+            }
+            if self.kind == FileKind::Sam {
+                self.qname_rows
+                    .entry(fields[0].clone())
+                    .or_default()
+                    .push(first + i);
+            }
+        }
+        if !parsed.rows.is_empty() {
+            self.contig_counts = None;
+        }
+        self.table_rows.extend(parsed.rows);
+        self.row_offsets.extend(parsed.offsets);
+        if !self.pending_notes.is_empty() {
+            for i in first..self.row_offsets.len() {
+                if let Some(note) = self.pending_notes.remove(&self.row_offsets[i]) {
+                    self.notes.insert(i, note);
+                }
+            }
+        }
+        self.malformed_lines += parsed.malformed;
+        self.padded_lines += parsed.padded;
+        for i in first..self.table_rows.len() {
+            for col in 0..self.columns().len() {
+                let width = self.shown_value(i, col).width().min(u16::MAX as usize) as u16;
+                if width > self.content_widths[col] {
+                    self.content_widths[col] = width;
                 }
             }
         }
+        self.extend_view(first);
+        if let Some(mut other) = self.split.take() {
+            self.swap_pane(&mut other);
+            self.extend_view(first);
+            self.swap_pane(&mut other);
+            self.split = Some(other);
+        }
+        self.refresh_search();
+    }
+
+    // add the rows from `first` on that pass the filters to the view
+    fn extend_view(&mut self, first: usize) {
+        // new rows can turn earlier ones into duplicates
+        if self.filters.duplicates {
+            self.apply_filters();
+            return;
+        }
+        for i in first..self.table_rows.len() {
+            if self.row_passes_filters(i) != self.filter_inverted {
+                self.filtered_indices.push(i);
+            }
+        }
+        let selected = self.selected_row();
+        if self.sort.is_some() {
+            self.sort_view();
+        }
+        self.reselect(selected);
+    }
+
+    fn swap_pane(&mut self, pane: &mut Pane) {
+        std::mem::swap(&mut self.table_state, &mut pane.table_state);
+        std::mem::swap(&mut self.filtered_indices, &mut pane.filtered_indices);
+        std::mem::swap(&mut self.filters, &mut pane.filters);
+        std::mem::swap(&mut self.filter_inverted, &mut pane.filter_inverted);
+        std::mem::swap(&mut self.sort, &mut pane.sort);
+        std::mem::swap(&mut self.template_view, &mut pane.template_view);
+    }
+
+    // v: open a second pane starting as a copy of this one, or close it
+    fn toggle_split(&mut self) {
+        if self.split.take().is_some() {
+            self.split_bottom = false;
+            return;
+        }
+        self.split = Some(Pane {
+            table_state: self.table_state.clone(),
+            filtered_indices: self.filtered_indices.clone(),
+            filters: self.filters.clone(),
+            filter_inverted: self.filter_inverted,
+            sort: self.sort,
+            template_view: self.template_view.clone(),
+        });
+    }
+
+    fn switch_pane(&mut self) {
+        let Some(mut other) = self.split.take() else {
+            return;
+        };
+        self.swap_pane(&mut other);
+        self.split = Some(other);
+        self.split_bottom = !self.split_bottom;
+        self.refresh_search();
+    }
+
+    // a prompt, the search box, the quick filter or the header filter takes the
+    // keys as text, so single-key commands must not fire
+    fn typing(&self) -> bool {
+        self.prompt.is_some()
+            || self.search_open
+            || self.quick_filter_open
+            || self.header_view.as_ref().is_some_and(|v| v.typing)
+    }
+
+    fn notify(&mut self, msg: impl Into<String>) {
+        if self.messages.is_empty() {
+            self.message_since = Some(Instant::now());
+        }
+        self.messages.push_back(msg.into());
+    }
+
+    // drop the shown message once it has been up long enough, then show the next;
+    // true when the status bar changed
+    fn expire_messages(&mut self) -> bool {
+        if self
+            .message_since
+            .is_none_or(|since| since.elapsed() < MESSAGE_TTL)
+        {
+            return false;
+        }
+        self.messages.pop_front();
+        self.message_since = if self.messages.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        true
+    }
+
+    fn mode_name(&self) -> &'static str {
+        if self.picker_open {
+            "PICKER"
+        } else if self.search_open {
+            "SEARCH"
+        } else if self.prompt.is_some() {
+            "FILTER"
+        } else if self.reorder_mode {
+            "REORDER"
+        } else if self.resize_mode {
+            "RESIZE"
+        } else {
+            "VIEW"
+        }
+    }
+
+    fn row_color(&self, fields: &[String]) -> Option<Color> {
+        match self.kind {
+            FileKind::Sam => {
+                let rg = sam::find_tag(fields, "RG")?;
+                let idx = self.read_groups.iter().position(|g| g == rg)?;
+                let palette = &self.theme.read_groups;
+                Some(palette[idx % palette.len()])
+            }
+            FileKind::Vcf => {
+                let key = self.config.af_field.as_deref().unwrap_or("AF");
+                let af = vcf::max_frequency(fields, key)?;
+                let band = if af < self.config.af_rare.unwrap_or(DEFAULT_AF_RARE) {
+                    0
+                } else if af < self.config.af_common.unwrap_or(DEFAULT_AF_COMMON) {
+                    1
+                } else {
+                    2
+                };
+                Some(self.theme.frequencies[band])
+            }
+        }
     }
 
+    // scan the picker directory in the background, sending entries in batches so
+    // slow (network) filesystems fill the list as they go; starting a new scan
+    // cancels the previous one
     fn refresh_picker(&mut self) {
         let path = self.picker_path.clone();
         let tx = self.loader_tx.clone().unwrap();
+        let scan = Arc::clone(&self.picker_scan);
+        let id = scan.fetch_add(1, Ordering::Relaxed) + 1;
+        self.picker_entries.clear();
+        self.picker_state.select(Some(0));
+        self.picker_scanning = true;
+        let show_parent = self.config.picker_parent != Some(false);
         thread::spawn(move || {
-            let mut entries: Vec<PathBuf> = vec![];
+            let mut entries: Vec<PickerEntry> = vec![];
+            let parent = path.parent();
+            let send = |mut entries: Vec<PickerEntry>| {
+                entries.sort_by_cached_key(|e| picker_key(e, parent));
+                tx.send(LoaderMsg::Files(id, entries)).is_ok()
+            };
 
-            if let Some(parent) = path.parent() {
-                entries.push(parent.to_path_buf());
+            if let Some(parent) = parent.filter(|_| show_parent) {
+                entries.push(PickerEntry {
+                    path: parent.to_path_buf(),
+                    kind: EntryKind::Dir,
+                });
             }
 
+            let mut last_send = Instant::now();
             for entry in WalkDir::new(&path)
                 .max_depth(1)
                 .into_iter()
                 .filter_map(|e| e.ok())
             {
+                if scan.load(Ordering::Relaxed) != id {
+                    return;
+                }
                 let p = entry.path().to_path_buf();
-                if p.is_dir()
-                    || p.extension()
-                        .map(|e| e == "sam" || e == "bam")
-                        .unwrap_or(false)
-                {
-                    entries.push(p);
+                // the directory listing already says what an entry is; only links
+                // need a stat to see where they point
+                let file_type = entry.file_type();
+                let kind = if file_type.is_dir() || (file_type.is_symlink() && p.is_dir()) {
+                    EntryKind::Dir
+                } else if is_fifo_type(file_type) {
+                    EntryKind::Fifo
+                } else if is_supported_file(&p) {
+                    EntryKind::File
+                } else {
+                    continue;
+                };
+                entries.push(PickerEntry { path: p, kind });
+                if last_send.elapsed() >= Duration::from_millis(100) && !entries.is_empty() {
+                    if !send(std::mem::take(&mut entries)) {
+                        return;
+                    }
+                    last_send = Instant::now();
                 }
             }
-            entries.sort_by_key(|p| (p.is_file(), p.to_str().map(|s| s.to_lowercase())));
-            let _ = tx.send(LoaderMsg::Files(entries));
+            send(entries);
+            let _ = tx.send(LoaderMsg::FilesDone(id));
         });
     }
 
-    fn load_sam(&mut self, path: PathBuf) {
-        let tx = self.loader_tx.clone().unwrap();
-        thread::spawn(move || {
-            let file = match File::open(&path) {
-                Ok(f) => f,
-                Err(_) => return,
+    // merge a batch into the sorted picker list, keeping the highlighted entry
+    fn add_picker_entries(&mut self, batch: Vec<PickerEntry>) {
+        let selected = self
+            .picker_state
+            .selected()
+            .and_then(|i| self.picker_entries.get(i))
+            .map(|e| e.path.clone());
+        // land on the loaded file when browsing its directory, unless the user moved
+        let moved = self.picker_state.selected().unwrap_or(0) != 0;
+        let loaded = self
+            .loaded_path
+            .as_ref()
+            .filter(|p| !moved && batch.iter().any(|e| e.path == **p))
+            .cloned();
+        // both lists are sorted, so one pass merges them
+        let parent = self.picker_path.parent();
+        let old = std::mem::take(&mut self.picker_entries);
+        let mut merged = Vec::with_capacity(old.len() + batch.len());
+        let (mut old, mut batch) = (old.into_iter().peekable(), batch.into_iter().peekable());
+        while let (Some(a), Some(b)) = (old.peek(), batch.peek()) {
+            let next = if picker_key(a, parent) <= picker_key(b, parent) {
+                old.next()
+            } else {
+                batch.next()
             };
-            let reader = BufReader::new(file);
-            let mut rows = vec![];
+            merged.extend(next);
+        }
+        merged.extend(old);
+        merged.extend(batch);
+        self.picker_entries = merged;
+        let target = loaded.or(selected);
+        let pos = target
+            .and_then(|t| self.picker_entries.iter().position(|e| e.path == t))
+            .unwrap_or(0);
+        self.picker_state.select(Some(pos));
+    }
 
-            for line in reader.lines().flatten() {
-                if line.starts_with('@') {
-                    continue;
-                }
-                let fields: Vec<String> = line.split('\t').map(|s| s.to_string()).collect();
-                if fields.len() >= 11 {
-                    rows.push(fields);
+    // per-extension view settings from the config (`view.EXT = ...`): dense, stripes,
+    // offsets, tid, color, detail, nowrap and sort=COLUMN (sort=-COLUMN for descending)
+    fn apply_view_defaults(&mut self, path: &Path) {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let Some(settings) = self.config.view_for(&name).map(|s| s.to_vec()) else {
+            return;
+        };
+        let mut unknown = vec![];
+        for setting in &settings {
+            match setting.to_lowercase().as_str() {
+                "dense" => self.dense = true,
+                "stripes" => self.stripes = true,
+                "offsets" => self.show_offsets = true,
+                "tid" => self.show_tid = true,
+                "color" => self.color_rows = true,
+                "detail" => self.detail_open = true,
+                "nowrap" => self.detail_nowrap = true,
+                s if s.starts_with("sort=") => {
+                    let column = &setting["sort=".len()..];
+                    let (column, descending) = match column.strip_prefix('-') {
+                        Some(c) => (c, true),
+                        None => (column, false),
+                    };
+                    match self
+                        .columns()
+                        .iter()
+                        .position(|c| c.eq_ignore_ascii_case(column))
+                    {
+                        Some(col) => self.sort = Some((col, descending)),
+                        None => unknown.push(setting.as_str()),
+                    }
                 }
+                _ => unknown.push(setting.as_str()),
             }
-            let _ = tx.send(LoaderMsg::SamRows(rows));
-        });
+        }
+        if !unknown.is_empty() {
+            self.notify(format!(
+                "config: unknown view setting(s) for {}: {}",
+                name,
+                unknown.join(", ")
+            ));
+        }
     }
 
-    fn perform_search(&mut self) {
-        let needle = self.search_input.trim();
-        if needle.is_empty() {
-            self.search_results.clear();
-            return;
+    // how long after the last key the session is saved; None when sessions are off
+    fn session_idle(&self) -> Option<Duration> {
+        match self.config.session_idle {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(SESSION_IDLE),
         }
+    }
 
-        self.search_results = self
-            .table_rows
-            .iter()
-            .enumerate()
-            .filter_map(|(i, fields)| {
-                if fields.get(0).map(|q| q.contains(needle)).unwrap_or(false) {
-                    Some(i)
-                } else {
-                    None
-                }
-            })
-            .collect();
+    // the size of a regular file over `large_file_mb` that would be read whole into
+    // memory; streams and region reads through a VCF index only hold what they read
+    fn needs_size_confirmation(&self, path: &Path) -> Option<u64> {
+        let limit = match self.config.large_file_mb {
+            Some(0) => return None,
+            Some(mb) => mb,
+            None => LARGE_FILE_MB,
+        };
+        if is_stream(path) {
+            return None;
+        }
+        let indexed = self.filters.region.is_some() && reads_region(path);
+        let size = std::fs::metadata(path).ok()?.len();
+        (!indexed && size > limit * 1024 * 1024).then_some(size)
+    }
 
-        if let Some(&first) = self.search_results.first() {
-            self.table_state.select(Some(first));
+    // y on the large-file popup loads the file, any other key leaves it closed
+    fn answer_large_file(&mut self, key: KeyCode) {
+        let Some((path, _)) = self.confirm_load.take() else {
+            return;
+        };
+        if matches!(key, KeyCode::Char('y') | KeyCode::Char('Y')) {
+            self.large_file_ok = Some(path.clone());
+            self.load_sam(path);
+        } else {
+            self.notify(format!(
+                "{} not opened",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ));
         }
     }
-}
 
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Percentage((100 - percent_y) / 2),
-            Constraint::Percentage(percent_y),
-            Constraint::Percentage((100 - percent_y) / 2),
-        ])
-        .split(r);
+    fn session_due(&self) -> bool {
+        self.session_dirty
+            && self
+                .session_idle()
+                .zip(self.last_key)
+                .is_some_and(|(idle, last)| last.elapsed() >= idle)
+    }
 
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage((100 - percent_x) / 2),
-            Constraint::Percentage(percent_x),
+    // the view as session entries: selection and pins by byte offset, columns by name
+    fn session_entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = Vec::new();
+        let offset = |i: usize| self.row_offsets.get(i).map(|o| o.to_string());
+        if let Some(offset) = self.selected_row().and_then(offset) {
+            entries.push(("selected", offset));
+        }
+        if let Some(&col) = self.visible_columns().get(self.focused_col) {
+            entries.push(("focused", self.columns()[col].to_string()));
+        }
+        if let Some((col, descending)) = self.sort {
+            let sign = if descending { "-" } else { "" };
+            entries.push(("sort", format!("{}{}", sign, self.columns()[col])));
+        }
+        // a template shown with J is a passing look, the filters under it are kept
+        let (filters, inverted) = match &self.template_view {
+            Some((filters, inverted)) => (filters, *inverted),
+            None => (&self.filters, self.filter_inverted),
+        };
+        if let Some(min) = filters.min_mapq {
+            entries.push(("min_mapq", min.to_string()));
+        }
+        if let Some(region) = &filters.region {
+            entries.push(("region", region.to_string()));
+        }
+        if filters.require_flags != 0 {
+            entries.push(("require_flags", format!("0x{:x}", filters.require_flags)));
+        }
+        if filters.exclude_flags != 0 {
+            entries.push(("exclude_flags", format!("0x{:x}", filters.exclude_flags)));
+        }
+        if let Some(tag) = &filters.tag {
+            entries.push(("tag", tag.text()));
+        }
+        if !filters.variant_types.is_empty() {
+            entries.push(("types", filters.variant_types.join(",")));
+        }
+        if filters.genotype.is_some() {
+            entries.push(("genotype", self.genotype_filter_text()));
+        }
+        if !filters.quick.is_empty() {
+            entries.push(("quick", filters.quick.clone()));
+        }
+        if let Some(expr) = &filters.expr {
+            entries.push(("filter", expr.text.clone()));
+        }
+        if filters.duplicates {
+            entries.push(("duplicates", "true".to_string()));
+        }
+        if filters.split_reads {
+            entries.push(("split_reads", "true".to_string()));
+        }
+        if inverted {
+            entries.push(("inverted", "true".to_string()));
+        }
+        if self.unmapped != Unmapped::Inline {
+            entries.push(("unmapped", self.unmapped.name().to_string()));
+        }
+        if !self.pinned.is_empty() {
+            let pins: Vec<String> = self.pinned.iter().filter_map(|&i| offset(i)).collect();
+            entries.push(("pinned", pins.join(",")));
+        }
+        entries
+    }
+
+    fn save_session(&mut self) {
+        self.session_dirty = false;
+        let Some(path) = self.loaded_path.clone().filter(|p| !is_stream(p)) else {
+            return;
+        };
+        if self.session_idle().is_none() || self.table_rows.is_empty() {
+            return;
+        }
+        let entries = self.session_entries();
+        // a view left as it opened does not replace a session still on offer
+        if self.saved_session.is_some()
+            && entries
+                .iter()
+                .all(|(key, _)| matches!(*key, "selected" | "focused"))
+        {
+            return;
+        }
+        if let Err(e) = session::save(&path, &entries) {
+            self.notify(format!("cannot save the session: {}", e));
+        }
+    }
+
+    // Y: bring back the saved session of the file just opened
+    fn restore_session(&mut self) {
+        let Some(entries) = self.saved_session.take() else {
+            return;
+        };
+        let row_at = |app: &App, offset: &str| {
+            let offset = offset.parse::<u64>().ok()?;
+            app.row_offsets.binary_search(&offset).ok()
+        };
+        let column = |app: &App, name: &str| {
+            app.columns()
+                .iter()
+                .position(|c| c.eq_ignore_ascii_case(name))
+        };
+        let sam = self.kind == FileKind::Sam;
+        let mut selected = self.selected_row();
+        self.filters = filter::Filters::default();
+        self.filter_inverted = false;
+        self.template_view = None;
+        self.sort = None;
+        for (key, value) in &entries {
+            let value = value.as_str();
+            match key.as_str() {
+                "selected" => selected = row_at(self, value).or(selected),
+                "focused" => {
+                    if let Some(col) = column(self, value)
+                        && let Some(pos) = self.visible_columns().iter().position(|&c| c == col)
+                    {
+                        self.focused_col = pos;
+                    }
+                }
+                "sort" => {
+                    let name = value.trim_start_matches('-');
+                    self.sort = column(self, name).map(|col| (col, value.starts_with('-')));
+                }
+                "min_mapq" => self.filters.min_mapq = value.parse().ok(),
+                "region" => self.filters.region = sam::Region::parse(value),
+                "require_flags" => {
+                    self.filters.require_flags = filter::parse_flag(value).unwrap_or(0)
+                }
+                "exclude_flags" => {
+                    self.filters.exclude_flags = filter::parse_flag(value).unwrap_or(0)
+                }
+                "tag" => self.filters.tag = filter::TagFilter::parse(value),
+                "types" => self.filters.variant_types = vcf::parse_types(value).unwrap_or_default(),
+                "genotype" => self.filters.genotype = self.parse_genotype_filter(value).ok(),
+                "quick" => self.filters.quick = value.to_string(),
+                "filter" => self.filters.expr = expr::Expr::parse(value, self.columns(), sam).ok(),
+                "duplicates" => self.filters.duplicates = value == "true",
+                "split_reads" => self.filters.split_reads = value == "true",
+                "inverted" => self.filter_inverted = value == "true",
+                "unmapped" => self.unmapped = Unmapped::parse(value).unwrap_or_default(),
+                "pinned" => {
+                    self.pinned = value.split(',').filter_map(|o| row_at(self, o)).collect();
+                }
+                _ => {}
+            }
+        }
+        if self.filters.region.is_some() {
+            self.region_changed();
+        } else {
+            self.apply_filters();
+        }
+        self.reselect(selected);
+        self.notify(format!(
+            "session restored: {} of {} records in view",
+            self.filtered_indices.len(),
+            self.table_rows.len()
+        ));
+    }
+
+    // move the loaded file's records into the cache before another file replaces them
+    fn stash_loaded(&mut self) {
+        let (Some(path), Some(mtime)) = (self.loaded_path.clone(), self.cache_ready.take()) else {
+            return;
+        };
+        let parsed = sam::ParsedSam {
+            header: std::mem::take(&mut self.header_lines),
+            rows: std::mem::take(&mut self.table_rows),
+            offsets: std::mem::take(&mut self.row_offsets),
+            malformed: self.malformed_lines,
+            padded: self.padded_lines,
+        };
+        self.cache
+            .put(path, cache::Entry::new(parsed, self.truncated, mtime));
+        self.reset_table();
+    }
+
+    fn load_sam(&mut self, path: PathBuf) {
+        // reloads of the same file (region changes on an indexed VCF) stay quiet
+        let new_file = self.loaded_path.as_ref() != Some(&path);
+        if new_file
+            && self.large_file_ok.as_ref() != Some(&path)
+            && let Some(size) = self.needs_size_confirmation(&path)
+        {
+            self.confirm_load = Some((path, size));
+            return;
+        }
+        if new_file {
+            self.pending_offset = None;
+            if self.session_dirty {
+                self.save_session();
+            }
+            // the template of a read in the old file means nothing in the new one
+            if let Some((filters, inverted)) = self.template_view.take() {
+                self.filters = filters;
+                self.filter_inverted = inverted;
+            }
+            self.stash_loaded();
+            self.saved_session = self
+                .session_idle()
+                .filter(|_| !is_stream(&path))
+                .and_then(|_| session::load(&path));
+            if self.saved_session.is_some() {
+                self.notify(format!(
+                    "{} has a saved session: Y restores its filters, sort and selection",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ));
+            }
+        }
+        self.cache_ready = None;
+        self.want_summary = new_file && self.config.load_summary != Some(false);
+        self.loaded_path = Some(path.clone());
+        if FileKind::of(&path) == FileKind::Sam && !is_stream(&path) {
+            self.alignments_path = Some(path.clone());
+        }
+        self.pending_notes = if path == Path::new("-") {
+            HashMap::new()
+        } else {
+            notes::load(&path)
+        };
+        self.load_generation += 1;
+        let id = self.load_generation;
+        let tx = self.loader_tx.clone().unwrap();
+        let kind = FileKind::of(&path);
+        if kind != self.kind {
+            self.reset_table();
+            self.set_kind(kind);
+        }
+        if new_file {
+            self.apply_view_defaults(&path);
+        }
+        let format = match kind {
+            FileKind::Sam => sam::LineFormat::sam(self.lenient, self.parse_columns),
+            FileKind::Vcf => vcf::FORMAT,
+        };
+        let mtime = if is_stream(&path) {
+            None
+        } else {
+            std::fs::metadata(&path).and_then(|m| m.modified()).ok()
+        };
+        if new_file
+            && let Some(mtime) = mtime
+            && let Some(entry) = self.cache.take(&path, mtime)
+        {
+            self.loading = false;
+            self.reset_table();
+            self.append_records(entry.parsed);
+            self.truncated = entry.truncated;
+            self.cache_ready = Some(mtime);
+            self.show_summary();
+            self.notify(format!(
+                "{} unchanged, reopened from memory",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            return;
+        }
+        // a region read through the index holds only part of the file
+        let partial = self.filters.region.is_some() && reads_region(&path);
+        self.load_mtime = mtime.filter(|_| !partial);
+        if is_stream(&path) {
+            self.loading = true;
+            self.reset_table();
+            thread::spawn(move || {
+                // the reader blocks until a FIFO writer connects and until data arrives;
+                // EOF means the writer closed
+                let send_chunk = |parsed| tx.send(LoaderMsg::SamChunk(id, parsed)).is_ok();
+                let result = if path == Path::new("-") {
+                    sam::parse_chunks(io::stdin().lock(), STREAM_BATCH, &format, send_chunk)
+                } else {
+                    File::open(&path).and_then(|file| {
+                        sam::parse_chunks(BufReader::new(file), STREAM_BATCH, &format, send_chunk)
+                    })
+                };
+                if let Err(e) = result {
+                    let _ = tx.send(LoaderMsg::Error(format!(
+                        "cannot read {}: {}",
+                        path.display(),
+                        e
+                    )));
+                }
+                let _ = tx.send(LoaderMsg::SamDone(id));
+            });
+            return;
+        }
+        // an indexed, bgzipped VCF or BAM only needs the blocks under the region filter
+        let region = self.filters.region.clone().filter(|_| partial);
+        let threads = self
+            .config
+            .parse_threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        let mmap = self.config.mmap;
+        thread::spawn(move || {
+            // BCF is decoded whole; its index is not used
+            let binary = if bam::is_bam(&path) {
+                let indexed = region.as_ref().map(|r| bam::read_region(&path, r, &format));
+                Some(match indexed.transpose() {
+                    Ok(Some(Some(parsed))) => Ok(parsed),
+                    Ok(_) => bam::read(&path, &format),
+                    Err(e) => Err(e),
+                })
+            } else if bcf::is_bcf(&path) {
+                Some(bcf::read(&path))
+            } else {
+                None
+            };
+            if let Some(result) = binary {
+                let _ = match result {
+                    Ok(parsed) => tx.send(LoaderMsg::SamRows(id, parsed)),
+                    Err(e) => tx.send(LoaderMsg::Error(format!(
+                        "cannot read {}: {}",
+                        path.display(),
+                        e
+                    ))),
+                };
+                if bgzf::missing_eof_marker(&path).unwrap_or(false) {
+                    let _ = tx.send(LoaderMsg::Truncated(id));
+                }
+                return;
+            }
+            let result = match region.map(|r| vcf::read_region(&path, &r)).transpose() {
+                Ok(Some(Some(parsed))) => Ok(parsed),
+                Ok(_) if !vcf::is_gzipped(&path) => sam::parse_file(&path, threads, mmap, &format),
+                Ok(_) => vcf::open_text(&path).and_then(|r| sam::parse_reader(r, &format)),
+                Err(e) => Err(e),
+            };
+            let _ = match result {
+                Ok(parsed) => tx.send(LoaderMsg::SamRows(id, parsed)),
+                Err(e) => tx.send(LoaderMsg::Error(format!(
+                    "cannot read {}: {}",
+                    path.display(),
+                    e
+                ))),
+            };
+            if vcf::is_gzipped(&path) && bgzf::missing_eof_marker(&path).unwrap_or(false) {
+                let _ = tx.send(LoaderMsg::Truncated(id));
+            }
+        });
+    }
+
+    fn row_passes_filters(&self, row: usize) -> bool {
+        let fields = &self.table_rows[row];
+        let passes = match self.kind {
+            FileKind::Sam => {
+                self.filters.passes(fields)
+                    && (!self.filters.duplicates || self.is_duplicate(fields))
+                    && (self.unmapped != Unmapped::Hidden || !sam::is_unmapped(fields))
+            }
+            FileKind::Vcf => self.filters.passes_vcf(fields),
+        };
+        passes
+            && self.filters.passes_quick(fields)
+            && self
+                .filters
+                .expr
+                .as_ref()
+                .is_none_or(|e| e.passes(&|field| self.field_value(row, field)))
+    }
+
+    // a row's value for a compound filter condition; None for a missing tag
+    fn field_value(&self, row: usize, field: &expr::Field) -> Option<Cow<'_, str>> {
+        match field {
+            expr::Field::Column(col) => Some(self.cell_value(row, *col)),
+            expr::Field::Tag(tag) => sam::find_tag(&self.table_rows[row], tag).map(Cow::Borrowed),
+        }
+    }
+
+    fn is_duplicate(&self, fields: &[String]) -> bool {
+        let Some((qname, bits)) = sam::template_key(fields) else {
+            return false;
+        };
+        self.qname_rows
+            .get(&qname)
+            .is_some_and(|rows| self.primary_records(rows, bits) > 1)
+    }
+
+    // how many of these rows are primary records of the same read (read1/read2 bits)
+    fn primary_records(&self, rows: &[usize], bits: u16) -> usize {
+        rows.iter()
+            .filter(|&&i| sam::template_key(&self.table_rows[i]).is_some_and(|(_, b)| b == bits))
+            .count()
+    }
+
+    // J: show every record of the selected read's template (read1, read2, secondary
+    // and supplementary alignments) whatever the filters, and J again goes back to
+    // the view as it was
+    fn toggle_template(&mut self) {
+        if let Some((filters, inverted)) = self.template_view.take() {
+            self.filters = filters;
+            self.filter_inverted = inverted;
+            self.apply_filters();
+            self.notify(format!(
+                "back to {} of {} records",
+                self.filtered_indices.len(),
+                self.table_rows.len()
+            ));
+            return;
+        }
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let qname = self.table_rows[row][0].clone();
+        let records = self.qname_rows.get(&qname).map_or(1, |rows| rows.len());
+        let filters = std::mem::take(&mut self.filters);
+        self.template_view = Some((filters, self.filter_inverted));
+        self.filters.template = Some(qname.clone());
+        self.filter_inverted = false;
+        self.apply_filters();
+        self.notify(format!(
+            "{} record(s) of template {}, J shows all reads again",
+            records, qname
+        ));
+    }
+
+    // u: keep only primary records whose QNAME (as read1/read2) occurs more than once
+    fn toggle_duplicates(&mut self) {
+        self.filters.duplicates = !self.filters.duplicates;
+        self.apply_filters();
+        if self.filters.duplicates {
+            let repeated = self.qname_rows.values().flat_map(|rows| {
+                [0, 0x40, 0x80, 0xc0].map(|bits| self.primary_records(rows, bits))
+            });
+            let (templates, records) = repeated
+                .filter(|&n| n > 1)
+                .fold((0, 0), |(t, r), n| (t + 1, r + n));
+            self.notify(format!(
+                "{} duplicated template(s), {} primary record(s)",
+                templates, records
+            ));
+        }
+    }
+
+    // contig names from the header (@SQ, ##contig), else those seen in the records
+    fn known_contigs(&self) -> Vec<String> {
+        let from_header = match self.kind {
+            FileKind::Sam => self.ref_lengths.keys().cloned().collect(),
+            FileKind::Vcf => vcf::header_contigs(&self.header_lines),
+        };
+        if !from_header.is_empty() {
+            return from_header;
+        }
+        let col = self.contig_column();
+        let mut seen = HashSet::new();
+        self.table_rows
+            .iter()
+            .filter_map(|fields| fields.get(col))
+            .filter(|name| *name != "*" && seen.insert(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    // x: drop every filter, the sort and the search, keeping the selected record
+    fn reset_view(&mut self) {
+        let had_region = self.filters.region.is_some();
+        self.filters = filter::Filters::default();
+        self.filter_inverted = false;
+        self.template_view = None;
+        self.sort = None;
+        self.search_query.clear();
+        self.search_results.clear();
+        if had_region {
+            self.region_changed();
+        } else {
+            self.apply_filters();
+        }
+        self.notify(format!(
+            "view reset: all {} records, no filters, sort or search",
+            self.table_rows.len()
+        ));
+    }
+
+    // the region decides what an indexed VCF or BAM loads, elsewhere it only filters
+    // the view
+    fn region_changed(&mut self) {
+        if let Some(path) = self.loaded_path.clone()
+            && reads_region(&path)
+        {
+            self.load_sam(path);
+        } else {
+            self.apply_filters();
+        }
+    }
+
+    // rebuild filtered_indices from the active filters, keeping the selected read if it survives
+    fn apply_filters(&mut self) {
+        let selected = self.selected_row();
+        self.filtered_indices = (0..self.table_rows.len())
+            .filter(|&i| self.row_passes_filters(i) != self.filter_inverted)
+            .collect();
+        self.sort_view();
+        self.reselect(selected);
+        self.refresh_search();
+    }
+
+    // after the view changed, select the record that was selected before (original
+    // index `selected`); if it is gone, the nearest row left, so the selection never
+    // points past the end of the view
+    fn reselect(&mut self, selected: Option<usize>) {
+        let len = self.filtered_indices.len();
+        if len == 0 {
+            self.table_state.select(None);
+            return;
+        }
+        let found = selected.and_then(|orig| self.filtered_indices.iter().position(|&i| i == orig));
+        let pos = match (found, selected) {
+            (Some(pos), _) => pos,
+            // file order: the next record after the one that went away
+            (None, Some(orig)) if self.sort.is_none() => {
+                self.filtered_indices.partition_point(|&i| i < orig)
+            }
+            _ => self.table_state.selected().unwrap_or(0),
+        };
+        self.table_state.select(Some(pos.min(len - 1)));
+    }
+
+    // order the view by the sort column: numbers numerically and before text, ties
+    // in file order; unsorted views stay in file order
+    fn sort_view(&mut self) {
+        self.sort_by_column();
+        // unmapped reads after the mapped ones, each group keeping its order
+        if self.unmapped == Unmapped::Last && self.kind == FileKind::Sam {
+            let (mapped, unmapped): (Vec<usize>, Vec<usize>) = self
+                .filtered_indices
+                .iter()
+                .partition(|&&i| !sam::is_unmapped(&self.table_rows[i]));
+            self.filtered_indices = mapped;
+            self.filtered_indices.extend(unmapped);
+        }
+    }
+
+    fn sort_by_column(&mut self) {
+        let Some((col, descending)) = self.sort else {
+            self.filtered_indices.sort_unstable();
+            return;
+        };
+        let mut keyed: Vec<(Result<f64, String>, usize)> = self
+            .filtered_indices
+            .iter()
+            .map(|&i| {
+                let value = self.cell_value(i, col);
+                (value.parse::<f64>().map_err(|_| value.into_owned()), i)
+            })
+            .collect();
+        keyed.sort_by(|(a, i), (b, j)| {
+            let order = match (a, b) {
+                (Ok(x), Ok(y)) => x.total_cmp(y),
+                (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                (Err(x), Err(y)) => x.cmp(y),
+            };
+            if descending { order.reverse() } else { order }.then(i.cmp(j))
+        });
+        self.filtered_indices = keyed.into_iter().map(|(_, i)| i).collect();
+    }
+
+    // RNAME in SAM mode, CHROM in VCF mode
+    fn contig_column(&self) -> usize {
+        match self.kind {
+            FileKind::Sam => 2,
+            FileKind::Vcf => 0,
+        }
+    }
+
+    fn open_contig_menu(&mut self) {
+        let col = self.contig_column();
+        let selected = self
+            .selected_row()
+            .and_then(|i| self.table_rows[i].get(col))
+            .cloned();
+        let counts = self.contig_counts.get_or_insert_with(|| {
+            let mut counts: Vec<(String, usize)> = Vec::new();
+            let mut index: HashMap<&str, usize> = HashMap::new();
+            for fields in &self.table_rows {
+                let name = fields.get(col).map(|s| s.as_str()).unwrap_or("*");
+                match index.get(name) {
+                    Some(&i) => counts[i].1 += 1,
+                    None => {
+                        index.insert(name, counts.len());
+                        counts.push((name.to_string(), 1));
+                    }
+                }
+            }
+            counts
+        });
+        if counts.is_empty() {
+            self.notify("no records loaded");
+            return;
+        }
+        // start on the contig of the selected record
+        let current = selected
+            .and_then(|name| counts.iter().position(|(n, _)| *n == name))
+            .unwrap_or(0);
+        let mut state = ListState::default();
+        state.select(Some(current));
+        self.contig_menu = Some(state);
+    }
+
+    // select the first record of the menu's contig in the current view
+    fn jump_to_contig(&mut self) {
+        let Some(name) = self
+            .contig_menu
+            .take()
+            .and_then(|state| state.selected())
+            .and_then(|i| self.contig_counts.as_ref()?.get(i))
+            .map(|(name, _)| name.clone())
+        else {
+            return;
+        };
+        let col = self.contig_column();
+        match self
+            .filtered_indices
+            .iter()
+            .position(|&i| self.table_rows[i].get(col) == Some(&name))
+        {
+            Some(pos) => self.table_state.select(Some(pos)),
+            None => self.notify(format!("no record on {} in the current view", name)),
+        }
+    }
+
+    fn open_header_view(&mut self) {
+        if self.header_lines.is_empty() {
+            self.notify("the file has no header");
+            return;
+        }
+        let mut types: Vec<String> = Vec::new();
+        for line in &self.header_lines {
+            let kind = header_record_type(line);
+            if !types.iter().any(|t| t == kind) {
+                types.push(kind.to_string());
+            }
+        }
+        let mut state = ListState::default();
+        state.select(Some(0));
+        self.header_view = Some(HeaderView {
+            types,
+            state,
+            ..HeaderView::default()
+        });
+    }
+
+    // header lines passing the popup's type and text filters
+    fn header_view_lines(&self) -> Vec<&str> {
+        let Some(view) = &self.header_view else {
+            return vec![];
+        };
+        let kind = view.kind.map(|k| view.types[k].as_str());
+        let query = view.query.to_lowercase();
+        self.header_lines
+            .iter()
+            .map(|l| l.as_str())
+            .filter(|l| kind.is_none_or(|k| header_record_type(l) == k))
+            .filter(|l| query.is_empty() || l.to_lowercase().contains(&query))
+            .collect()
+    }
+
+    // ←/→ in the header popup: all lines, then each record type in turn
+    fn cycle_header_type(&mut self, forward: bool) {
+        let Some(view) = self.header_view.as_mut() else {
+            return;
+        };
+        let n = view.types.len();
+        view.kind = match (view.kind, forward) {
+            (None, true) => Some(0),
+            (None, false) => n.checked_sub(1),
+            (Some(k), true) => (k + 1 < n).then_some(k + 1),
+            (Some(k), false) => k.checked_sub(1),
+        };
+        view.state.select(Some(0));
+    }
+
+    fn scroll_header_view(&mut self, delta: isize) {
+        let len = self.header_view_lines().len();
+        if let Some(view) = self.header_view.as_mut() {
+            let i = view.state.selected().unwrap_or(0) as isize + delta;
+            view.state
+                .select(Some(i.clamp(0, len.saturating_sub(1) as isize) as usize));
+        }
+    }
+
+    // M: unmapped reads inline, then after the mapped ones, then hidden
+    fn cycle_unmapped(&mut self) {
+        self.unmapped = match self.unmapped {
+            Unmapped::Inline => Unmapped::Last,
+            Unmapped::Last => Unmapped::Hidden,
+            Unmapped::Hidden => Unmapped::Inline,
+        };
+        self.apply_filters();
+        let count = self
+            .table_rows
+            .iter()
+            .filter(|f| sam::is_unmapped(f))
+            .count();
+        self.notify(format!(
+            "{} unmapped read(s) {}",
+            count,
+            self.unmapped.describe()
+        ));
+    }
+
+    // j: let the region filter also keep split reads whose SA tag puts another part
+    // of them inside the region, so reads supporting a breakpoint are not missed
+    fn toggle_split_reads(&mut self) {
+        self.filters.split_reads = !self.filters.split_reads;
+        self.apply_filters();
+        let Some(region) = &self.filters.region else {
+            let msg = if self.filters.split_reads {
+                "a region will also keep split reads with an SA part in it"
+            } else {
+                "a region keeps only reads aligned in it"
+            };
+            self.notify(msg);
+            return;
+        };
+        let msg = if self.filters.split_reads {
+            let split = self
+                .table_rows
+                .iter()
+                .filter(|f| !region.overlaps(f) && region.overlaps_split(f))
+                .count();
+            format!(
+                "{} also keeps {} split read(s) with an SA part in it",
+                region, split
+            )
+        } else {
+            format!("{} keeps only reads aligned in it", region)
+        };
+        self.notify(msg);
+    }
+
+    // K: pin the selected row above the table, or unpin it
+    fn toggle_pin(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        match self.pinned.iter().position(|&i| i == row) {
+            Some(pos) => {
+                self.pinned.remove(pos);
+            }
+            None => self.pinned.push(row),
+        }
+    }
+
+    // every record of the selected read's QNAME, starting on the selected one
+    fn open_alignment_menu(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let rows = self
+            .qname_rows
+            .get(&self.table_rows[row][0])
+            .cloned()
+            .unwrap_or_default();
+        let mut state = ListState::default();
+        state.select(rows.iter().position(|&i| i == row));
+        self.alignment_menu = Some((rows, state));
+    }
+
+    fn jump_to_alignment(&mut self) {
+        let Some(row) = self
+            .alignment_menu
+            .take()
+            .and_then(|(rows, state)| rows.get(state.selected()?).copied())
+        else {
+            return;
+        };
+        match self.filtered_indices.iter().position(|&i| i == row) {
+            Some(pos) => self.table_state.select(Some(pos)),
+            None => self.notify("that alignment is not in the current view"),
+        }
+    }
+
+    // every match of the confirmed search, starting on the first at or after the
+    // selected row
+    fn open_results_menu(&mut self) {
+        if self.search_results.is_empty() {
+            let msg = if self.search_query.is_empty() {
+                "no search yet, / starts one".to_string()
+            } else {
+                format!("no matches for {}", self.search_query)
+            };
+            self.notify(msg);
+            return;
+        }
+        let row = self.selected_row().unwrap_or(0);
+        let first = self.search_results.partition_point(|&i| i < row);
+        let mut state = ListState::default();
+        state.select(Some(first.min(self.search_results.len() - 1)));
+        let mut outside: HashSet<usize> = HashSet::new();
+        if self.search_all {
+            let view: HashSet<usize> = self.filtered_indices.iter().copied().collect();
+            outside.extend(self.search_results.iter().filter(|i| !view.contains(i)));
+        }
+        self.results_menu = Some((outside, state));
+    }
+
+    fn move_results_menu(&mut self, delta: isize) {
+        let last = self.search_results.len().saturating_sub(1) as isize;
+        if let Some((_, state)) = self.results_menu.as_mut() {
+            let i = state.selected().unwrap_or(0) as isize;
+            state.select(Some((i + delta).clamp(0, last) as usize));
+        }
+    }
+
+    fn jump_to_result(&mut self) {
+        let Some(row) = self
+            .results_menu
+            .take()
+            .and_then(|(_, state)| self.search_results.get(state.selected()?).copied())
+        else {
+            return;
+        };
+        match self.filtered_indices.iter().position(|&i| i == row) {
+            Some(pos) => self.table_state.select(Some(pos)),
+            None => self.notify("that match is not in the current view"),
+        }
+    }
+
+    // one line about a row for the results list: where it is and what it is
+    fn row_summary(&self, row: usize) -> String {
+        let fields = &self.table_rows[row];
+        let get = |i: usize| fields.get(i).map(|s| s.as_str()).unwrap_or("");
+        match self.kind {
+            FileKind::Sam => format!(
+                "{}  {}:{}  FLAG {}  MAPQ {}  {}",
+                get(0),
+                get(2),
+                get(3),
+                get(1),
+                get(4),
+                get(5)
+            ),
+            FileKind::Vcf => format!(
+                "{}:{}  {}  {}>{}  {}",
+                get(0),
+                get(1),
+                get(2),
+                get(3),
+                get(4),
+                get(6)
+            ),
+        }
+    }
+
+    // ?: select a random row of the view, for spot checks
+    fn jump_to_random(&mut self) {
+        if self.filtered_indices.is_empty() {
+            return;
+        }
+        let pos = self.rng.below(self.filtered_indices.len());
+        self.table_state.select(Some(pos));
+    }
+
+    // !: start a fresh sequence of random picks
+    fn reseed(&mut self) {
+        let seed = rng::Rng::clock_seed();
+        self.rng = rng::Rng::new(seed);
+        self.notify(format!(
+            "random seed {} (random_seed = {} in the config repeats these picks)",
+            seed, seed
+        ));
+    }
+
+    // S on the focused column: ascending, then descending, then back to file order
+    fn cycle_sort(&mut self) {
+        let Some(&col) = self.visible_columns().get(self.focused_col) else {
+            return;
+        };
+        self.sort = match self.sort {
+            Some((c, false)) if c == col => Some((col, true)),
+            Some((c, true)) if c == col => None,
+            _ => Some((col, false)),
+        };
+        let selected = self.selected_row();
+        self.sort_view();
+        self.reselect(selected);
+    }
+
+    // the genotype filter as typed in its prompt, e.g. "NA001=het"
+    fn genotype_filter_text(&self) -> String {
+        let Some(genotype) = &self.filters.genotype else {
+            return String::new();
+        };
+        let names = vcf::sample_names(&self.header_lines);
+        match genotype.sample.and_then(|s| names.get(s)) {
+            Some(name) => format!("{}={}", name, genotype.zygosity.label()),
+            None => genotype.zygosity.label().to_string(),
+        }
+    }
+
+    // `het` for any sample, `SAMPLE=het` for one
+    fn parse_genotype_filter(&self, input: &str) -> Result<filter::GenotypeFilter, String> {
+        let (sample, zygosity) = match input.split_once('=') {
+            Some((name, z)) => (Some(name.trim()), z),
+            None => (None, input),
+        };
+        let Some(zygosity) = vcf::Zygosity::parse(zygosity) else {
+            return Err(format!("not a genotype class: {}", zygosity.trim()));
+        };
+        let names = vcf::sample_names(&self.header_lines);
+        let sample = match sample {
+            Some(name) => match names.iter().position(|n| n == name) {
+                Some(i) => Some(i),
+                None => return Err(format!("no sample named {}", name)),
+            },
+            None => None,
+        };
+        Ok(filter::GenotypeFilter { sample, zygosity })
+    }
+
+    // original index of the selected row
+    fn selected_row(&self) -> Option<usize> {
+        self.table_state
+            .selected()
+            .and_then(|pos| self.filtered_indices.get(pos).copied())
+    }
+
+    fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        let input = prompt.input.trim();
+        match prompt.kind {
+            PromptKind::MinMapq => {
+                if input.is_empty() {
+                    self.filters.min_mapq = None;
+                } else if let Ok(min) = input.parse() {
+                    self.filters.min_mapq = Some(min);
+                } else {
+                    self.notify(format!("not a MAPQ value: {}", input));
+                    return;
+                }
+                self.apply_filters();
+            }
+            PromptKind::Region => {
+                if input.is_empty() {
+                    self.filters.region = None;
+                } else if let Some(region) = sam::Region::parse(input) {
+                    let contigs = self.known_contigs();
+                    if !contigs.is_empty() && !contigs.contains(&region.name) {
+                        let names: Vec<&str> = contigs.iter().map(|c| c.as_str()).collect();
+                        let msg = match sam::closest_name(&region.name, &names) {
+                            Some(close) => {
+                                format!("no contig named {}; did you mean {}?", region.name, close)
+                            }
+                            None => format!("no contig named {}", region.name),
+                        };
+                        self.notify(msg);
+                        // reopen the prompt so the typo can be fixed in place
+                        self.prompt = Some(Prompt {
+                            kind: PromptKind::Region,
+                            input: input.to_string(),
+                        });
+                        return;
+                    }
+                    config::push_history(&mut self.config.region_history, &region.to_string());
+                    let _ = self.config.save();
+                    self.filters.region = Some(region);
+                } else {
+                    self.notify(format!("not a region: {}", input));
+                    return;
+                }
+                self.region_changed();
+            }
+            PromptKind::VariantType => {
+                let Some(types) = vcf::parse_types(input) else {
+                    self.notify(format!(
+                        "unknown variant type in {}, use {} or indel",
+                        input,
+                        vcf::VARIANT_TYPES.join(",")
+                    ));
+                    return;
+                };
+                self.filters.variant_types = types;
+                self.apply_filters();
+            }
+            PromptKind::ExportCsv => {
+                let (path, columns) = match input.split_once(char::is_whitespace) {
+                    Some((path, columns)) => (path, columns.trim()),
+                    None => (input, ""),
+                };
+                if path.is_empty() {
+                    return;
+                }
+                if self.kind == FileKind::Sam && is_fastq_path(path) {
+                    if ![1, 9, 10].iter().all(|&col| self.column_parsed(col)) {
+                        self.notify(
+                            "a FASTQ export needs FLAG, SEQ and QUAL, and --columns left some out while loading",
+                        );
+                        return;
+                    }
+                    let msg = match self.export_fastq(Path::new(path)) {
+                        Ok((written, no_seq, not_primary)) => format!(
+                            "wrote {} read(s) to {}; skipped {} without SEQ/QUAL and {} secondary/supplementary",
+                            written, path, no_seq, not_primary
+                        ),
+                        Err(e) => format!("cannot write {}: {}", path, e),
+                    };
+                    self.notify(msg);
+                    return;
+                }
+                let raw = match self.kind {
+                    FileKind::Sam => "sam",
+                    FileKind::Vcf => "vcf",
+                };
+                if Path::new(path)
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case(raw))
+                {
+                    if self.fields_dropped() {
+                        self.notify(format!(
+                            "a .{} export writes records as they are in the file, and --columns left fields out while loading; pick .tsv or .csv",
+                            raw
+                        ));
+                        return;
+                    }
+                    let msg = match self.export_records(Path::new(path)) {
+                        Ok(()) => format!(
+                            "wrote the header and {} record(s) to {}",
+                            self.filtered_indices.len(),
+                            path
+                        ),
+                        Err(e) => format!("cannot write {}: {}", path, e),
+                    };
+                    self.notify(msg);
+                    return;
+                }
+                let columns = if columns.is_empty() {
+                    self.visible_columns()
+                } else {
+                    let mut picked = Vec::new();
+                    for name in columns
+                        .split(',')
+                        .map(|s| s.trim())
+                        .filter(|s| !s.is_empty())
+                    {
+                        match self
+                            .columns()
+                            .iter()
+                            .position(|c| c.eq_ignore_ascii_case(name))
+                        {
+                            Some(col) => picked.push(col),
+                            None => {
+                                self.notify(format!("no column {}", name));
+                                return;
+                            }
+                        }
+                    }
+                    picked
+                };
+                let msg = match self.export_csv(
+                    Path::new(path),
+                    &columns,
+                    is_tsv_path(Path::new(path)),
+                ) {
+                    Ok(()) => format!(
+                        "wrote {} rows of {} column(s) to {}",
+                        self.filtered_indices.len(),
+                        columns.len(),
+                        path
+                    ),
+                    Err(e) => format!("cannot write {}: {}", path, e),
+                };
+                self.notify(msg);
+            }
+            PromptKind::ExportPileup => {
+                let (site, path) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+                let path = path.trim();
+                let Some(site) = sam::Region::parse(site).filter(|_| site.contains(':')) else {
+                    self.notify(format!("not a position: {}", site));
+                    return;
+                };
+                if path.is_empty() {
+                    self.notify("give a file name after the position");
+                    return;
+                }
+                let msg = match self.export_pileup(&site.name, site.start, Path::new(path)) {
+                    Ok(reads) => format!(
+                        "wrote {} read(s) at {}:{} to {}",
+                        reads, site.name, site.start, path
+                    ),
+                    Err(e) => format!("cannot write {}: {}", path, e),
+                };
+                self.notify(msg);
+            }
+            PromptKind::ExtractBam => {
+                let (region, path) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+                let path = path.trim();
+                let Some(region) = sam::Region::parse(region) else {
+                    self.notify(format!("not a region: {}", region));
+                    return;
+                };
+                if path.is_empty() {
+                    self.notify("give a BAM file name after the region");
+                    return;
+                }
+                self.extract_bam(region, PathBuf::from(path));
+            }
+            PromptKind::Derive => {
+                if input.is_empty() {
+                    self.derived = None;
+                } else {
+                    match Derived::parse(input, self.columns()) {
+                        Ok(derived) => self.derived = Some(derived),
+                        Err(e) => {
+                            self.notify(e);
+                            return;
+                        }
+                    }
+                }
+                let col = self.derived_column();
+                self.content_widths[col] = self.columns()[col].width() as u16;
+                for i in 0..self.table_rows.len() {
+                    let width = self.cell_value(i, col).width().min(u16::MAX as usize) as u16;
+                    self.content_widths[col] = self.content_widths[col].max(width);
+                }
+                if self.sort.is_some_and(|(c, _)| c == col) {
+                    let selected = self.selected_row();
+                    self.sort_view();
+                    self.reselect(selected);
+                }
+                self.refresh_search();
+            }
+            PromptKind::Expression => {
+                if input.is_empty() {
+                    self.filters.expr = None;
+                } else {
+                    match expr::Expr::parse(input, self.columns(), self.kind == FileKind::Sam) {
+                        Ok(expr) => self.filters.expr = Some(expr),
+                        Err(e) => {
+                            self.notify(format!("filter not applied: {}", e));
+                            return;
+                        }
+                    }
+                }
+                self.apply_filters();
+                let msg = format!(
+                    "{} of {} records shown",
+                    self.filtered_indices.len(),
+                    self.table_rows.len()
+                );
+                self.notify(msg);
+            }
+            PromptKind::Tag => {
+                if input.is_empty() {
+                    self.filters.tag = None;
+                } else if let Some(tag) = filter::TagFilter::parse(input) {
+                    self.filters.tag = Some(tag);
+                } else {
+                    self.notify(format!("not a two-character tag name: {}", input));
+                    return;
+                }
+                self.apply_filters();
+                let msg = format!(
+                    "{} of {} reads shown",
+                    self.filtered_indices.len(),
+                    self.table_rows.len()
+                );
+                self.notify(msg);
+            }
+            PromptKind::Note => {
+                let Some(row) = self.selected_row() else {
+                    return;
+                };
+                if input.is_empty() {
+                    self.notes.remove(&row);
+                } else {
+                    self.notes.insert(row, input.to_string());
+                }
+                self.save_notes();
+            }
+            PromptKind::Genotype => {
+                if input.is_empty() {
+                    self.filters.genotype = None;
+                    self.apply_filters();
+                    return;
+                }
+                match self.parse_genotype_filter(input) {
+                    Ok(genotype) => self.filters.genotype = Some(genotype),
+                    Err(e) => {
+                        self.notify(e);
+                        return;
+                    }
+                }
+                self.apply_filters();
+            }
+        }
+    }
+
+    // raw lines for the pager: the selected record, or the header and every record
+    // of the current view
+    fn pager_text(&self, view: bool) -> Option<String> {
+        if !view {
+            return self
+                .selected_row()
+                .map(|i| self.table_rows[i].join("\t") + "\n");
+        }
+        let mut text = String::new();
+        for line in &self.header_lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        for &i in &self.filtered_indices {
+            text.push_str(&self.table_rows[i].join("\t"));
+            text.push('\n');
+        }
+        Some(text)
+    }
+
+    // `export_line_ending = crlf` in the config ends exported lines Windows style
+    fn line_ending(&self) -> &'static str {
+        if self.config.export_line_ending.as_deref() == Some("crlf") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    // a table writer for an export, tab-separated for TSV, ending lines as
+    // configured; the bool says whether to start with the column names
+    fn export_writer(&self, path: &Path, tsv: bool) -> io::Result<(csv::Writer<File>, bool)> {
+        let terminator = match self.line_ending() {
+            "\n" => csv::Terminator::Any(b'\n'),
+            _ => csv::Terminator::CRLF,
+        };
+        let out = csv::WriterBuilder::new()
+            .delimiter(if tsv { b'\t' } else { b',' })
+            .terminator(terminator)
+            .from_path(path)?;
+        Ok((out, self.config.export_header.unwrap_or(!tsv)))
+    }
+
+    // the rows of the current view, in display order, as CSV or TSV; fields
+    // holding the separator or quotes (INFO strings) are quoted by the writer
+    fn export_csv(&self, path: &Path, columns: &[usize], tsv: bool) -> io::Result<()> {
+        let (mut out, header) = self.export_writer(path, tsv)?;
+        if header {
+            out.write_record(columns.iter().map(|&c| self.columns()[c]))?;
+        }
+        for &i in &self.filtered_indices {
+            out.write_record(columns.iter().map(|&c| self.cell_value(i, c).into_owned()))?;
+        }
+        out.flush()
+    }
+
+    // the header and the records of the current view as they are in the file, a
+    // smaller SAM (or VCF) for other tools
+    fn export_records(&self, path: &Path) -> io::Result<()> {
+        let mut text = self.pager_text(true).unwrap_or_default();
+        if self.line_ending() != "\n" {
+            text = text.replace('\n', self.line_ending());
+        }
+        std::fs::write(path, text)
+    }
+
+    // the reads of the current view as FASTQ, reverse-strand reads turned back to
+    // the orientation they were sequenced in; secondary and supplementary records
+    // repeat a read (or part of it) and are left out like samtools fastq does.
+    // Returns the reads written, those without SEQ/QUAL and those left out
+    fn export_fastq(&self, path: &Path) -> io::Result<(usize, usize, usize)> {
+        let mut text = String::new();
+        let (mut written, mut no_seq, mut not_primary) = (0, 0, 0);
+        for &i in &self.filtered_indices {
+            let fields = &self.table_rows[i];
+            let flag = fields[1].parse::<u16>().unwrap_or(0);
+            if flag & 0x900 != 0 {
+                not_primary += 1;
+                continue;
+            }
+            let (seq, qual) = (&fields[9], &fields[10]);
+            if seq.is_empty() || seq == "*" || qual == "*" || seq.len() != qual.len() {
+                no_seq += 1;
+                continue;
+            }
+            let (seq, qual) = if flag & 0x10 != 0 {
+                (
+                    Cow::Owned(sam::reverse_complement(seq)),
+                    Cow::Owned(qual.chars().rev().collect()),
+                )
+            } else {
+                (Cow::Borrowed(seq.as_str()), Cow::Borrowed(qual.as_str()))
+            };
+            let nl = self.line_ending();
+            text.push_str(&format!("@{}{nl}{}{nl}+{nl}{}{nl}", fields[0], seq, qual));
+            written += 1;
+        }
+        if path.extension().is_some_and(|e| e == "gz") {
+            let mut out =
+                flate2::write::GzEncoder::new(File::create(path)?, flate2::Compression::default());
+            out.write_all(text.as_bytes())?;
+            out.finish()?;
+        } else {
+            std::fs::write(path, text)?;
+        }
+        Ok((written, no_seq, not_primary))
+    }
+
+    // one line per read of the current view covering `chrom:pos`: its base there
+    // (* inside a deletion), base quality and strand; returns the number of reads
+    fn export_pileup(&self, chrom: &str, pos: u64, path: &Path) -> io::Result<usize> {
+        let (mut out, header) = self.export_writer(path, is_tsv_path(path))?;
+        if header {
+            out.write_record(["QNAME", "FLAG", "MAPQ", "BASE", "QUAL", "STRAND"])?;
+        }
+        let mut reads = 0;
+        for &i in &self.filtered_indices {
+            let fields = &self.table_rows[i];
+            let Ok(flag) = fields[1].parse::<u16>() else {
+                continue;
+            };
+            let (Some(cigar), Ok(start)) = (sam::record_cigar(fields), fields[3].parse()) else {
+                continue;
+            };
+            if flag & 0x4 != 0 || fields[2] != chrom {
+                continue;
+            }
+            let Some(offset) = align::query_offset_at(&cigar, start, pos) else {
+                continue;
+            };
+            let (base, qual) = match offset {
+                Some(q) => (
+                    fields[9].get(q..q + 1).unwrap_or("N").to_string(),
+                    sam::phred_scores(&fields[10])
+                        .and_then(|scores| scores.get(q).copied())
+                        .map(|s| s.to_string())
+                        .unwrap_or_default(),
+                ),
+                None => ("*".to_string(), String::new()),
+            };
+            let strand = if flag & 0x10 != 0 { "-" } else { "+" };
+            out.write_record([
+                fields[0].as_str(),
+                &fields[1],
+                &fields[4],
+                &base,
+                &qual,
+                strand,
+            ])?;
+            reads += 1;
+        }
+        out.flush()?;
+        Ok(reads)
+    }
+
+    // B: write the records overlapping `region` (all of them, not only the view) to
+    // an indexed BAM, like `samtools view -b`, on a thread reporting its progress
+    fn extract_bam(&mut self, region: sam::Region, path: PathBuf) {
+        if self.progress.is_some() {
+            self.notify("an extraction is still being written");
+            return;
+        }
+        let rows: Vec<Vec<String>> = self
+            .table_rows
+            .iter()
+            .filter(|f| region.overlaps(f))
+            .cloned()
+            .collect();
+        if rows.is_empty() {
+            self.notify(format!("no reads overlap {}", region));
+            return;
+        }
+        let header = self.header_lines.clone();
+        let Some(tx) = self.loader_tx.clone() else {
+            return;
+        };
+        let total = rows.len();
+        self.progress = Some(format!("writing {}: 0%", path.display()));
+        thread::spawn(move || {
+            let result = bam::write(&path, &header, &rows, |written| {
+                let _ = tx.send(LoaderMsg::Progress(format!(
+                    "writing {}: {}%",
+                    path.display(),
+                    written * 100 / total
+                )));
+            });
+            let _ = tx.send(LoaderMsg::ProgressDone(match result {
+                Ok(()) => format!(
+                    "wrote {} read(s) in {} to {} and {}",
+                    total,
+                    region,
+                    path.display(),
+                    bam::index_path(&path).display()
+                ),
+                Err(e) => format!("cannot write {}: {}", path.display(), e),
+            }));
+        });
+    }
+
+    fn save_notes(&mut self) {
+        let Some(path) = self.loaded_path.clone().filter(|p| p != Path::new("-")) else {
+            self.notify("notes on stdin are kept for this session only");
+            return;
+        };
+        let mut rows: Vec<usize> = self.notes.keys().copied().collect();
+        rows.sort_unstable();
+        let entries: Vec<(u64, String, &str)> = rows
+            .iter()
+            .map(|&i| {
+                let fields = &self.table_rows[i];
+                let record = match self.kind {
+                    FileKind::Sam => fields[0].clone(),
+                    FileKind::Vcf => format!("{}:{}", fields[0], fields[1]),
+                };
+                (self.row_offsets[i], record, self.notes[&i].as_str())
+            })
+            .collect();
+        if let Err(e) = notes::save(&path, &entries) {
+            let msg = format!("cannot save {}: {}", notes::sidecar(&path).display(), e);
+            self.notify(msg);
+        }
+    }
+
+    fn cell_value(&self, row: usize, col: usize) -> Cow<'_, str> {
+        let fields = &self.table_rows[row];
+        if col == self.derived_column() {
+            let Some(derived) = &self.derived else {
+                return Cow::Borrowed("");
+            };
+            return Cow::Owned(
+                derived
+                    .extract(&self.cell_value(row, derived.source))
+                    .to_string(),
+            );
+        }
+        if col == self.offset_column() {
+            return Cow::Owned(
+                self.row_offsets
+                    .get(row)
+                    .map(|o| o.to_string())
+                    .unwrap_or_default(),
+            );
+        }
+        match (self.kind, col) {
+            (FileKind::Sam, RG_COLUMN) => Cow::Borrowed(sam::find_tag(fields, "RG").unwrap_or("*")),
+            // `.` for an RNAME no @SQ line declares, `*` included
+            (FileKind::Sam, TID_COLUMN) => fields
+                .get(2)
+                .and_then(|r| self.ref_ids.get(r))
+                .map_or(Cow::Borrowed("."), |tid| Cow::Owned(tid.to_string())),
+            (FileKind::Vcf, vcf::TYPE_COLUMN) => Cow::Borrowed(vcf::variant_type(fields)),
+            (FileKind::Vcf, vcf::SAMPLES_COLUMN) => {
+                Cow::Owned(fields.get(col..).map(|s| s.join(" ")).unwrap_or_default())
+            }
+            _ => Cow::Borrowed(fields.get(col).map(|s| s.as_str()).unwrap_or("")),
+        }
+    }
+
+    // a cell as drawn in the table: cell_value with thousands separators in numeric
+    // columns when the config asks for them
+    fn shown_value(&self, row: usize, col: usize) -> Cow<'_, str> {
+        let value = self.cell_value(row, col);
+        if !self.config.group_digits || !self.kind.is_numeric(col) {
+            return value;
+        }
+        match group_thousands(&value) {
+            Some(grouped) => Cow::Owned(grouped),
+            None => value,
+        }
+    }
+
+    // every value of the focused column in the current view, one per line
+    fn copy_column(&mut self) {
+        let Some(&col) = self.visible_columns().get(self.focused_col) else {
+            return;
+        };
+        let mut text = String::new();
+        for &i in &self.filtered_indices {
+            text.push_str(&self.cell_value(i, col));
+            text.push('\n');
+        }
+        let name = self.columns()[col];
+        let what = format!("{} {} value(s)", self.filtered_indices.len(), name);
+        self.copy_text(text, &what, &format!("varview-{}.txt", name.to_lowercase()));
+    }
+
+    // the selected record's raw line
+    fn copy_row(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let text = self.table_rows[row].join("\t") + "\n";
+        self.copy_text(text, "the selected record", "varview-record.txt");
+    }
+
+    // to the clipboard or, without one, to `file_name` in the temp directory
+    fn copy_text(&mut self, text: String, what: &str, file_name: &str) {
+        if self.clipboard.is_none() {
+            self.clipboard = arboard::Clipboard::new().ok();
+        }
+        if let Some(clipboard) = self.clipboard.as_mut()
+            && clipboard.set_text(text.clone()).is_ok()
+        {
+            self.notify(format!("copied {}", what));
+            return;
+        }
+        let path = std::env::temp_dir().join(file_name);
+        match std::fs::write(&path, text) {
+            Ok(()) => self.notify(format!(
+                "no clipboard: wrote {} to {}",
+                what,
+                path.display()
+            )),
+            Err(e) => self.notify(format!("copy failed: {}", e)),
+        }
+    }
+
+    // select the other read of the selected read's pair (the primary record with
+    // the opposite read1/read2 bit)
+    fn jump_to_mate(&mut self) {
+        let Some(row) = self.selected_row().filter(|_| self.kind == FileKind::Sam) else {
+            return;
+        };
+        let bits = self.table_rows[row][1].parse::<u16>().unwrap_or(0) & 0xc0;
+        if bits == 0 {
+            self.notify("not a paired read");
+            return;
+        }
+        let mate = self
+            .qname_rows
+            .get(&self.table_rows[row][0])
+            .and_then(|rows| {
+                rows.iter().copied().find(|&i| {
+                    sam::template_key(&self.table_rows[i]).is_some_and(|(_, b)| b != bits && b != 0)
+                })
+            });
+        let Some(mate) = mate else {
+            self.notify("the mate is not in the loaded records");
+            return;
+        };
+        match self.filtered_indices.iter().position(|&i| i == mate) {
+            Some(pos) => self.table_state.select(Some(pos)),
+            None => self.notify("the mate is not in the current view"),
+        }
+    }
+
+    // Enter in the table, set by `enter_action` in the config
+    fn enter_action(&mut self) {
+        match self.config.enter_action.as_deref().unwrap_or("detail") {
+            "copy" => self.copy_row(),
+            "mate" => self.jump_to_mate(),
+            _ => self.detail_open = !self.detail_open,
+        }
+    }
+
+    // the visible columns that fit in `room` cells once the table is scrolled
+    // `table_scroll.0` cells to the right, as (display position, logical column, cells
+    // cut from the left, width); the last one is cut short on the right if needed
+    fn scrolled_columns(&self, room: usize) -> Vec<(usize, usize, usize, u16)> {
+        let spacing = if self.dense { 0 } else { 1 };
+        let mut skip = self.table_scroll.0 as usize;
+        let mut used = 0;
+        let mut shown = Vec::new();
+        for (pos, col) in self.visible_columns().into_iter().enumerate() {
+            let width = self.column_width(col) as usize;
+            if skip >= width {
+                skip = skip.saturating_sub(width + spacing);
+                continue;
+            }
+            let cut = skip;
+            skip = 0;
+            let width = (width - cut).min(room.saturating_sub(used));
+            if width == 0 {
+                break;
+            }
+            shown.push((pos, col, cut, width as u16));
+            used += width + spacing;
+        }
+        shown
+    }
+
+    // scroll sideways, stopping once the last column reaches the left edge
+    fn scroll_table(&mut self, delta: isize) {
+        let spacing = if self.dense { 0 } else { 1 };
+        let widths: Vec<usize> = self
+            .visible_columns()
+            .into_iter()
+            .map(|col| self.column_width(col) as usize + spacing)
+            .collect();
+        let max = widths.iter().sum::<usize>() - widths.last().copied().unwrap_or(0);
+        let h = (self.table_scroll.0 as isize + delta).clamp(0, max as isize);
+        self.table_scroll.0 = h as u16;
+    }
+
+    // displayed logical columns, in order, skipping optional columns that are off
+    fn visible_columns(&self) -> Vec<usize> {
+        self.column_order
+            .iter()
+            .copied()
+            .filter(|&c| c != self.offset_column() || self.show_offsets)
+            .filter(|&c| self.kind != FileKind::Sam || c != TID_COLUMN || self.show_tid)
+            .filter(|&c| self.column_parsed(c))
+            .filter(|&c| c != self.derived_column() || self.derived.is_some())
+            .collect()
+    }
+
+    // whether --columns left SAM fields out of the records while loading
+    fn fields_dropped(&self) -> bool {
+        self.kind == FileKind::Sam && self.parse_columns.is_some()
+    }
+
+    // whether a column has values: with --columns, SAM fields left out while
+    // parsing (and what is worked out from them) are not shown
+    fn column_parsed(&self, col: usize) -> bool {
+        let Some(keep) = self.parse_columns.filter(|_| self.kind == FileKind::Sam) else {
+            return true;
+        };
+        match col {
+            RG_COLUMN => keep & sam::TAGS_BIT != 0,
+            TID_COLUMN => keep & (1 << 2) != 0,
+            col if col < sam::SAM_MANDATORY => keep & (1 << col) != 0,
+            _ => true,
+        }
+    }
+
+    fn move_focus(&mut self, delta: isize) {
+        let last = self.visible_columns().len().saturating_sub(1) as isize;
+        self.focused_col = (self.focused_col as isize + delta).clamp(0, last) as usize;
+    }
+
+    // shift the focused column one place left or right, keeping it focused
+    fn move_focused_column(&mut self, delta: isize) {
+        let visible = self.visible_columns();
+        let from = self.focused_col;
+        let to = from as isize + delta;
+        if from >= visible.len() || to < 0 || to as usize >= visible.len() {
+            return;
+        }
+        let a = self.column_order.iter().position(|&c| c == visible[from]);
+        let b = self
+            .column_order
+            .iter()
+            .position(|&c| c == visible[to as usize]);
+        if let (Some(a), Some(b)) = (a, b) {
+            self.column_order.swap(a, b);
+            self.focused_col = to as usize;
+        }
+    }
+
+    // a manual width wins; otherwise fit the content up to the column's cap
+    fn column_width(&self, col: usize) -> u16 {
+        if let Some(&width) = self.width_overrides.get(&col) {
+            return width;
+        }
+        let cap = self.column_cap(col);
+        let width = self.content_widths[col].clamp(1, cap.max(1));
+        // leave room for the sort arrow
+        match self.sort {
+            Some((c, _)) if c == col => width.max(self.columns()[col].width() as u16 + 2),
+            _ => width,
+        }
+    }
+
+    // the configured cap wins in dense mode too; only the default is tighter there
+    fn column_cap(&self, col: usize) -> u16 {
+        self.config
+            .column_max_widths
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(self.columns()[col]))
+            .map(|(_, w)| *w)
+            .or(self.config.max_column_width)
+            .unwrap_or(if self.dense {
+                DENSE_MAX_WIDTH
+            } else {
+                DEFAULT_MAX_WIDTH
+            })
+    }
+
+    fn resize_focused_column(&mut self, delta: i16) {
+        let Some(&col) = self.visible_columns().get(self.focused_col) else {
+            return;
+        };
+        let width = (self.column_width(col) as i16 + delta).clamp(1, 200) as u16;
+        self.width_overrides.insert(col, width);
+    }
+
+    // written once when resize mode ends rather than on every step
+    fn save_column_widths(&mut self) {
+        let mut widths: Vec<(usize, u16)> =
+            self.width_overrides.iter().map(|(&c, &w)| (c, w)).collect();
+        widths.sort_unstable();
+        // widths are kept by name, so leave other kinds' columns alone
+        let columns = self.columns();
+        self.config
+            .column_widths
+            .retain(|(name, _)| !columns.iter().any(|c| c.eq_ignore_ascii_case(name)));
+        self.config
+            .column_widths
+            .extend(widths.into_iter().map(|(c, w)| (columns[c].to_string(), w)));
+        let _ = self.config.save();
+    }
+
+    fn save_column_order(&mut self) {
+        let names = self
+            .column_order
+            .iter()
+            .map(|&c| self.columns()[c].to_string())
+            .collect();
+        match self.kind {
+            FileKind::Sam => self.config.column_order = names,
+            FileKind::Vcf => self.config.vcf_column_order = names,
+        }
+        let _ = self.config.save();
+    }
+
+    // narrow the view to reads overlapping the selected read's alignment
+    // V: from a variant to the reads under it in the last SAM file opened, and
+    // from those reads back to the variants
+    fn toggle_variant_reads(&mut self) {
+        match self.kind {
+            FileKind::Vcf => self.show_variant_reads(),
+            FileKind::Sam => self.return_to_variants(),
+        }
+    }
+
+    fn show_variant_reads(&mut self) {
+        let Some(reads) = self.alignments_path.clone() else {
+            self.notify("open a SAM file first; V on a variant then shows its reads");
+            return;
+        };
+        let (Some(row), Some(path)) = (self.selected_row(), self.loaded_path.clone()) else {
+            return;
+        };
+        let fields = &self.table_rows[row];
+        let Some(pos) = fields.get(1).and_then(|p| p.parse::<u64>().ok()) else {
+            return;
+        };
+        let region = sam::Region {
+            name: fields[0].clone(),
+            start: pos,
+            end: vcf::record_end(fields).unwrap_or(pos),
+        };
+        let locus = format!("{}:{}", region.name, region.start);
+        let offset = self.row_offsets.get(row).copied();
+        self.variant_return = Some((path, self.filters.region.take(), offset));
+        self.filters.region = Some(region);
+        self.switch_file(reads);
+        self.notify(format!("reads overlapping {}, V goes back", locus));
+    }
+
+    // load without the summary popup, which would only get in the way of V
+    fn switch_file(&mut self, path: PathBuf) {
+        self.load_sam(path);
+        self.want_summary = false;
+        self.summary = None;
+    }
+
+    fn return_to_variants(&mut self) {
+        let Some((path, region, offset)) = self.variant_return.take() else {
+            self.notify("V on a variant in VCF mode shows the reads under it");
+            return;
+        };
+        self.filters.region = region;
+        self.switch_file(path);
+        // a VCF back from the cache is already loaded, a fresh read selects it on arrival
+        self.pending_offset = offset;
+        self.select_pending();
+    }
+
+    fn select_pending(&mut self) {
+        let Some(offset) = self.pending_offset else {
+            return;
+        };
+        if let Ok(row) = self.row_offsets.binary_search(&offset) {
+            self.pending_offset = None;
+            self.reselect(Some(row));
+        }
+    }
+
+    // contig and 1-based position of a row; None for unplaced reads
+    fn row_position(&self, row: usize) -> Option<(String, u64)> {
+        let fields = &self.table_rows[row];
+        let (name, pos) = match self.kind {
+            FileKind::Sam => (&fields[2], &fields[3]),
+            FileKind::Vcf => (&fields[0], &fields[1]),
+        };
+        let pos = pos.parse::<u64>().ok().filter(|&p| p > 0)?;
+        (name != "*").then(|| (name.clone(), pos))
+    }
+
+    // X: remember the selected record's position for W
+    fn set_mark(&mut self) {
+        let Some(mark) = self.selected_row().and_then(|i| self.row_position(i)) else {
+            self.notify("the selected record has no position to mark");
+            return;
+        };
+        self.notify(format!(
+            "marked {}:{}, W measures from here",
+            mark.0, mark.1
+        ));
+        self.mark = Some(mark);
+    }
+
+    // W: distance from the mark to the selected record, and how many records of the
+    // file start between the two
+    fn measure(&mut self) {
+        let Some((contig, from)) = self.mark.clone() else {
+            self.notify("no mark yet, X marks the selected record's position");
+            return;
+        };
+        let Some((to_contig, to)) = self.selected_row().and_then(|i| self.row_position(i)) else {
+            self.notify("the selected record has no position");
+            return;
+        };
+        if to_contig != contig {
+            self.notify(format!(
+                "{}:{} and {}:{} are on different contigs",
+                contig, from, to_contig, to
+            ));
+            return;
+        }
+        let (start, end) = (from.min(to), from.max(to));
+        let between = (0..self.table_rows.len())
+            .filter_map(|i| self.row_position(i))
+            .filter(|(name, pos)| *name == contig && *pos > start && *pos < end)
+            .count();
+        self.notify(format!(
+            "{}:{} to {}: {} bp, {} record(s) start in between",
+            contig,
+            from,
+            to,
+            to.abs_diff(from),
+            between
+        ));
+    }
+
+    fn filter_to_selected_locus(&mut self) {
+        let Some(fields) = self.selected_row().map(|i| &self.table_rows[i]) else {
+            return;
+        };
+        let region = match self.kind {
+            FileKind::Sam => {
+                let Some(pos) = fields.get(3).and_then(|p| p.parse::<u64>().ok()) else {
+                    return;
+                };
+                if fields[2] == "*" || pos == 0 {
+                    return;
+                }
+                let region = sam::Region {
+                    name: fields[2].clone(),
+                    start: pos,
+                    end: sam::reference_end(pos, fields),
+                };
+                // only primary mapped reads: no unmapped, secondary or supplementary
+                self.filters.locus_flags |= LOCUS_EXCLUDE_FLAGS & !self.filters.exclude_flags;
+                self.filters.exclude_flags |= LOCUS_EXCLUDE_FLAGS;
+                region
+            }
+            FileKind::Vcf => {
+                let Some(pos) = fields.get(1).and_then(|p| p.parse::<u64>().ok()) else {
+                    return;
+                };
+                sam::Region {
+                    name: fields[0].clone(),
+                    start: pos,
+                    end: vcf::record_end(fields).unwrap_or(pos),
+                }
+            }
+        };
+        self.filters.region = Some(region);
+        self.region_changed();
+    }
+
+    fn perform_search(&mut self) {
+        self.search_query = self.search_input.trim().to_string();
+        if !self.search_query.is_empty() {
+            config::push_history(&mut self.config.search_history, &self.search_query);
+            let _ = self.config.save();
+        }
+        self.refresh_search();
+
+        // search_results is in row order, so each row of the view is a binary search
+        if let Some(pos) = self
+            .filtered_indices
+            .iter()
+            .position(|i| self.search_results.binary_search(i).is_ok())
+        {
+            self.table_state.select(Some(pos));
+        }
+    }
+
+    // step through earlier queries in the search modal, older with Up, newer with Down
+    fn browse_history(&mut self, older: bool) {
+        let history = &self.config.search_history;
+        if history.is_empty() {
+            return;
+        }
+        self.history_pos = history_step(history.len(), self.history_pos, older);
+        self.search_input = self
+            .history_pos
+            .map(|p| history[p].clone())
+            .unwrap_or_default();
+    }
+
+    // the same through earlier regions in the region prompt
+    fn browse_region_history(&mut self, older: bool) {
+        let history = &self.config.region_history;
+        if history.is_empty() {
+            return;
+        }
+        self.history_pos = history_step(history.len(), self.history_pos, older);
+        if let Some(prompt) = self.prompt.as_mut() {
+            prompt.input = self
+                .history_pos
+                .map(|p| history[p].clone())
+                .unwrap_or_default();
+        }
+    }
+
+    // recompute matches for the confirmed query without moving the selection
+    fn search_fields(&self) -> Vec<usize> {
+        if !self.search_columns.is_empty() {
+            return self.search_columns.clone();
+        }
+        // QNAME for reads, ID for variants
+        match self.kind {
+            FileKind::Sam => vec![0],
+            FileKind::Vcf => vec![2],
+        }
+    }
+
+    fn search_label(&self) -> String {
+        self.search_fields()
+            .iter()
+            .map(|&c| self.columns()[c])
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    // f: search the focused column instead, or go back to the default on repeat
+    fn toggle_search_column(&mut self) {
+        let Some(&col) = self.visible_columns().get(self.focused_col) else {
+            return;
+        };
+        if self.search_columns == [col] {
+            self.search_columns.clear();
+        } else {
+            self.search_columns = vec![col];
+        }
+        let msg = format!("/ searches {}", self.search_label());
+        self.notify(msg);
+        self.refresh_search();
+    }
+
+    fn refresh_search(&mut self) {
+        let needle = self.search_query.as_str();
+        if needle.is_empty() {
+            self.search_results.clear();
+            return;
+        }
+        let fields = self.search_fields();
+        let matches = |i: &usize| {
+            fields
+                .iter()
+                .any(|&col| self.cell_value(*i, col).contains(needle))
+        };
+        self.search_results = if self.search_all {
+            (0..self.table_rows.len()).filter(matches).collect()
+        } else {
+            self.filtered_indices
+                .iter()
+                .copied()
+                .filter(matches)
+                .collect()
+        };
+        // keep original order so results read top to bottom in file order
+        self.search_results.sort_unstable();
+    }
+}
+
+// the history entry after `pos` going older or newer; None past the newest, back
+// at the text being typed
+fn history_step(len: usize, pos: Option<usize>, older: bool) -> Option<usize> {
+    match (pos, older) {
+        (None, true) => Some(len - 1),
+        (None, false) => None,
+        (Some(p), true) => Some(p.saturating_sub(1)),
+        (Some(p), false) if p + 1 < len => Some(p + 1),
+        (Some(_), false) => None,
+    }
+}
+
+// `s` without its first `cells` terminal cells
+fn skip_cells(s: &str, cells: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in s.char_indices() {
+        if used >= cells {
+            return &s[i..];
+        }
+        used += c.width().unwrap_or(0);
+    }
+    ""
+}
+
+// base qualities squeezed into `width` block characters, each the mean of its
+// share of the read (▁ for Q0 up to █ for Q40 and over), with the read's mean
+fn quality_sparkline(scores: &[u8], width: usize) -> (String, u8) {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let mean = |s: &[u8]| (s.iter().map(|&q| q as usize).sum::<usize>() / s.len().max(1)) as u8;
+    let width = width.min(scores.len());
+    let spark = (0..width)
+        .map(|b| {
+            let bin = &scores[b * scores.len() / width..(b + 1) * scores.len() / width];
+            BARS[(mean(bin) as usize * BARS.len() / 41).min(BARS.len() - 1)]
+        })
+        .collect();
+    (spark, mean(scores))
+}
+
+// shorten a value to the column width, marking the cut with an ellipsis; fit `s`
+// into `width` terminal cells, measuring display width so wide (CJK, emoji)
+// characters do not push the following columns out of line
+fn abbreviate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+// 123456789 as 123,456,789 (sign and decimals kept); None for anything that is
+// not a plain number
+fn group_thousands(s: &str) -> Option<String> {
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", s),
+    };
+    let (int, frac) = match rest.split_once('.') {
+        Some((int, frac)) => (int, Some(frac)),
+        None => (rest, None),
+    };
+    if int.is_empty()
+        || !int.bytes().all(|b| b.is_ascii_digit())
+        || !frac.is_none_or(|f| f.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+    let mut out = sign.to_string();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    if let Some(frac) = frac {
+        out.push('.');
+        out.push_str(frac);
+    }
+    Some(out)
+}
+
+fn is_tsv_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("tsv") || e.eq_ignore_ascii_case("txt"))
+}
+
+// .fq, .fastq and their .gz forms
+fn is_fastq_path(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    let path = path.strip_suffix(".gz").unwrap_or(&path);
+    path.ends_with(".fq") || path.ends_with(".fastq")
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
             Constraint::Percentage((100 - percent_x) / 2),
         ])
-        .split(popup_layout[1])[1]
+        .split(popup_layout[1])[1]
+}
+
+// stream through the input counting records that pass the filters, without keeping them
+fn count_records(path: &Path, filters: &filter::Filters, lenient: bool) -> Result<usize> {
+    let kind = FileKind::of(path);
+    let passes = |f: &Vec<String>| match kind {
+        FileKind::Sam => filters.passes(f),
+        FileKind::Vcf => filters.passes_vcf(f),
+    };
+    let bam = bam::is_bam(path);
+    let binary = bam || bcf::is_bcf(path);
+    if (vcf::is_gzipped(path) || binary) && bgzf::missing_eof_marker(path)? {
+        eprintln!(
+            "warning: {} appears truncated (no BGZF EOF marker), counting the records that could be read",
+            path.display()
+        );
+    }
+    if bam {
+        let parsed = bam::read(path, &sam::LineFormat::sam(lenient, None))?;
+        return Ok(parsed.rows.iter().filter(|f| passes(f)).count());
+    }
+    if binary {
+        return Ok(bcf::read(path)?.rows.iter().filter(|f| passes(f)).count());
+    }
+    if kind == FileKind::Vcf
+        && let Some(region) = &filters.region
+        && let Some(parsed) = vcf::read_region(path, region)?
+    {
+        return Ok(parsed.rows.iter().filter(|f| passes(f)).count());
+    }
+    let format = match kind {
+        FileKind::Sam => sam::LineFormat::sam(lenient, None),
+        FileKind::Vcf => vcf::FORMAT,
+    };
+    let mut count = 0;
+    let mut tally = |batch: sam::ParsedSam| {
+        count += batch.rows.iter().filter(|f| passes(f)).count();
+        true
+    };
+    if path == Path::new("-") {
+        sam::parse_chunks(io::stdin().lock(), STREAM_BATCH, &format, &mut tally)?;
+    } else {
+        sam::parse_chunks(vcf::open_text(path)?, STREAM_BATCH, &format, &mut tally)?;
+    }
+    Ok(count)
+}
+
+// parse a file without the TUI and report throughput, for profiling the loader;
+// timed on one thread, split across every core, then from a memory map
+fn bench_parse(path: &str, keep: Option<u32>) -> Result<()> {
+    let bytes = std::fs::metadata(path)?.len();
+    let format = sam::LineFormat::sam(false, None);
+    let start = Instant::now();
+    let file = BufReader::with_capacity(1 << 20, File::open(path)?);
+    let parsed = sam::parse_reader(file, &format)?;
+    let secs = start.elapsed().as_secs_f64();
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    let start = Instant::now();
+    let split = sam::parse_file(Path::new(path), cores, false, &format)?;
+    let split_secs = start.elapsed().as_secs_f64();
+    let start = Instant::now();
+    let mapped = sam::parse_file(Path::new(path), cores, true, &format)?;
+    let mapped_secs = start.elapsed().as_secs_f64();
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    println!("file:      {}", path);
+    println!("rows:      {}", parsed.rows.len());
+    println!("malformed: {}", parsed.malformed);
+    println!("size:      {:.2} MB", mb);
+    println!("parse:     {:.3} s", secs);
+    println!("speed:     {:.2} MB/s", mb / secs.max(f64::EPSILON));
+    println!("threads:   {}", cores);
+    println!("parallel:  {:.3} s", split_secs);
+    println!("speed:     {:.2} MB/s", mb / split_secs.max(f64::EPSILON));
+    println!("mmap:      {:.3} s", mapped_secs);
+    println!("speed:     {:.2} MB/s", mb / mapped_secs.max(f64::EPSILON));
+    for (other, how) in [(&split, "parallel"), (&mapped, "mmap")] {
+        if other.rows != parsed.rows || other.offsets != parsed.offsets {
+            color_eyre::eyre::bail!("{} parse differs from the serial one", how);
+        }
+    }
+    let held = parsed.heap_bytes() as f64 / (1024.0 * 1024.0);
+    println!("memory:    {:.2} MB", held);
+    // with --columns, the same file keeping only those fields
+    if let Some(keep) = keep {
+        let format = sam::LineFormat::sam(false, Some(keep));
+        let start = Instant::now();
+        let some = sam::parse_file(Path::new(path), cores, false, &format)?;
+        let some_secs = start.elapsed().as_secs_f64();
+        let some_held = some.heap_bytes() as f64 / (1024.0 * 1024.0);
+        println!("columns:   {:.3} s", some_secs);
+        println!("speed:     {:.2} MB/s", mb / some_secs.max(f64::EPSILON));
+        println!(
+            "memory:    {:.2} MB ({:.0}% of all columns)",
+            some_held,
+            100.0 * some_held / held.max(f64::EPSILON)
+        );
+        if some.rows.len() != parsed.rows.len() {
+            color_eyre::eyre::bail!("--columns parse found a different number of rows");
+        }
+    }
+    Ok(())
+}
+
+// whether setup turned mouse capture on, so teardown only undoes what it did
+static MOUSE_CAPTURED: AtomicBool = AtomicBool::new(false);
+
+// put the terminal back into cooked mode; safe to call more than once
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = leave_screen(&mut io::stdout());
+}
+
+// the escape codes that undo setup: mouse capture, the alternate screen and the
+// hidden cursor
+fn leave_screen(out: &mut impl Write) -> io::Result<()> {
+    if MOUSE_CAPTURED.swap(false, Ordering::Relaxed) {
+        execute!(out, DisableMouseCapture)?;
+    }
+    execute!(out, LeaveAlternateScreen, crossterm::cursor::Show)
+}
+
+type PanicHook = Box<dyn Fn(&std::panic::PanicHookInfo) + Send + Sync>;
+
+// run `restore` before `report`, so a panic anywhere (a key handler, drawing)
+// is printed on a usable terminal
+fn restoring_hook(restore: impl Fn() + Send + Sync + 'static, report: PanicHook) -> PanicHook {
+    Box::new(move |info| {
+        restore();
+        report(info);
+    })
+}
+
+// hand the terminal to $PAGER (less by default) reading `text`, then take it back
+fn show_in_pager(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    text: &str,
+) -> io::Result<()> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+    let mouse = MOUSE_CAPTURED.load(Ordering::Relaxed);
+    restore_terminal();
+    let shown = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                // quitting the pager before the end closes the pipe
+                if let Err(e) = stdin.write_all(text.as_bytes())
+                    && e.kind() != io::ErrorKind::BrokenPipe
+                {
+                    return Err(e);
+                }
+            }
+            child.wait().map(|_| ())
+        });
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    if mouse {
+        execute!(io::stdout(), EnableMouseCapture)?;
+        MOUSE_CAPTURED.store(true, Ordering::Relaxed);
+    }
+    terminal.clear()?;
+    shown.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", program, e)))
+}
+
+// color_eyre's panic report would otherwise be printed into the alternate
+// screen of a terminal still in raw mode
+fn install_hooks() -> Result<()> {
+    let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default().into_hooks();
+    eyre_hook.install()?;
+    std::panic::set_hook(restoring_hook(
+        restore_terminal,
+        panic_hook.into_panic_hook(),
+    ));
+    Ok(())
+}
+
+#[derive(Default)]
+struct Cli {
+    // file to open at startup, "-" for stdin; FIFOs are streamed like stdin
+    path: Option<PathBuf>,
+    // hidden developer mode, deliberately not advertised in the README
+    bench_parse: Option<String>,
+    // print the number of matching records and exit, like samtools view -c
+    count: bool,
+    lenient: bool,
+    // column names for / to search, from --search-col
+    search_columns: Vec<String>,
+    // SAM columns to parse and store, from --columns
+    columns: Option<String>,
+    // skip mouse capture for terminals and multiplexers that leak its escape codes
+    no_mouse: bool,
+    // without a file argument, open the startup directory's only supported file
+    auto_open: bool,
+    filters: filter::Filters,
+    // assembly name and FASTA path per --reference
+    references: Vec<(String, PathBuf)>,
+}
+
+// Region::parse takes anything it cannot split as a contig name; on the command
+// line a range that does not read as numbers is more likely a typo
+fn region_arg(what: &str, value: &str) -> Result<sam::Region> {
+    let bad = || {
+        color_eyre::eyre::eyre!(
+            "{} needs RNAME, RNAME:POS or RNAME:START-END, got {:?}",
+            what,
+            value
+        )
+    };
+    let region = sam::Region::parse(value).ok_or_else(bad)?;
+    if region.name.chars().any(char::is_whitespace) {
+        return Err(bad());
+    }
+    if let Some((_, range)) = value.trim().rsplit_once(':')
+        && range.starts_with(|c: char| c.is_ascii_digit())
+        && region.name == value.trim()
+    {
+        return Err(bad());
+    }
+    Ok(region)
+}
+
+fn parse_args() -> Result<Cli> {
+    let mut cli = Cli::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bench-parse" => {
+                let Some(path) = args.next() else {
+                    color_eyre::eyre::bail!("--bench-parse needs a file");
+                };
+                cli.bench_parse = Some(path);
+            }
+            "--columns" => {
+                let Some(value) = args.next() else {
+                    color_eyre::eyre::bail!("--columns needs a list such as QNAME,RNAME,POS");
+                };
+                cli.columns = Some(value);
+            }
+            "--count" | "-c" => cli.count = true,
+            "--lenient" => cli.lenient = true,
+            "--no-mouse" => cli.no_mouse = true,
+            "--auto-open" => cli.auto_open = true,
+            "--search-col" => {
+                let Some(value) = args.next() else {
+                    color_eyre::eyre::bail!("--search-col needs a column name");
+                };
+                cli.search_columns.extend(
+                    value
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty()),
+                );
+            }
+            "--min-mapq" | "-q" => {
+                let value = args.next().unwrap_or_default();
+                let Ok(min) = value.parse() else {
+                    color_eyre::eyre::bail!("{} needs a number, got {:?}", arg, value);
+                };
+                cli.filters.min_mapq = Some(min);
+            }
+            "--require-flags" | "-f" | "--exclude-flags" | "-F" => {
+                let value = args.next().unwrap_or_default();
+                let Some(flags) = filter::parse_flag(&value) else {
+                    color_eyre::eyre::bail!("{} needs a FLAG value, got {:?}", arg, value);
+                };
+                if arg == "-f" || arg == "--require-flags" {
+                    cli.filters.require_flags = flags;
+                } else {
+                    cli.filters.exclude_flags = flags;
+                }
+            }
+            "--reference" | "-R" => {
+                let Some(value) = args.next() else {
+                    color_eyre::eyre::bail!("{} needs [NAME=]FASTA", arg);
+                };
+                let (name, path) = match value.split_once('=') {
+                    Some((name, path)) => (name.to_string(), PathBuf::from(path)),
+                    None => {
+                        let path = PathBuf::from(&value);
+                        let stem = path.file_stem().unwrap_or_default();
+                        (stem.to_string_lossy().into_owned(), path)
+                    }
+                };
+                cli.references.push((name, path));
+            }
+            "--region" | "-r" => {
+                let value = args.next().unwrap_or_default();
+                cli.filters.region = Some(region_arg(&arg, &value)?);
+            }
+            "-" => cli.path = Some(PathBuf::from(arg)),
+            _ if arg.starts_with('-') => color_eyre::eyre::bail!("unknown option {}", arg),
+            // `varview file.sam chr1:1000-2000`, like samtools view
+            _ if cli.path.is_some() => {
+                if cli.filters.region.is_some() {
+                    color_eyre::eyre::bail!("only one region can be given, {:?} is extra", arg);
+                }
+                cli.filters.region = Some(region_arg("the region after the file", &arg)?);
+            }
+            _ => cli.path = Some(PathBuf::from(arg)),
+        }
+    }
+    Ok(cli)
+}
+
+fn main() -> Result<()> {
+    install_hooks()?;
+
+    let cli = parse_args()?;
+    let parse_columns = match cli.columns.as_deref().map(parse_column_mask) {
+        Some(Err(e)) => color_eyre::eyre::bail!("--columns: {}", e),
+        Some(Ok(mask)) => Some(mask),
+        None => None,
+    };
+    if let Some(path) = &cli.bench_parse {
+        return bench_parse(path, parse_columns);
+    }
+    if cli.count {
+        let Some(path) = &cli.path else {
+            color_eyre::eyre::bail!("--count needs a file or - for stdin");
+        };
+        println!("{}", count_records(path, &cli.filters, cli.lenient)?);
+        return Ok(());
+    }
+
+    let kind = cli.path.as_deref().map(FileKind::of).unwrap_or_default();
+    let columns = kind.columns();
+    for name in &cli.search_columns {
+        if !columns.iter().any(|c| c.eq_ignore_ascii_case(name)) {
+            color_eyre::eyre::bail!(
+                "--search-col: no column {} in {} files, use one of {}",
+                name,
+                kind.name(),
+                columns.join(",")
+            );
+        }
+    }
+
+    // read the FASTAs before taking over the terminal so a bad path is reported plainly
+    let mut references = Vec::new();
+    for (name, path) in &cli.references {
+        match reference::Reference::load(name, path) {
+            Ok(r) => references.push(r),
+            Err(e) => color_eyre::eyre::bail!("cannot read reference {}: {}", path.display(), e),
+        }
+    }
+
+    let mut stdout = io::stdout();
+    enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen)?;
+    if !cli.no_mouse && config::Config::load().mouse != Some(false) {
+        execute!(stdout, EnableMouseCapture)?;
+        MOUSE_CAPTURED.store(true, Ordering::Relaxed);
+    }
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // tear down on the error path too, not only after a clean quit
+    let result = run_app(&mut terminal, cli, references);
+    restore_terminal();
+    result
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    cli: Cli,
+    references: Vec<reference::Reference>,
+) -> Result<()> {
+    terminal.clear()?;
+
+    let mut app = App::new();
+    app.filters = cli.filters;
+    app.lenient = cli.lenient;
+    // --columns was checked in main
+    app.parse_columns = cli
+        .columns
+        .as_deref()
+        .and_then(|c| parse_column_mask(c).ok());
+    if app.parse_columns.is_none()
+        && let Some(list) = app.config.parse_columns.clone()
+    {
+        match parse_column_mask(&list) {
+            Ok(mask) => app.parse_columns = Some(mask),
+            Err(e) => app.notify(format!("parse_columns in the config: {}", e)),
+        }
+    }
+    app.references = references;
+    app.refresh_picker();
+    if let Some(path) = cli.path {
+        app.load_sam(path);
+    } else if cli.auto_open || app.config.auto_open {
+        match single_supported_file(&app.picker_path) {
+            Some(path) => app.load_sam(path),
+            None => app.picker_open = true,
+        }
+    }
+    // checked against the startup file's columns in main
+    app.search_columns = cli
+        .search_columns
+        .iter()
+        .filter_map(|name| {
+            app.columns()
+                .iter()
+                .position(|c| c.eq_ignore_ascii_case(name))
+        })
+        .collect();
+
+    let tick_rate = Duration::from_millis(250);
+    let mut last_tick = Instant::now();
+    // `event_loop = blocking` sleeps until something happens instead of waking
+    // every tick; the input thread then feeds keys through the loader channel
+    let input = (app.config.event_loop.as_deref() == Some("blocking"))
+        .then(|| app.loader_tx.clone().map(input::InputThread::spawn))
+        .flatten();
+
+    // only draw after something changed: a key, a resize, loader messages or an
+    // expired status message; an idle tick leaves the screen alone
+    let mut redraw = true;
+    loop {
+        if std::mem::take(&mut redraw) {
+            terminal.draw(|f| {
+                ui(f, &app);
+                theme::fit_colors(f.buffer_mut(), app.color_depth);
+            })?;
+        }
+
+        let key = if input.is_some() {
+            // each wakeup is a key, a loader message or an expiry
+            redraw = true;
+            app.wait_for_key()
+        } else {
+            let timeout = tick_rate.saturating_sub(last_tick.elapsed());
+            if event::poll(timeout)? {
+                match event::read()? {
+                    Event::Key(key) => Some(key),
+                    Event::Resize(..) => {
+                        redraw = true;
+                        None
+                    }
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        };
+        if let Some(key) = key {
+            redraw = true;
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            app.session_dirty = true;
+            app.last_key = Some(Instant::now());
+            // any key closes the load summary
+            if app.summary.take().is_some() {
+                continue;
+            }
+            if app.confirm_load.is_some() {
+                app.answer_large_file(key.code);
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') if !app.typing() => break,
+
+                // Open search modal
+                KeyCode::Char('/')
+                    if !app.picker_open && !app.typing() && app.header_view.is_none() =>
+                {
+                    app.search_open = true;
+                    app.search_input.clear();
+                    app.history_pos = None;
+                }
+
+                KeyCode::Tab if !app.typing() => {
+                    app.picker_open = !app.picker_open;
+                    if app.picker_open {
+                        app.refresh_picker();
+                    }
+                }
+
+                _ if app.picker_open => match key.code {
+                    KeyCode::Esc => app.picker_open = false,
+                    KeyCode::Up => {
+                        let i = app.picker_state.selected().unwrap_or(0);
+                        let i = i.saturating_sub(1);
+                        app.picker_state.select(Some(i));
+                    }
+                    KeyCode::Down => {
+                        let i = app.picker_state.selected().unwrap_or(0);
+                        let len = app.picker_entries.len();
+                        let i = if i + 1 >= len { 0 } else { i + 1 };
+                        app.picker_state.select(Some(i));
+                    }
+                    KeyCode::Enter => {
+                        if let Some(selected) = app
+                            .picker_state
+                            .selected()
+                            .and_then(|idx| app.picker_entries.get(idx))
+                        {
+                            let path = selected.path.clone();
+                            if selected.kind == EntryKind::Dir {
+                                app.picker_path = path;
+                                app.refresh_picker();
+                            } else {
+                                app.picker_open = false;
+                                app.load_sam(path);
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+
+                _ if !app.picker_open => match key.code {
+                    // Search modal handling
+                    _ if app.search_open => match key.code {
+                        KeyCode::Esc => app.search_open = false,
+                        KeyCode::Enter => {
+                            app.perform_search();
+                            app.search_open = false;
+                        }
+                        KeyCode::Up => app.browse_history(true),
+                        KeyCode::Down => app.browse_history(false),
+                        KeyCode::Backspace => {
+                            app.search_input.pop();
+                        }
+                        KeyCode::Char(c) => {
+                            app.search_input.push(c);
+                        }
+                        _ => {}
+                    },
+
+                    // the quick-filter bar narrows the view on every keystroke
+                    _ if app.quick_filter_open => match key.code {
+                        KeyCode::Enter => app.quick_filter_open = false,
+                        KeyCode::Esc => {
+                            app.quick_filter_open = false;
+                            app.filters.quick.clear();
+                            app.apply_filters();
+                        }
+                        KeyCode::Backspace => {
+                            app.filters.quick.pop();
+                            app.apply_filters();
+                        }
+                        KeyCode::Char(c) => {
+                            app.filters.quick.push(c);
+                            app.apply_filters();
+                        }
+                        _ => {}
+                    },
+
+                    _ if app.alignment_menu.is_some() => match key.code {
+                        KeyCode::Esc | KeyCode::Char('A') => app.alignment_menu = None,
+                        KeyCode::Enter => app.jump_to_alignment(),
+                        KeyCode::Up | KeyCode::Down => {
+                            if let Some((rows, state)) = app.alignment_menu.as_mut() {
+                                let i = state.selected().unwrap_or(0);
+                                let i = if key.code == KeyCode::Up {
+                                    i.saturating_sub(1)
+                                } else {
+                                    (i + 1).min(rows.len().saturating_sub(1))
+                                };
+                                state.select(Some(i));
+                            }
+                        }
+                        _ => {}
+                    },
+
+                    // typing a filter in the header popup
+                    _ if app.header_view.as_ref().is_some_and(|v| v.typing) => {
+                        if let Some(view) = app.header_view.as_mut() {
+                            match key.code {
+                                KeyCode::Esc | KeyCode::Enter => view.typing = false,
+                                KeyCode::Backspace => {
+                                    view.query.pop();
+                                }
+                                KeyCode::Char(c) => view.query.push(c),
+                                _ => {}
+                            }
+                            view.state.select(Some(0));
+                        }
+                    }
+
+                    _ if app.header_view.is_some() => match key.code {
+                        KeyCode::Esc | KeyCode::Char('H') => app.header_view = None,
+                        KeyCode::Char('/') => {
+                            if let Some(view) = app.header_view.as_mut() {
+                                view.typing = true;
+                            }
+                        }
+                        KeyCode::Left => app.cycle_header_type(false),
+                        KeyCode::Right => app.cycle_header_type(true),
+                        KeyCode::Up => app.scroll_header_view(-1),
+                        KeyCode::Down => app.scroll_header_view(1),
+                        KeyCode::PageUp => app.scroll_header_view(-20),
+                        KeyCode::PageDown => app.scroll_header_view(20),
+                        _ => {}
+                    },
+
+                    _ if app.results_menu.is_some() => match key.code {
+                        KeyCode::Esc | KeyCode::Char('s') => app.results_menu = None,
+                        KeyCode::Enter => app.jump_to_result(),
+                        KeyCode::Up => app.move_results_menu(-1),
+                        KeyCode::Down => app.move_results_menu(1),
+                        KeyCode::PageUp => app.move_results_menu(-20),
+                        KeyCode::PageDown => app.move_results_menu(20),
+                        KeyCode::Home => app.move_results_menu(isize::MIN / 2),
+                        KeyCode::End => app.move_results_menu(isize::MAX / 2),
+                        _ => {}
+                    },
+
+                    _ if app.contig_menu.is_some() => match key.code {
+                        KeyCode::Esc | KeyCode::Char('C') => app.contig_menu = None,
+                        KeyCode::Enter => app.jump_to_contig(),
+                        KeyCode::Up | KeyCode::Down => {
+                            let len = app.contig_counts.as_ref().map_or(0, |c| c.len());
+                            if let Some(state) = app.contig_menu.as_mut() {
+                                let i = state.selected().unwrap_or(0);
+                                let i = if key.code == KeyCode::Up {
+                                    i.saturating_sub(1)
+                                } else {
+                                    (i + 1).min(len.saturating_sub(1))
+                                };
+                                state.select(Some(i));
+                            }
+                        }
+                        _ => {}
+                    },
+
+                    _ if app.prompt.is_some() => match key.code {
+                        KeyCode::Esc => app.prompt = None,
+                        KeyCode::Enter => app.submit_prompt(),
+                        KeyCode::Up | KeyCode::Down
+                            if app
+                                .prompt
+                                .as_ref()
+                                .is_some_and(|p| matches!(p.kind, PromptKind::Region)) =>
+                        {
+                            app.browse_region_history(key.code == KeyCode::Up);
+                        }
+                        KeyCode::Backspace => {
+                            if let Some(p) = app.prompt.as_mut() {
+                                p.input.pop();
+                            }
+                        }
+                        KeyCode::Char(c) => {
+                            if let Some(p) = app.prompt.as_mut() {
+                                p.input.push(c);
+                            }
+                        }
+                        _ => {}
+                    },
+
+                    KeyCode::Up => {
+                        let i = app.table_state.selected().unwrap_or(0);
+                        app.table_state.select(Some(i.saturating_sub(1)));
+                    }
+                    KeyCode::Down => {
+                        let max = app.filtered_indices.len().saturating_sub(1);
+                        let i = match app.table_state.selected() {
+                            Some(i) if i < max => i + 1,
+                            Some(_) => max,
+                            None => 0,
+                        };
+                        app.table_state.select(Some(i));
+                    }
+                    KeyCode::Left if app.reorder_mode => app.move_focused_column(-1),
+                    KeyCode::Right if app.reorder_mode => app.move_focused_column(1),
+                    KeyCode::Char('c') | KeyCode::Esc if app.reorder_mode => {
+                        app.reorder_mode = false;
+                        app.save_column_order();
+                    }
+                    KeyCode::Char('c') if !app.resize_mode => app.reorder_mode = true,
+                    KeyCode::Char('+') | KeyCode::Right if app.resize_mode => {
+                        app.resize_focused_column(1)
+                    }
+                    KeyCode::Char('-') | KeyCode::Left if app.resize_mode => {
+                        app.resize_focused_column(-1)
+                    }
+                    KeyCode::Char('=') | KeyCode::Enter | KeyCode::Esc if app.resize_mode => {
+                        app.resize_mode = false;
+                        app.save_column_widths();
+                    }
+                    KeyCode::Char('=') if !app.reorder_mode => app.resize_mode = true,
+                    KeyCode::Char('[') => app.move_focus(-1),
+                    KeyCode::Char(']') => app.move_focus(1),
+                    // Shift+arrows move by a screen, plain arrows by `hscroll_step` cells
+                    KeyCode::Left | KeyCode::Right => {
+                        let step = if key.modifiers.contains(KeyModifiers::SHIFT) {
+                            crossterm::terminal::size().map_or(80, |(w, _)| w.saturating_sub(4))
+                        } else {
+                            app.config.hscroll_step.unwrap_or(DEFAULT_HSCROLL_STEP)
+                        };
+                        let step = step.max(1) as isize;
+                        app.scroll_table(if key.code == KeyCode::Left {
+                            -step
+                        } else {
+                            step
+                        });
+                    }
+                    KeyCode::Char('z') => app.dense = !app.dense,
+                    KeyCode::Char('N') => {
+                        app.row_numbers = match app.row_numbers {
+                            RowNumbers::Off => RowNumbers::Absolute,
+                            RowNumbers::Absolute => RowNumbers::Relative,
+                            RowNumbers::Relative => RowNumbers::Off,
+                        };
+                    }
+                    KeyCode::Char('S') => app.cycle_sort(),
+                    KeyCode::Char('y') => app.copy_column(),
+                    KeyCode::Char(c @ ('p' | 'P')) => {
+                        if let Some(text) = app.pager_text(c == 'P') {
+                            // the pager reads the keyboard itself
+                            if let Some(input) = &input {
+                                input.pause();
+                            }
+                            let shown = show_in_pager(terminal, &text);
+                            if let Some(input) = &input {
+                                input.resume();
+                            }
+                            if let Err(e) = shown {
+                                app.notify(format!("pager: {}", e));
+                            } else if app.fields_dropped() {
+                                app.notify("the pager showed only the fields kept by --columns");
+                            }
+                        }
+                    }
+                    KeyCode::Char('E') => {
+                        let stem = app
+                            .loaded_path
+                            .as_ref()
+                            .filter(|p| *p != Path::new("-"))
+                            .and_then(|p| p.file_name())
+                            .map(|n| n.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| "stdin".to_string());
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::ExportCsv,
+                            input: format!("{}.view.csv", stem),
+                        });
+                    }
+                    KeyCode::Char('U') if app.kind == FileKind::Sam => {
+                        let stem = app
+                            .loaded_path
+                            .as_ref()
+                            .filter(|p| *p != Path::new("-"))
+                            .and_then(|p| p.file_name())
+                            .map(|n| n.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| "stdin".to_string());
+                        // start from the selected read's position
+                        let site = app
+                            .selected_row()
+                            .map(|i| &app.table_rows[i])
+                            .filter(|f| f[2] != "*")
+                            .map(|f| format!("{}:{} ", f[2], f[3]))
+                            .unwrap_or_default();
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::ExportPileup,
+                            input: format!("{}{}.pileup.tsv", site, stem),
+                        });
+                    }
+                    KeyCode::Char('B') if app.fields_dropped() => {
+                        app.notify("a BAM needs every SAM field, and --columns left some out while loading");
+                    }
+                    KeyCode::Char('B') if app.kind == FileKind::Sam => {
+                        let stem = app
+                            .loaded_path
+                            .as_ref()
+                            .filter(|p| *p != Path::new("-"))
+                            .and_then(|p| p.file_stem())
+                            .map(|n| n.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| "stdin".to_string());
+                        // the region filter, else the selected read's span
+                        let region = app
+                            .filters
+                            .region
+                            .as_ref()
+                            .map(|r| format!("{} ", r))
+                            .or_else(|| {
+                                let f = &app.table_rows[app.selected_row()?];
+                                let start: u64 = f[3].parse().ok().filter(|_| f[2] != "*")?;
+                                let end = sam::reference_end(start, f);
+                                Some(format!("{}:{}-{} ", f[2], start, end))
+                            })
+                            .unwrap_or_default();
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::ExtractBam,
+                            input: format!("{}{}.region.bam", region, stem),
+                        });
+                    }
+                    KeyCode::Char('F') => app.quick_filter_open = true,
+                    KeyCode::Char('K') => app.toggle_pin(),
+                    KeyCode::Char('Q') => app.qual_sparkline = !app.qual_sparkline,
+                    KeyCode::Char('M') if app.kind == FileKind::Sam => app.cycle_unmapped(),
+                    KeyCode::Char('C') => app.open_contig_menu(),
+                    KeyCode::Char('s') => app.open_results_menu(),
+                    KeyCode::Char('H') => app.open_header_view(),
+                    KeyCode::Char('A') if app.kind == FileKind::Sam => app.open_alignment_menu(),
+                    KeyCode::Char('v') => app.toggle_split(),
+                    KeyCode::BackTab => app.switch_pane(),
+                    KeyCode::Char('n') => {
+                        if let Some(row) = app.selected_row() {
+                            app.prompt = Some(Prompt {
+                                kind: PromptKind::Note,
+                                input: app.notes.get(&row).cloned().unwrap_or_default(),
+                            });
+                        }
+                    }
+                    KeyCode::Char('d') => app.detail_open = !app.detail_open,
+                    KeyCode::Enter => app.enter_action(),
+                    KeyCode::Char('D') if app.kind == FileKind::Sam => {
+                        app.dotplot_open = !app.dotplot_open;
+                    }
+                    KeyCode::Esc if app.dotplot_open => app.dotplot_open = false,
+                    KeyCode::Char('w') if app.detail_open => {
+                        app.detail_nowrap = !app.detail_nowrap;
+                        app.detail_hscroll = 0;
+                    }
+                    KeyCode::Char('k') if app.detail_open => app.cigar_list = !app.cigar_list,
+                    KeyCode::Char('O') if app.detail_open && app.kind == FileKind::Sam => {
+                        app.mate_relative = !app.mate_relative;
+                    }
+                    KeyCode::Char('{') if app.detail_nowrap => {
+                        app.detail_hscroll = app.detail_hscroll.saturating_sub(10);
+                    }
+                    KeyCode::Char('}') if app.detail_nowrap => {
+                        app.detail_hscroll = app.detail_hscroll.saturating_add(10);
+                    }
+                    KeyCode::Char('b') => {
+                        app.show_offsets = !app.show_offsets;
+                        app.move_focus(0);
+                    }
+                    KeyCode::Char('I') if app.kind == FileKind::Sam => {
+                        app.show_tid = !app.show_tid;
+                        app.move_focus(0);
+                        if app.show_tid && app.ref_ids.is_empty() {
+                            app.notify("no @SQ lines in the header, every TID is .".to_string());
+                        }
+                    }
+                    KeyCode::Char('#') => {
+                        let msg = format!(
+                            "{} of {} records match the current filters",
+                            app.filtered_indices.len(),
+                            app.table_rows.len()
+                        );
+                        app.notify(msg);
+                    }
+                    KeyCode::Char('f') => app.toggle_search_column(),
+                    KeyCode::Char('a') => {
+                        app.search_all = !app.search_all;
+                        app.refresh_search();
+                    }
+                    KeyCode::Char('o') => {
+                        if let Some(dir) = app
+                            .loaded_path
+                            .as_ref()
+                            .and_then(|p| p.parent())
+                            .filter(|d| d.is_dir())
+                        {
+                            app.picker_path = dir.to_path_buf();
+                            app.picker_open = true;
+                            app.refresh_picker();
+                        }
+                    }
+                    KeyCode::Char('g') => {
+                        app.color_rows = !app.color_rows;
+                    }
+                    KeyCode::Char('Z') => app.stripes = !app.stripes,
+                    KeyCode::Char('m') => {
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::MinMapq,
+                            input: app
+                                .filters
+                                .min_mapq
+                                .map(|m| m.to_string())
+                                .unwrap_or_default(),
+                        });
+                    }
+                    KeyCode::Char('r') => {
+                        app.history_pos = None;
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::Region,
+                            input: app
+                                .filters
+                                .region
+                                .as_ref()
+                                .map(|r| r.to_string())
+                                .unwrap_or_default(),
+                        });
+                    }
+                    KeyCode::Char('t') if app.kind == FileKind::Vcf => {
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::VariantType,
+                            input: app.filters.variant_types.join(","),
+                        });
+                    }
+                    KeyCode::Char('u') if app.kind == FileKind::Sam => app.toggle_duplicates(),
+                    KeyCode::Char('J') if app.kind == FileKind::Sam => app.toggle_template(),
+                    KeyCode::Char('j') if app.kind == FileKind::Sam => app.toggle_split_reads(),
+                    KeyCode::Char('R') => {
+                        let input = match &app.derived {
+                            Some(derived) => derived.text(app.columns()),
+                            None => app
+                                .visible_columns()
+                                .get(app.focused_col)
+                                .map(|&c| format!("{} ", app.columns()[c]))
+                                .unwrap_or_default(),
+                        };
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::Derive,
+                            input,
+                        });
+                    }
+                    KeyCode::Char('e') => {
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::Expression,
+                            input: app
+                                .filters
+                                .expr
+                                .as_ref()
+                                .map(|e| e.text.clone())
+                                .unwrap_or_default(),
+                        });
+                    }
+                    KeyCode::Char('T') if app.kind == FileKind::Sam => {
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::Tag,
+                            input: app
+                                .filters
+                                .tag
+                                .as_ref()
+                                .map(|t| t.text())
+                                .unwrap_or_default(),
+                        });
+                    }
+                    KeyCode::Char('G') if app.kind == FileKind::Vcf => {
+                        app.prompt = Some(Prompt {
+                            kind: PromptKind::Genotype,
+                            input: app.genotype_filter_text(),
+                        });
+                    }
+                    KeyCode::Char('l') => app.filter_to_selected_locus(),
+                    KeyCode::Char('V') => app.toggle_variant_reads(),
+                    KeyCode::Char('?') => app.jump_to_random(),
+                    KeyCode::Char('!') => app.reseed(),
+                    KeyCode::Char('L') => {
+                        app.filters.region = None;
+                        app.filters.exclude_flags &= !app.filters.locus_flags;
+                        app.filters.locus_flags = 0;
+                        app.region_changed();
+                    }
+                    KeyCode::Char('x') => app.reset_view(),
+                    KeyCode::Char('X') => app.set_mark(),
+                    KeyCode::Char('W') => app.measure(),
+                    KeyCode::Char('Y') if app.saved_session.is_some() => app.restore_session(),
+                    KeyCode::Char('i') => {
+                        app.filter_inverted = !app.filter_inverted;
+                        app.apply_filters();
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if input.is_none() && last_tick.elapsed() >= tick_rate {
+            redraw |= app.recv();
+            redraw |= app.expire_messages();
+            last_tick = Instant::now();
+        }
+        if app.session_due() {
+            app.save_session();
+        }
+    }
+    app.save_session();
+
+    Ok(())
+}
+
+fn label(theme: &theme::Theme, name: &str) -> Span<'static> {
+    Span::styled(format!("{:<7}", name), theme.label)
+}
+
+// a header line's record type: the @ code in SAM (@SQ), the key of a ## line in
+// VCF (##INFO), else the line's first field (#CHROM)
+fn header_record_type(line: &str) -> &str {
+    let end = match line.strip_prefix("##") {
+        Some(rest) => rest.find('=').map_or(line.len(), |i| i + 2),
+        None => line.find('\t').unwrap_or(line.len()),
+    };
+    &line[..end]
+}
+
+// PAIR: the orientation of a paired read and its mate with TLEN's sign spelled
+// out; anything but an inward FR pair is flagged as a possible SV
+fn pair_line(fields: &[String], theme: &theme::Theme) -> Option<Line<'static>> {
+    let flag = fields[1].parse::<u16>().ok()?;
+    if flag & 0x1 == 0 {
+        return None;
+    }
+    let quiet = theme.muted;
+    let flagged = theme.error;
+    let (text, style) = if flag & 0x4 != 0 {
+        ("read unmapped".to_string(), quiet)
+    } else if flag & 0x8 != 0 {
+        ("mate unmapped".to_string(), quiet)
+    } else if let Some(orientation) = sam::pair_orientation(fields) {
+        let (arrows, note) = match orientation {
+            "FR" => ("→ ←", "inward facing"),
+            "RF" => ("← →", "outward facing, as at tandem duplications"),
+            "FF" => ("→ →", "same strand, as at inversions"),
+            _ => ("← ←", "same strand, as at inversions"),
+        };
+        let tlen = &fields[8];
+        let side = match tlen.parse::<i64>().unwrap_or(0).signum() {
+            1 => "this read leftmost",
+            -1 => "mate leftmost",
+            _ => "no insert size",
+        };
+        let text = format!(
+            "{}  {}  {}, TLEN {} ({})",
+            orientation, arrows, note, tlen, side
+        );
+        let style = if orientation == "FR" {
+            Style::default()
+        } else {
+            flagged
+        };
+        (text, style)
+    } else {
+        (format!("mate on {}, another contig", fields[6]), flagged)
+    };
+    Some(Line::from(vec![
+        label(theme, "PAIR"),
+        Span::styled(text, style),
+    ]))
+}
+
+// where the mate starts relative to this read, e.g. `+200 from POS (PNEXT 300)`;
+// None when the mate is on another contig or either position is unknown
+fn mate_offset(fields: &[String]) -> Option<String> {
+    if fields[6] != "=" && fields[6] != fields[2] {
+        return None;
+    }
+    let pos = fields[3].parse::<i64>().ok().filter(|&p| p > 0)?;
+    let pnext = fields[7].parse::<i64>().ok().filter(|&p| p > 0)?;
+    Some(format!("{:+} from POS (PNEXT {})", pnext - pos, pnext))
+}
+
+// fields of the selected record, one per line, for the detail pane
+// `width` is the pane's inner width when it wraps
+fn detail_lines(app: &App, width: Option<usize>) -> Vec<Line<'static>> {
+    let Some(row) = app.selected_row() else {
+        return vec![];
+    };
+    let theme = &app.theme;
+    let fields = &app.table_rows[row];
+    let note = app.notes.get(&row).map(|note| {
+        Line::from(vec![
+            label(theme, "NOTE"),
+            Span::styled(note.clone(), theme.note),
+        ])
+    });
+    if app.kind == FileKind::Vcf {
+        return note.into_iter().chain(variant_lines(app, row)).collect();
+    }
+    let mut lines: Vec<Line> = note
+        .into_iter()
+        .chain(
+            COLUMNS
+                .iter()
+                .zip(fields.iter())
+                .take(sam::SAM_MANDATORY)
+                .map(|(name, value)| {
+                    let value = match mate_offset(fields) {
+                        Some(offset) if *name == "PNEXT" && app.mate_relative => offset,
+                        _ if *name == "FLAG" => match value.parse::<u16>() {
+                            Ok(flag) if flag != 0 => format!(
+                                "{}  ({})",
+                                value,
+                                sam::flag_labels(flag, app.labels).join(", ")
+                            ),
+                            _ => value.clone(),
+                        },
+                        _ => value.clone(),
+                    };
+                    Line::from(vec![label(theme, name), Span::raw(value)])
+                }),
+        )
+        .collect();
+    for tag in fields.iter().skip(sam::SAM_MANDATORY) {
+        lines.push(Line::from(vec![
+            label(theme, "TAG"),
+            Span::raw(tag.clone()),
+        ]));
+    }
+    if let Some(ops) = sam::cigar_from_tag(fields) {
+        lines.push(Line::from(vec![
+            label(theme, "CIGAR"),
+            Span::styled(
+                format!(
+                    "{} operations from the CG tag, the CIGAR column is a placeholder",
+                    ops.len()
+                ),
+                theme.warning,
+            ),
+        ]));
+    }
+    lines.extend(pair_line(fields, theme));
+    if let Some(rows) = app.qname_rows.get(&fields[0])
+        && rows.len() > 1
+    {
+        let count = |kind| {
+            let n = rows
+                .iter()
+                .filter(|&&i| sam::alignment_kind(&app.table_rows[i]) == kind)
+                .count();
+            format!("{} {}", n, sam::alignment_kind_label(kind, app.labels))
+        };
+        lines.push(Line::from(vec![
+            label(theme, "ALIGNS"),
+            Span::raw(format!(
+                "{} records for this QNAME: {}, {}, {} (A to list)",
+                rows.len(),
+                count("primary"),
+                count("secondary"),
+                count("supplementary")
+            )),
+        ]));
+    }
+    let found = reference::find_contig(&app.references, &fields[2]);
+    lines.extend(quality_lines(fields, theme));
+    if app.cigar_list {
+        lines.extend(cigar_op_lines(fields, app.labels, theme));
+    } else {
+        lines.extend(cigar_block_lines(fields, theme));
+    }
+    let colors = app.config.base_colors != Some(false);
+    lines.extend(alignment_lines(
+        fields,
+        found.map(|(_, seq)| seq),
+        theme,
+        colors,
+        width,
+    ));
+    if let Some((r, _)) = found {
+        lines.push(Line::from(vec![
+            label(theme, "REFSRC"),
+            Span::raw(r.name.clone()),
+        ]));
+    } else if !app.references.is_empty() && fields[2] != "*" {
+        lines.push(Line::from(vec![
+            label(theme, "REFSRC"),
+            Span::styled(
+                format!("{} not found in any reference", fields[2]),
+                theme.muted,
+            ),
+        ]));
+    }
+    if app.out_of_bounds.contains(&row) {
+        lines.push(Line::from(Span::styled(
+            "WARNING alignment extends past the @SQ length of its contig",
+            theme.error,
+        )));
+    }
+    if let Some(offset) = app.row_offsets.get(row) {
+        lines.push(Line::from(vec![
+            label(theme, "OFFSET"),
+            Span::raw(format!("byte {}", offset)),
+        ]));
+    }
+    lines
+}
+
+// fields of the selected variant with one line per sample
+fn variant_lines(app: &App, row: usize) -> Vec<Line<'static>> {
+    let theme = &app.theme;
+    let fields = &app.table_rows[row];
+    let mut lines: Vec<Line> = vcf::COLUMNS
+        .iter()
+        .zip(fields.iter())
+        .take(vcf::SAMPLES_COLUMN)
+        .map(|(name, value)| Line::from(vec![label(theme, name), Span::raw(value.clone())]))
+        .collect();
+    lines.extend(structural_lines(fields, theme));
+    let names = vcf::sample_names(&app.header_lines);
+    let samples = fields.len().saturating_sub(vcf::SAMPLES_COLUMN);
+    for i in 0..samples {
+        let name = names
+            .get(i)
+            .cloned()
+            .unwrap_or_else(|| format!("S{}", i + 1));
+        let mut spans = vec![label(theme, &name)];
+        for (key, value) in vcf::sample_fields(fields, i) {
+            if key == "GT" {
+                let zygosity = vcf::zygosity(value);
+                let color = theme.genotypes[match zygosity {
+                    vcf::Zygosity::HomRef => 0,
+                    vcf::Zygosity::Het => 1,
+                    vcf::Zygosity::HomAlt | vcf::Zygosity::Haploid => 2,
+                    vcf::Zygosity::Missing => 3,
+                }];
+                spans.push(Span::styled(
+                    format!("{} ({})  ", value, zygosity.label()),
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
+                ));
+            } else {
+                spans.push(Span::raw(format!("{}={}  ", key, value)));
+            }
+        }
+        lines.push(Line::from(spans));
+    }
+    if let Some(offset) = app.row_offsets.get(row) {
+        lines.push(Line::from(vec![
+            label(theme, "OFFSET"),
+            Span::raw(offset.to_string()),
+        ]));
+    }
+    lines
+}
+
+// SV type and size from INFO (SVTYPE, SVLEN, END) or the symbolic ALT, and the
+// mate position of each breakend ALT
+fn structural_lines(fields: &[String], theme: &theme::Theme) -> Vec<Line<'static>> {
+    if vcf::variant_type(fields) != "SV" && vcf::info_field(fields, "SVTYPE").is_none() {
+        return vec![];
+    }
+    let alts: Vec<&str> = fields
+        .get(4)
+        .map(|a| a.split(',').collect())
+        .unwrap_or_default();
+    let symbolic = alts
+        .iter()
+        .find_map(|a| a.strip_prefix('<')?.strip_suffix('>'));
+    let sv_type = vcf::info_field(fields, "SVTYPE")
+        .or(symbolic)
+        .or_else(|| alts.iter().any(|a| vcf::is_breakend(a)).then_some("BND"));
+    let mut parts = vec![sv_type.unwrap_or("?").to_string()];
+    let pos = fields.get(1).and_then(|p| p.parse::<u64>().ok());
+    let end = vcf::info_field(fields, "END").and_then(|e| e.parse::<u64>().ok());
+    match vcf::info_field(fields, "SVLEN") {
+        // deletions carry a negative SVLEN
+        Some(len) => parts.push(format!("{} bp", len.replace('-', ""))),
+        None => {
+            if let (Some(pos), Some(end)) = (pos, end)
+                && end >= pos
+            {
+                parts.push(format!("{} bp", end - pos + 1));
+            }
+        }
+    }
+    if let Some(end) = end {
+        parts.push(format!("END {}", end));
+    }
+    let mut lines = vec![Line::from(vec![
+        label(theme, "SV"),
+        Span::styled(parts.join("  "), theme.structural),
+    ])];
+    for alt in alts.iter().filter(|a| vcf::is_breakend(a)) {
+        lines.push(Line::from(vec![
+            label(theme, "MATE"),
+            match vcf::parse_breakend(alt) {
+                Some(mate) => Span::raw(format!("{}:{}  {}", mate.chrom, mate.pos, mate.joining)),
+                None => Span::styled(format!("{} is not a valid breakend", alt), theme.error),
+            },
+        ]));
+    }
+    lines
+}
+
+// SEQ laid out along the reference under a coordinate ruler; with an MD tag the
+// reference rebuilt from it is shown above the read, mismatches highlighted, and
+// with a FASTA contig its bases are shown too, soft-masked ones dimmed. With a
+// `width` the tracks are cut into blocks that fit it, so wrapping cannot pull the
+// ruler and the bases apart
+fn alignment_lines(
+    fields: &[String],
+    contig: Option<&[u8]>,
+    theme: &theme::Theme,
+    colors: bool,
+    width: Option<usize>,
+) -> Vec<Line<'static>> {
+    let md = sam::find_tag(fields, "MD");
+    let pos = fields[3].parse::<u64>().unwrap_or(0);
+    let Some(cigar) = sam::record_cigar(fields) else {
+        return vec![];
+    };
+    if pos == 0 || fields[9] == "*" {
+        return vec![];
+    }
+    let Some(aligned) = align::align_read(&fields[9], &cigar, pos, md) else {
+        return vec![Line::from(vec![
+            label(theme, "ALIGN"),
+            Span::styled(
+                if md.is_some() {
+                    "MD does not fit SEQ/CIGAR"
+                } else {
+                    "CIGAR does not fit SEQ"
+                },
+                theme.error,
+            ),
+        ])];
+    };
+    // the label takes 7 cells of each line
+    let block = width.map_or(aligned.len(), |w| w.saturating_sub(7).max(10));
+    let ruler_style = theme.note;
+    let mut lines = Vec::new();
+    let mut masked = 0;
+    for part in aligned.chunks(block.max(1)) {
+        let (numbers, ticks) = align::ruler(part);
+        lines.push(Line::from(vec![
+            label(theme, "COORD"),
+            Span::styled(numbers, ruler_style),
+        ]));
+        lines.push(Line::from(vec![
+            label(theme, ""),
+            Span::styled(ticks, ruler_style),
+        ]));
+        if let Some(contig) = contig {
+            let (line, part_masked) = fasta_line(part, contig, theme, colors);
+            lines.push(line);
+            masked += part_masked;
+        }
+        let (read, reference) = base_tracks(part, theme, colors);
+        if md.is_some() {
+            lines.push(reference);
+        }
+        lines.push(read);
+    }
+    if masked > 0 {
+        lines.push(Line::from(vec![
+            label(theme, "MASKED"),
+            Span::styled(
+                format!("{} reference base(s) soft-masked (lowercase)", masked),
+                theme.muted,
+            ),
+        ]));
+    }
+    if md.is_none() {
+        return lines;
+    }
+    let count = |kind| aligned.iter().filter(|b| b.kind == kind).count();
+    lines.push(Line::from(vec![
+        label(theme, "MD"),
+        Span::raw(format!(
+            "{} mismatch(es), {} deleted base(s), {} inserted base(s)",
+            count(align::BaseKind::Mismatch),
+            count(align::BaseKind::Deletion),
+            count(align::BaseKind::Insertion)
+        )),
+    ]));
+    lines
+}
+
+// the READ and REF tracks of aligned bases, colored by kind
+fn base_tracks(
+    aligned: &[align::AlignedBase],
+    theme: &theme::Theme,
+    colors: bool,
+) -> (Line<'static>, Line<'static>) {
+    let mut read = vec![label(theme, "READ")];
+    let mut reference = vec![label(theme, "REF")];
+    for base in aligned {
+        let style = match base.kind {
+            align::BaseKind::Match => Style::default(),
+            align::BaseKind::Mismatch => theme.cigar_op('X'),
+            align::BaseKind::Insertion => theme.cigar_op('I'),
+            align::BaseKind::Deletion => theme.cigar_op('D'),
+            align::BaseKind::SoftClip => theme.cigar_op('S'),
+        };
+        let r = base.read.map(|b| b as char).unwrap_or('-');
+        let g = match (base.reference, base.kind) {
+            (Some(b), _) => b as char,
+            (None, align::BaseKind::SoftClip) => ' ',
+            (None, _) => '-',
+        };
+        // in the palette, mismatches are picked out in reverse video and inserted
+        // bases underlined; clipped bases and gaps keep their plain style
+        let paint = |c: char| match theme.base(c).filter(|_| colors) {
+            Some(color) => match base.kind {
+                align::BaseKind::Match | align::BaseKind::Deletion => Style::default().fg(color),
+                align::BaseKind::Mismatch => Style::default()
+                    .fg(color)
+                    .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                align::BaseKind::Insertion => Style::default()
+                    .fg(color)
+                    .add_modifier(Modifier::UNDERLINED),
+                align::BaseKind::SoftClip => style,
+            },
+            None => style,
+        };
+        read.push(Span::styled(r.to_string(), paint(r)));
+        reference.push(Span::styled(g.to_string(), paint(g)));
+    }
+    (Line::from(read), Line::from(reference))
+}
+
+// each base with its Phred quality written downwards beneath it (tens, then units),
+// in blocks short enough not to wrap; mismatches (from MD, else from X operations)
+// are underlined so low-quality mismatches stand out
+fn quality_lines(fields: &[String], theme: &theme::Theme) -> Vec<Line<'static>> {
+    const BLOCK: usize = 50;
+    let seq = fields[9].as_bytes();
+    let Some(quals) = sam::phred_scores(&fields[10]) else {
+        return vec![];
+    };
+    if fields[9] == "*" || quals.len() != seq.len() {
+        return vec![];
+    }
+    // whether each read base mismatches the reference, in query order
+    let mismatches: Vec<bool> = sam::record_cigar(fields)
+        .and_then(|cigar| {
+            let pos = fields[3].parse::<u64>().ok()?;
+            align::align_read(&fields[9], &cigar, pos, sam::find_tag(fields, "MD"))
+        })
+        .map(|aligned| {
+            aligned
+                .iter()
+                .filter(|b| b.read.is_some())
+                .map(|b| b.kind == align::BaseKind::Mismatch)
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut lines = Vec::new();
+    for start in (0..seq.len()).step_by(BLOCK) {
+        let end = (start + BLOCK).min(seq.len());
+        let mut bases = vec![label(theme, if start == 0 { "BASEQ" } else { "" })];
+        let mut tens = vec![Span::raw(format!("{:<7}", start + 1))];
+        let mut units = vec![label(theme, "")];
+        for i in start..end {
+            let q = quals[i];
+            let mut style = Style::default().fg(theme.quality(q));
+            if mismatches.get(i).copied().unwrap_or(false) {
+                style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+            }
+            bases.push(Span::styled((seq[i] as char).to_string(), style));
+            tens.push(Span::styled((q / 10).to_string(), style));
+            units.push(Span::styled((q % 10).to_string(), style));
+        }
+        lines.extend([Line::from(bases), Line::from(tens), Line::from(units)]);
+    }
+    lines
+}
+
+// SEQ split into its CIGAR operations, each block labelled with its op above it
+fn cigar_block_lines(fields: &[String], theme: &theme::Theme) -> Vec<Line<'static>> {
+    let Some(cigar) = sam::record_cigar(fields) else {
+        return vec![];
+    };
+    if fields[9] == "*" {
+        return vec![];
+    }
+    let Some(blocks) = align::cigar_blocks(&fields[9], &cigar) else {
+        return vec![];
+    };
+    let mut ops = vec![label(theme, "OPS")];
+    let mut seq = vec![label(theme, "BLOCKS")];
+    for block in blocks {
+        let style = theme.cigar_op(block.op);
+        let name = format!("{}{}", block.len, block.op);
+        let width = name.len().max(block.bases.len());
+        ops.push(Span::styled(
+            format!("{:<width$} ", name, width = width),
+            style,
+        ));
+        seq.push(Span::styled(
+            format!("{:<width$} ", block.bases, width = width),
+            style,
+        ));
+    }
+    vec![Line::from(ops), Line::from(seq)]
+}
+
+// one line per CIGAR operation with the 1-based query and reference ranges it
+// covers, e.g. "76M  query 1–76  ref 1000–1075"; "-" where it consumes neither
+fn cigar_op_lines(
+    fields: &[String],
+    labels: sam::Labels,
+    theme: &theme::Theme,
+) -> Vec<Line<'static>> {
+    let Some(cigar) = sam::record_cigar(fields) else {
+        return vec![];
+    };
+    let pos = fields[3].parse::<u64>().unwrap_or(0);
+    let span = |start: u64, len: u32, used: bool| {
+        if used && len > 0 {
+            format!("{}–{}", start, start + len as u64 - 1)
+        } else {
+            "-".to_string()
+        }
+    };
+    let (mut query, mut reference) = (1, pos);
+    let mut lines = Vec::new();
+    for (i, &(len, op)) in cigar.iter().enumerate() {
+        let on_query = matches!(op, 'M' | 'I' | 'S' | '=' | 'X');
+        let on_reference = pos > 0 && sam::consumes_reference(op);
+        let name = match labels {
+            sam::Labels::Long => format!("  {:<14}", sam::cigar_op_label(op, labels)),
+            sam::Labels::Short => String::new(),
+        };
+        lines.push(Line::from(vec![
+            label(theme, if i == 0 { "CIGAR" } else { "" }),
+            Span::styled(format!("{:>6}{}{}", len, op, name), theme.cigar_op(op)),
+            Span::raw(format!(
+                "  query {:<15} ref {}",
+                span(query, len, on_query),
+                span(reference, len, on_reference)
+            )),
+        ]));
+        if on_query {
+            query += len as u64;
+        }
+        if on_reference {
+            reference += len as u64;
+        }
+    }
+    lines
+}
+
+// the FASTA bases under an aligned read with how many are soft-masked; lowercase
+// is kept from the file and dimmed so reads sitting in repeat-masked sequence
+// stand out
+fn fasta_line(
+    aligned: &[align::AlignedBase],
+    contig: &[u8],
+    theme: &theme::Theme,
+    colors: bool,
+) -> (Line<'static>, usize) {
+    let mut spans = vec![label(theme, "FASTA")];
+    let mut masked = 0;
+    let plain = |b: u8| match theme.base(b as char).filter(|_| colors) {
+        Some(color) => Style::default().fg(color),
+        None => Style::default(),
+    };
+    for base in aligned {
+        let fasta = base
+            .ref_pos
+            .and_then(|p| contig.get(usize::try_from(p).ok()?.checked_sub(1)?));
+        let (c, style) = match (fasta, base.kind) {
+            (Some(&b), _) if b.is_ascii_lowercase() => {
+                masked += 1;
+                let style = if colors { plain(b) } else { theme.muted };
+                (b as char, style.add_modifier(Modifier::DIM))
+            }
+            (Some(&b), align::BaseKind::Match | align::BaseKind::Mismatch)
+                if base.read.is_some_and(|r| !r.eq_ignore_ascii_case(&b)) =>
+            {
+                let style = if colors {
+                    plain(b).add_modifier(Modifier::BOLD | Modifier::REVERSED)
+                } else {
+                    theme.error
+                };
+                (b as char, style)
+            }
+            (Some(&b), _) => (b as char, plain(b)),
+            (None, align::BaseKind::SoftClip | align::BaseKind::Insertion) => {
+                (' ', Style::default())
+            }
+            // past the end of the contig
+            (None, _) => ('?', theme.error),
+        };
+        spans.push(Span::styled(c.to_string(), style));
+    }
+    (Line::from(spans), masked)
 }
 
-fn main() -> Result<()> {
-    color_eyre::install()?;
+// less-style bottom line: mode, file, position, active filters and messages
+fn render_status_bar(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let file = match app.loaded_path.as_deref() {
+        Some(p) if p == Path::new("-") => "stdin".to_string(),
+        Some(p) => p
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        None => "no file".to_string(),
+    };
+    let mut info = format!(
+        " {} │ {} │ Row {}/{}  H-scroll: {}  {} match(es) in {}",
+        app.mode_name(),
+        file,
+        app.table_state.selected().map(|s| s + 1).unwrap_or(0),
+        app.filtered_indices.len(),
+        app.table_scroll.0,
+        app.search_results.len(),
+        if app.search_all { "all rows" } else { "view" }
+    );
+    if let Some(min) = app.filters.min_mapq {
+        info.push_str(&format!("  MAPQ>={}", min));
+    }
+    if let Some(region) = &app.filters.region {
+        info.push_str(&format!("  region {}", region));
+        if app.filters.split_reads && app.kind == FileKind::Sam {
+            info.push_str(" +SA");
+        }
+    }
+    if app.filters.genotype.is_some() {
+        info.push_str(&format!("  GT {}", app.genotype_filter_text()));
+    }
+    if let Some(tag) = &app.filters.tag {
+        info.push_str(&format!("  tag {}", tag.text()));
+    }
+    if app.filters.duplicates {
+        info.push_str("  duplicates");
+    }
+    if let Some(qname) = &app.filters.template {
+        info.push_str(&format!("  template {}", qname));
+    }
+    if let Some((contig, pos)) = &app.mark {
+        info.push_str(&format!("  mark {}:{}", contig, pos));
+    }
+    if let Some(progress) = &app.progress {
+        info.push_str(&format!("  {}", progress));
+    }
+    if let Some(expr) = &app.filters.expr {
+        info.push_str(&format!("  where {}", expr.text));
+    }
+    if app.kind == FileKind::Sam && app.unmapped != Unmapped::Inline {
+        info.push_str(&format!("  unmapped {}", app.unmapped.describe()));
+    }
+    if !app.filters.quick.is_empty() && !app.quick_filter_open {
+        info.push_str(&format!("  filter \"{}\"", app.filters.quick));
+    }
+    if !app.filters.variant_types.is_empty() {
+        info.push_str(&format!("  type {}", app.filters.variant_types.join(",")));
+    }
+    if app.filter_inverted {
+        info.push_str(" (inverted)");
+    }
+    if !app.out_of_bounds.is_empty() {
+        info.push_str(&format!(
+            "  ! {} read(s) past contig end",
+            app.out_of_bounds.len()
+        ));
+    }
+    if app.reorder_mode {
+        info.push_str("  [←/→ move column, c to finish]");
+    }
+    if app.resize_mode {
+        info.push_str("  [+/- or ←/→ resize column, = to finish]");
+    }
+    let mut spans = vec![Span::raw(info)];
+    if let Some(msg) = app.messages.front() {
+        spans.push(Span::raw(" │ "));
+        spans.push(Span::styled(
+            msg.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+    }
+    let bar =
+        Paragraph::new(Line::from(spans)).style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_widget(bar, area);
+}
 
-    let mut stdout = io::stdout();
-    enable_raw_mode()?;
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-    terminal.clear()?;
+// read (top to bottom) against the reference span of its alignment, soft clips
+// included (left to right), with a braille dot for every shared k-mer; indels show
+// as steps in the diagonal and repeats as parallel diagonals
+fn render_dotplot(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    f.render_widget(Clear, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().bg(app.theme.canvas));
+    let message = |text: &str| {
+        Paragraph::new(text.to_string())
+            .style(app.theme.muted)
+            .block(block.clone().title("Dotplot – D or Esc to close"))
+    };
+    let Some(fields) = app.selected_row().map(|i| &app.table_rows[i]) else {
+        return;
+    };
+    let Some((_, contig)) = reference::find_contig(&app.references, &fields[2]) else {
+        let text = if app.references.is_empty() {
+            "a dotplot needs a reference: start with -R ref.fa"
+        } else {
+            "the read's contig is in none of the references"
+        };
+        f.render_widget(message(text), area);
+        return;
+    };
+    let pos = fields[3].parse::<u64>().unwrap_or(0);
+    let cigar = sam::record_cigar(fields).unwrap_or_default();
+    if pos == 0 || fields[9] == "*" || cigar.is_empty() {
+        f.render_widget(message("the read is unmapped or has no SEQ"), area);
+        return;
+    }
+    let clip = |op: Option<&(u32, char)>| match op {
+        Some(&(len, 'S')) => len as u64,
+        _ => 0,
+    };
+    let start = (pos - 1).saturating_sub(clip(cigar.first()));
+    let end = (sam::reference_end(pos, fields) + clip(cigar.last())).min(contig.len() as u64);
+    let slice = contig.get(start as usize..end as usize).unwrap_or_default();
+    let read = fields[9].as_bytes();
+    let k = if read.len() < 50 { 4 } else { 8 };
+    let dots: Vec<(f64, f64)> = align::kmer_dots(read, slice, k)
+        .into_iter()
+        .map(|(i, j)| (j as f64, (read.len() - i) as f64))
+        .collect();
+    let title = format!(
+        "Dotplot {} vs {}:{}-{} – {}-mers, D or Esc to close",
+        fields[0],
+        fields[2],
+        start + 1,
+        end,
+        k
+    );
+    let canvas = Canvas::default()
+        .block(block.title(title))
+        .marker(symbols::Marker::Braille)
+        .x_bounds([0.0, slice.len() as f64])
+        .y_bounds([0.0, read.len() as f64])
+        .paint(|ctx| {
+            ctx.draw(&Points {
+                coords: &dots,
+                color: app.theme.dots,
+            });
+        });
+    f.render_widget(canvas, area);
+}
 
-    let mut app = App::new();
-    app.refresh_picker();
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Unmapped {
+    #[default]
+    Inline,
+    Last,
+    Hidden,
+}
 
-    let tick_rate = Duration::from_millis(250);
-    let mut last_tick = Instant::now();
+impl Unmapped {
+    // `unmapped = inline | last | hidden` in the config
+    fn parse(s: &str) -> Option<Unmapped> {
+        match s {
+            "inline" => Some(Unmapped::Inline),
+            "last" => Some(Unmapped::Last),
+            "hidden" => Some(Unmapped::Hidden),
+            _ => None,
+        }
+    }
 
-    loop {
-        terminal.draw(|f| ui(f, &app))?;
+    fn name(self) -> &'static str {
+        match self {
+            Unmapped::Inline => "inline",
+            Unmapped::Last => "last",
+            Unmapped::Hidden => "hidden",
+        }
+    }
 
-        let timeout = tick_rate.saturating_sub(last_tick.elapsed());
-        if event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind != KeyEventKind::Press {
-                    continue;
-                }
+    fn describe(self) -> &'static str {
+        match self {
+            Unmapped::Inline => "shown in place",
+            Unmapped::Last => "moved to the end",
+            Unmapped::Hidden => "hidden",
+        }
+    }
+}
 
-                match key.code {
-                    KeyCode::Char('q') => break,
+// what a table pane is showing: the focused view, the other pane of a split, or the
+// pinned rows above the view
+#[derive(Clone, Copy, PartialEq)]
+enum PaneRole {
+    Focused,
+    Other,
+    Pinned,
+}
 
-                    // Open search modal
-                    KeyCode::Char('/') if !app.picker_open && !app.search_open => {
-                        app.search_open = true;
-                        app.search_input.clear();
-                    }
+// one table pane over the shared rows; only the focused pane marks the focused
+// column and shows its selection in full color
+fn render_table(
+    f: &mut ratatui::Frame,
+    app: &App,
+    area: Rect,
+    indices: &[usize],
+    state: &TableState,
+    sort: Option<(usize, bool)>,
+    role: PaneRole,
+) {
+    let focused = role == PaneRole::Focused;
+    let (border, highlight) = if focused {
+        (Style::default(), app.theme.focused)
+    } else {
+        (app.theme.muted, app.theme.unfocused)
+    };
+    let marker = if app.dense { 1 } else { 3 };
+    let spacing = if app.dense { 0 } else { 1 };
+    // the number gutter stays put while the columns scroll sideways
+    let gutter = match app.row_numbers {
+        RowNumbers::Off => 0,
+        _ => app.table_rows.len().max(1).to_string().len() + spacing,
+    };
+    let room = (area.width as usize).saturating_sub(2 + marker + gutter);
+    let mut columns = app.scrolled_columns(room);
+    // once every column fits, `table_layout` decides what the rest of the row is for:
+    // fill widens SEQ (INFO in VCF mode, else the last column), center splits it
+    let used = columns.iter().map(|c| c.3 as usize).sum::<usize>()
+        + spacing * columns.len().saturating_sub(1);
+    let spare = room.saturating_sub(used);
+    let layout = app.config.table_layout.as_deref();
+    let mut fill = None;
+    if spare > 0 && layout == Some("fill") {
+        let wide = app.kind.wide_column();
+        let pos = columns.iter().position(|c| c.1 == wide);
+        if let Some(c) = pos
+            .or(columns.len().checked_sub(1))
+            .map(|p| &mut columns[p])
+        {
+            c.3 += spare as u16;
+            fill = Some((c.1, spare));
+        }
+    }
+    let selected = state.selected().unwrap_or(0);
+    let header_cells = columns.iter().map(|&(pos, col, cut, _)| {
+        let mut style = app.theme.label;
+        if focused && pos == app.focused_col {
+            style = if app.reorder_mode {
+                style.bg(app.theme.reordering)
+            } else if app.resize_mode {
+                style.bg(app.theme.resizing)
+            } else {
+                style.add_modifier(Modifier::UNDERLINED)
+            };
+        }
+        let name = app.columns()[col];
+        let label = match sort {
+            Some((c, descending)) if c == col => {
+                format!("{} {}", name, if descending { "▼" } else { "▲" })
+            }
+            _ => name.to_string(),
+        };
+        let label = Line::from(skip_cells(&label, cut).to_string());
+        Cell::from(if app.config.group_digits && app.kind.is_numeric(col) {
+            label.alignment(Alignment::Right)
+        } else {
+            label
+        })
+        .style(style)
+    });
 
-                    KeyCode::Tab => {
-                        app.picker_open = !app.picker_open;
-                        if app.picker_open {
-                            app.refresh_picker();
-                        }
-                    }
+    let header_cells = (gutter > 0)
+        .then(|| Cell::from(Line::from("#").alignment(Alignment::Right)))
+        .into_iter()
+        .chain(header_cells);
+    let header = Row::new(header_cells).style(app.theme.panel).height(1);
 
-                    _ if app.picker_open => match key.code {
-                        KeyCode::Esc => app.picker_open = false,
-                        KeyCode::Up => {
-                            let i = app.picker_state.selected().unwrap_or(0);
-                            let i = i.saturating_sub(1);
-                            app.picker_state.select(Some(i));
-                        }
-                        KeyCode::Down => {
-                            let i = app.picker_state.selected().unwrap_or(0);
-                            let len = app.picker_entries.len();
-                            let i = if i + 1 >= len { 0 } else { i + 1 };
-                            app.picker_state.select(Some(i));
-                        }
-                        KeyCode::Enter => {
-                            if let Some(idx) = app.picker_state.selected() {
-                                let selected = &app.picker_entries[idx];
-                                if selected.is_dir() {
-                                    app.picker_path = selected.clone();
-                                    app.refresh_picker();
-                                } else {
-                                    app.picker_open = false;
-                                    app.load_sam(selected.clone());
-                                }
-                            }
+    let stripe = app
+        .config
+        .stripe_color
+        .as_deref()
+        .and_then(|c| c.parse::<Color>().ok())
+        .unwrap_or(app.theme.stripe);
+    let rows: Vec<Row> = indices
+        .iter()
+        .enumerate()
+        .map(|(pos, &i)| {
+            let fields = &app.table_rows[i];
+            // the stripe is the base layer; search and selection highlights replace it
+            let mut style = Style::default();
+            if app.stripes && pos % 2 == 1 {
+                style = style.bg(stripe);
+            }
+            if app.color_rows
+                && let Some(color) = app.row_color(fields)
+            {
+                style = style.fg(color);
+            }
+            if app.search_results.binary_search(&i).is_ok() {
+                style = style.bg(app.theme.search_match);
+            }
+            let out_of_bounds = app.out_of_bounds.contains(&i);
+            if out_of_bounds {
+                style = style.patch(app.theme.error);
+            }
+            let cells = columns.iter().map(|&(pos, col, cut, _)| {
+                let mut value = app.shown_value(i, col);
+                if out_of_bounds && pos == 0 {
+                    value = Cow::Owned(format!("! {}", value));
+                }
+                if pos == 0 && app.notes.contains_key(&i) {
+                    value = Cow::Owned(format!("✎ {}", value));
+                }
+                let extra = fill
+                    .filter(|&(c, _)| c == col)
+                    .map_or(0, |(_, extra)| extra);
+                // Q: QUAL as a sparkline colored by the read's mean quality; blank for *
+                if app.qual_sparkline && app.kind == FileKind::Sam && col == QUAL_COLUMN {
+                    let width = app.column_width(col) as usize + extra;
+                    return match sam::phred_scores(&fields[QUAL_COLUMN]) {
+                        Some(scores) if !scores.is_empty() => {
+                            let (spark, mean) = quality_sparkline(&scores, width);
+                            Cell::from(skip_cells(&spark, cut).to_string())
+                                .style(Style::default().fg(app.theme.quality(mean)))
                         }
-                        _ => {}
-                    },
+                        _ => Cell::from(""),
+                    };
+                }
+                let value = abbreviate(&value, app.column_width(col) as usize + extra);
+                let line = Line::from(skip_cells(&value, cut).to_string());
+                Cell::from(if app.config.group_digits && app.kind.is_numeric(col) {
+                    line.alignment(Alignment::Right)
+                } else {
+                    line
+                })
+            });
+            let number = match app.row_numbers {
+                RowNumbers::Off => None,
+                RowNumbers::Absolute => Some(i + 1),
+                // vim style: the selected row keeps its own number
+                RowNumbers::Relative if pos == selected => Some(pos + 1),
+                RowNumbers::Relative => Some(pos.abs_diff(selected)),
+            };
+            let number = number.map(|n| {
+                Cell::from(Line::from(n.to_string()).alignment(Alignment::Right))
+                    .style(app.theme.muted)
+            });
+            Row::new(number.into_iter().chain(cells))
+                .style(style)
+                .height(1)
+        })
+        .collect();
 
-                    _ if !app.picker_open => match key.code {
-                        // Search modal handling
-                        _ if app.search_open => match key.code {
-                            KeyCode::Esc => app.search_open = false,
-                            KeyCode::Enter => {
-                                app.perform_search();
-                                app.search_open = false;
-                            }
-                            KeyCode::Backspace => {
-                                app.search_input.pop();
-                            }
-                            KeyCode::Char(c) => {
-                                app.search_input.push(c);
-                            }
-                            _ => {}
-                        },
+    let widths = (gutter > 0)
+        .then(|| Constraint::Length((gutter - spacing) as u16))
+        .into_iter()
+        .chain(columns.iter().map(|&(.., width)| Constraint::Length(width)))
+        .collect::<Vec<_>>();
 
-                        KeyCode::Up => {
-                            let i = app.table_state.selected().unwrap_or(0);
-                            app.table_state.select(Some(i.saturating_sub(1)));
-                        }
-                        KeyCode::Down => {
-                            let i = app.table_state.selected().unwrap_or(0);
-                            let max = app.table_rows.len().saturating_sub(1);
-                            let i = if i >= max { max } else { i + 1 };
-                            app.table_state.select(Some(i));
-                        }
-                        KeyCode::Left => {
-                            let (h, _) = app.table_scroll;
-                            app.table_scroll.0 = h.saturating_sub(5);
-                        }
-                        KeyCode::Right => {
-                            app.table_scroll.0 = app.table_scroll.0.saturating_add(5);
-                        }
-                        _ => {}
-                    },
-                    _ => {}
-                }
+    let block = Block::default()
+        .title(if role == PaneRole::Pinned {
+            format!(
+                "Pinned – {} record(s), K on a row pins or unpins it",
+                indices.len()
+            )
+        } else {
+            let kind = app.kind.name();
+            let mut title = format!("{} – {} rows", kind, app.table_rows.len());
+            if indices.len() != app.table_rows.len() {
+                title = format!(
+                    "{} – {} of {} rows",
+                    kind,
+                    indices.len(),
+                    app.table_rows.len()
+                );
             }
-        }
-        if last_tick.elapsed() >= tick_rate {
-            app.recv();
-            last_tick = Instant::now();
-        }
+            // a stream keeps counting up until its writer closes
+            if app.loading {
+                title.push_str(" (loading)");
+            }
+            if !app.read_groups.is_empty() {
+                title.push_str(&format!(" – {} read group(s)", app.read_groups.len()));
+            }
+            if app.malformed_lines > 0 {
+                title.push_str(&format!(" – {} malformed line(s)", app.malformed_lines));
+            }
+            if app.padded_lines > 0 {
+                title.push_str(&format!(" – {} padded line(s)", app.padded_lines));
+            }
+            if app.truncated {
+                title.push_str(" – truncated");
+            }
+            title
+        })
+        .borders(Borders::ALL)
+        .border_style(border);
+    let table = Table::new(rows, widths)
+        .header(header)
+        .highlight_style(Style::default().bg(highlight))
+        .highlight_symbol(if app.dense { ">" } else { ">> " })
+        // the pinned pane has no selection but keeps its columns under the view's
+        .highlight_spacing(HighlightSpacing::Always)
+        .column_spacing(if app.dense { 0 } else { 1 });
+
+    let mut table_state = state.clone();
+    let inner = block.inner(area);
+    let mut rows_area = inner;
+    if spare > 0 && layout == Some("center") {
+        // the border keeps the full width, the rows sit in the middle of it
+        rows_area = Rect {
+            x: inner.x + (spare / 2) as u16,
+            width: inner.width.saturating_sub(spare as u16),
+            ..inner
+        };
+        f.render_widget(block, area);
+        f.render_stateful_widget(table, rows_area, &mut table_state);
+    } else {
+        f.render_stateful_widget(table.block(block), area, &mut table_state);
     }
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-    Ok(())
+    // the selected row's focused cell, when cut short, in full just under it
+    if !focused || app.config.cell_tooltip == Some(false) {
+        return;
+    }
+    let Some(&row) = state.selected().and_then(|s| indices.get(s)) else {
+        return;
+    };
+    let Some(k) = columns.iter().position(|c| c.0 == app.focused_col) else {
+        return;
+    };
+    let (_, col, cut, width) = columns[k];
+    let value = app.shown_value(row, col);
+    if cut == 0 && value.width() <= width as usize {
+        return;
+    }
+    let x = rows_area.x as usize
+        + marker
+        + gutter
+        + columns[..k]
+            .iter()
+            .map(|c| c.3 as usize + spacing)
+            .sum::<usize>();
+    let line = (selected - table_state.offset()) as u16 + 1;
+    let y = if line + 1 < inner.height {
+        inner.y + line + 1
+    } else {
+        inner.y + line.saturating_sub(1)
+    };
+    let width = (value.width() + 2).min(inner.width as usize) as u16;
+    let x = (x as u16).min(inner.right().saturating_sub(width));
+    let tip = Rect {
+        x,
+        y,
+        width,
+        height: 1,
+    };
+    f.render_widget(Clear, tip);
+    f.render_widget(
+        Paragraph::new(format!(" {} ", abbreviate(&value, width as usize - 2)))
+            .style(app.theme.tooltip),
+        tip,
+    );
 }
 
 fn ui(f: &mut ratatui::Frame, app: &App) {
-    let area = f.area();
+    let bar = if app.quick_filter_open { 1 } else { 0 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(bar),
+            Constraint::Length(1),
+        ])
+        .split(f.area());
+    let area = chunks[0];
+    render_status_bar(f, app, chunks[2]);
+    if app.quick_filter_open {
+        let line = Line::from(vec![
+            Span::styled("filter ", app.theme.label),
+            Span::raw(format!("{}█", app.filters.quick)),
+            Span::styled(
+                format!(
+                    "  {} of {} rows, Enter keeps, Esc clears",
+                    app.filtered_indices.len(),
+                    app.table_rows.len()
+                ),
+                app.theme.muted,
+            ),
+        ]);
+        f.render_widget(Paragraph::new(line), chunks[1]);
+    }
 
-    // Main table
-    if !app.table_rows.is_empty() {
-        let header_cells = [
-            "QNAME", "FLAG", "RNAME", "POS", "MAPQ", "CIGAR", "RNEXT", "PNEXT", "TLEN", "SEQ",
-            "QUAL",
-        ]
-        .iter()
-        .map(|h| {
-            Cell::from(*h).style(
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
+    // Detail pane below the table
+    let (table_area, detail_area) = if app.detail_open && !app.table_rows.is_empty() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(area);
+        (chunks[0], Some(chunks[1]))
+    } else {
+        (area, None)
+    };
+    if let Some(detail_area) = detail_area {
+        let title = if app.detail_nowrap {
+            format!(
+                "Record – no wrap, {{ }} to scroll (col {})",
+                app.detail_hscroll
             )
-        });
+        } else {
+            "Record".to_string()
+        };
+        let width = (!app.detail_nowrap).then(|| detail_area.width.saturating_sub(2) as usize);
+        let mut detail = Paragraph::new(detail_lines(app, width))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        detail = if app.detail_nowrap {
+            detail.scroll((0, app.detail_hscroll))
+        } else {
+            detail.wrap(Wrap { trim: false })
+        };
+        f.render_widget(detail, detail_area);
+    }
 
-        let header = Row::new(header_cells)
-            .style(Style::default().bg(Color::DarkGray))
-            .height(1);
+    // Pinned rows stay above the view, column-aligned with it
+    let table_area = if app.pinned.is_empty() || app.table_rows.is_empty() {
+        table_area
+    } else {
+        let height = (app.pinned.len() as u16 + 3).min(table_area.height / 2);
+        let parts = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(height), Constraint::Min(0)])
+            .split(table_area);
+        render_table(
+            f,
+            app,
+            parts[0],
+            &app.pinned,
+            &TableState::default(),
+            None,
+            PaneRole::Pinned,
+        );
+        parts[1]
+    };
 
-        let rows: Vec<Row> = app
-            .table_rows
-            .iter()
-            .enumerate()
-            .map(|(i, fields)| {
-                let style = if app.search_results.contains(&i) {
-                    Style::default().bg(Color::LightGreen)
+    // Main table, or two panes over the same rows when split
+    if !app.table_rows.is_empty() {
+        match &app.split {
+            Some(other) => {
+                let halves = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(table_area);
+                let (active, inactive) = if app.split_bottom {
+                    (halves[1], halves[0])
                 } else {
-                    Style::default()
+                    (halves[0], halves[1])
                 };
-                Row::new(fields.iter().take(11).map(|s| Cell::from(s.clone())))
-                    .style(style)
-                    .height(1)
-            })
-            .collect();
-
-        let widths = (0..11).map(|_| Constraint::Length(12)).collect::<Vec<_>>();
-
-        let table = Table::new(rows, widths)
-            .header(header)
-            .block(
-                Block::default()
-                    .title(format!("SAM – {} rows", app.table_rows.len()))
-                    .borders(Borders::ALL),
-            )
-            .highlight_style(Style::default().bg(Color::LightBlue))
-            .highlight_symbol(">> ")
-            .column_spacing(1);
-
-        let mut table_state = app.table_state.clone();
-        f.render_stateful_widget(table, area, &mut table_state);
-
-        // Info bar
-        let info = format!(
-            "Row {}/{}  H-scroll: {}  {} match(es)",
-            app.table_state.selected().map(|s| s + 1).unwrap_or(0),
-            app.table_rows.len(),
-            app.table_scroll.0,
-            app.search_results.len()
-        );
-        let info_par = Paragraph::new(info).style(Style::default().fg(Color::Cyan));
-        let info_area = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(100)])
-            .split(area)[0];
-        f.render_widget(
-            info_par,
-            Rect {
-                y: info_area.y,
-                ..info_area
-            },
-        );
+                render_table(
+                    f,
+                    app,
+                    active,
+                    &app.filtered_indices,
+                    &app.table_state,
+                    app.sort,
+                    PaneRole::Focused,
+                );
+                render_table(
+                    f,
+                    app,
+                    inactive,
+                    &other.filtered_indices,
+                    &other.table_state,
+                    other.sort,
+                    PaneRole::Other,
+                );
+            }
+            None => render_table(
+                f,
+                app,
+                table_area,
+                &app.filtered_indices,
+                &app.table_state,
+                app.sort,
+                PaneRole::Focused,
+            ),
+        }
     } else {
         let placeholder = Paragraph::new("No file loaded – press <Tab> to open file picker")
-            .style(Style::default().fg(Color::DarkGray))
+            .style(app.theme.muted)
             .block(Block::default().borders(Borders::ALL).title("SAM Viewer"));
         f.render_widget(placeholder, area);
     }
@@ -416,31 +5504,275 @@ fn ui(f: &mut ratatui::Frame, app: &App) {
         });
 
         let title = Block::default()
-            .title(format!("File Picker – {}", app.picker_path.display()))
+            .title(format!(
+                "File Picker – {}{}",
+                app.picker_path.display(),
+                if app.picker_scanning {
+                    format!(" – scanning… {} found", app.picker_entries.len())
+                } else {
+                    String::new()
+                }
+            ))
             .borders(Borders::ALL)
-            .style(Style::default().bg(Color::DarkGray));
+            .style(app.theme.panel);
 
+        let parent = app.picker_path.parent();
         let list_items: Vec<ListItem> = app
             .picker_entries
             .iter()
-            .map(|p| {
-                let name = p.file_name().unwrap_or_default().to_string_lossy();
-                let prefix = if p.is_dir() { "[DIR] " } else { "      " };
-                ListItem::new(Line::from(format!("{}{}", prefix, name)))
+            .map(|entry| {
+                let p = &entry.path;
+                let name = if Some(p.as_path()) == parent {
+                    Cow::Borrowed("..")
+                } else {
+                    p.file_name().unwrap_or_default().to_string_lossy()
+                };
+                let is_dir = entry.kind == EntryKind::Dir;
+                let prefix = if is_dir { "[DIR] " } else { "      " };
+                // the prefix stays for terminals without color
+                let style = if is_dir {
+                    app.theme.directory
+                } else if entry.kind == EntryKind::Fifo {
+                    app.theme.fifo
+                } else if vcf::is_vcf(p) {
+                    app.theme.variants
+                } else {
+                    Style::default()
+                };
+                ListItem::new(Line::from(format!("{}{}", prefix, name))).style(style)
             })
             .collect();
 
         let list = List::new(list_items)
             .block(title)
-            .highlight_style(Style::default().bg(Color::Yellow))
+            .highlight_style(app.theme.highlight)
             .highlight_symbol(symbols::block::FULL);
 
         let mut list_state = app.picker_state.clone();
         f.render_stateful_widget(list, inner, &mut list_state);
     }
 
-    // Search modal
-    if app.search_open {
+    if app.dotplot_open && app.kind == FileKind::Sam {
+        render_dotplot(f, app, centered_rect(80, 80, area));
+    }
+
+    // confirmation before reading a very large file into memory
+    if let Some((path, size)) = &app.confirm_load {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mb = size / (1024 * 1024);
+        let size = if mb >= 1024 {
+            format!("{:.1} GB", mb as f64 / 1024.0)
+        } else {
+            format!("{} MB", mb)
+        };
+        let stream = if vcf::is_gzipped(path) {
+            format!("zcat {} | head -n 1000000 | varview -", name)
+        } else {
+            format!("head -n 1000000 {} | varview -", name)
+        };
+        let text = vec![
+            Line::from(format!(
+                "{} is {} and is read whole into memory.",
+                name, size
+            )),
+            Line::from("y loads it, any other key leaves it closed."),
+            Line::from(""),
+            Line::from(format!(
+                "To look at part of it, stream the start instead: {}",
+                stream
+            )),
+        ];
+        let height = text.len() as u16 + 2;
+        let popup = centered_rect(70, 100, area);
+        let popup = Rect {
+            y: area.y + area.height.saturating_sub(height) / 2,
+            height: height.min(area.height),
+            ..popup
+        };
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Large file")
+                    .style(app.theme.panel),
+            ),
+            popup,
+        );
+    }
+
+    // Load summary popup
+    if let Some(summary) = &app.summary {
+        let height = summary.len() as u16 + 2;
+        let popup = centered_rect(40, 100, area);
+        let popup = Rect {
+            y: area.y + area.height.saturating_sub(height) / 2,
+            height: height.min(area.height),
+            ..popup
+        };
+        f.render_widget(Clear, popup);
+        let name = app
+            .loaded_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let text: Vec<Line> = summary.iter().map(|l| Line::from(l.as_str())).collect();
+        f.render_widget(
+            Paragraph::new(text).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("{} – any key to close", name))
+                    .style(app.theme.panel),
+            ),
+            popup,
+        );
+    }
+
+    // Alignments of the selected read's QNAME
+    if let Some((rows, state)) = &app.alignment_menu {
+        let popup = centered_rect(60, 50, area);
+        f.render_widget(Clear, popup);
+        let items: Vec<ListItem> = rows
+            .iter()
+            .map(|&i| {
+                let fields = &app.table_rows[i];
+                ListItem::new(format!(
+                    "{:<14} FLAG {:<5} {}:{}  {}  MAPQ {}",
+                    sam::alignment_kind_label(sam::alignment_kind(fields), app.labels),
+                    fields[1],
+                    fields[2],
+                    fields[3],
+                    fields[5],
+                    fields[4]
+                ))
+            })
+            .collect();
+        let qname = rows
+            .first()
+            .map(|&i| app.table_rows[i][0].as_str())
+            .unwrap_or("");
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title(format!(
+                        "Alignments of {} – {} record(s), Enter to jump",
+                        qname,
+                        rows.len()
+                    ))
+                    .borders(Borders::ALL)
+                    .style(app.theme.panel),
+            )
+            .highlight_style(app.theme.highlight)
+            .highlight_symbol(symbols::block::FULL);
+        f.render_stateful_widget(list, popup, &mut state.clone());
+    }
+
+    // Search matches
+    if let Some((outside, state)) = &app.results_menu {
+        let popup = centered_rect(70, 60, area);
+        f.render_widget(Clear, popup);
+        let number_width = app.table_rows.len().to_string().len();
+        let items: Vec<ListItem> = app
+            .search_results
+            .iter()
+            .map(|&i| {
+                // matches outside the view (searching all rows) are dimmed
+                let style = if outside.contains(&i) {
+                    app.theme.outside
+                } else {
+                    Style::default()
+                };
+                ListItem::new(format!(
+                    "{:>width$}  {}",
+                    i + 1,
+                    app.row_summary(i),
+                    width = number_width
+                ))
+                .style(style)
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title(format!(
+                        "{} match(es) for {} – Enter to jump, Esc to close",
+                        app.search_results.len(),
+                        app.search_query
+                    ))
+                    .borders(Borders::ALL)
+                    .style(app.theme.panel),
+            )
+            .highlight_style(app.theme.highlight);
+        f.render_stateful_widget(list, popup, &mut state.clone());
+    }
+
+    // Header lines, filtered by record type and text
+    if let Some(view) = &app.header_view {
+        let popup = centered_rect(80, 70, area);
+        f.render_widget(Clear, popup);
+        let lines = app.header_view_lines();
+        let items: Vec<ListItem> = lines
+            .iter()
+            .map(|l| ListItem::new(l.replace('\t', "  ")))
+            .collect();
+        let kind = view.kind.map_or("all", |k| view.types[k].as_str());
+        let mut title = format!(
+            "Header – {} – {} of {} line(s), ←/→ type, / filter",
+            kind,
+            lines.len(),
+            app.header_lines.len()
+        );
+        if view.typing || !view.query.is_empty() {
+            title.push_str(&format!(" – /{}", view.query));
+            if view.typing {
+                title.push('█');
+            }
+        }
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .style(app.theme.panel),
+            )
+            .highlight_style(app.theme.highlight);
+        f.render_stateful_widget(list, popup, &mut view.state.clone());
+    }
+
+    // Contig jump menu
+    if let (Some(state), Some(counts)) = (&app.contig_menu, &app.contig_counts) {
+        let popup = centered_rect(40, 60, area);
+        f.render_widget(Clear, popup);
+        let name_width = counts.iter().map(|(n, _)| n.width()).max().unwrap_or(0);
+        let items: Vec<ListItem> = counts
+            .iter()
+            .map(|(name, count)| {
+                ListItem::new(format!(
+                    "{}{} {:>9}",
+                    name,
+                    " ".repeat(name_width - name.width()),
+                    count
+                ))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title(format!(
+                        "Jump to contig – {} contig(s), Enter to jump",
+                        counts.len()
+                    ))
+                    .borders(Borders::ALL)
+                    .style(app.theme.panel),
+            )
+            .highlight_style(app.theme.highlight)
+            .highlight_symbol(symbols::block::FULL);
+        f.render_stateful_widget(list, popup, &mut state.clone());
+    }
+
+    // Filter prompt modal
+    if let Some(prompt) = &app.prompt {
         let popup = centered_rect(60, 20, area);
         f.render_widget(Clear, popup);
 
@@ -450,17 +5782,45 @@ fn ui(f: &mut ratatui::Frame, app: &App) {
             .constraints([Constraint::Length(3), Constraint::Min(0)])
             .split(popup);
 
-        let input = Paragraph::new(format!("QNAME: {}", app.search_input))
-            .style(Style::default().fg(Color::Yellow))
+        let input = Paragraph::new(prompt.input.as_str())
+            .style(app.theme.input)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Search QNAME (Enter to confirm, Esc to cancel)"),
+                    .title(prompt.kind.title()),
             );
         f.render_widget(input, chunks[0]);
 
+        let cursor_x = chunks[0].x + 1 + UnicodeWidthStr::width(prompt.input.as_str()) as u16;
+        f.set_cursor_position((cursor_x, chunks[0].y + 1));
+    }
+
+    // Search modal
+    if app.search_open {
+        let popup = centered_rect(60, 20, area);
+        f.render_widget(Clear, popup);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(popup);
+
+        let columns = app.search_label();
+        let prefix = format!("{}: ", columns);
+        let input = Paragraph::new(format!("{}{}", prefix, app.search_input))
+            .style(app.theme.input)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "Search {} in {} (Enter to confirm, Esc to cancel)",
+                columns,
+                if app.search_all { "all rows" } else { "view" }
+            )));
+        f.render_widget(input, chunks[0]);
+
         // Cursor position
-        let cursor_x = chunks[0].x + 8 + UnicodeWidthStr::width(app.search_input.as_str()) as u16;
+        let cursor_x = chunks[0].x
+            + 1
+            + UnicodeWidthStr::width(format!("{}{}", prefix, app.search_input).as_str()) as u16;
         let cursor_y = chunks[0].y + 1;
         f.set_cursor_position((cursor_x, cursor_y));
 
@@ -475,8 +5835,105 @@ fn ui(f: &mut ratatui::Frame, app: &App) {
                     app.search_results[0] + 1
                 )
             };
-            let preview = Paragraph::new(preview_text).style(Style::default().fg(Color::Green));
+            let preview = Paragraph::new(preview_text).style(app.theme.success);
             f.render_widget(preview, chunks[1]);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn record(qname: &str, pos: u32, mapq: u32) -> Vec<String> {
+        format!("{qname}\t0\tchr1\t{pos}\t{mapq}\t4M\t*\t0\t0\tACGT\tIIII")
+            .split('\t')
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    // an app holding reads with these MAPQs, all in view, the row at `selected` picked
+    fn app_with(mapqs: &[u32], selected: usize) -> App {
+        let mut app = App {
+            table_rows: mapqs
+                .iter()
+                .enumerate()
+                .map(|(i, &mapq)| record(&format!("r{i}"), 100 + i as u32, mapq))
+                .collect(),
+            ..App::default()
+        };
+        app.apply_filters();
+        app.table_state.select(Some(selected));
+        app
+    }
+
+    #[test]
+    fn filtering_out_the_selected_row_selects_the_next_survivor() {
+        let mut app = app_with(&[10, 50, 20, 60, 5], 2);
+        app.filters.min_mapq = Some(30);
+        app.apply_filters();
+        assert_eq!(app.filtered_indices, vec![1, 3]);
+        assert_eq!(app.selected_row(), Some(3));
+
+        // past the last survivor the selection stays on the last row
+        let mut app = app_with(&[60, 10, 5], 2);
+        app.filters.min_mapq = Some(30);
+        app.apply_filters();
+        assert_eq!(app.filtered_indices, vec![0]);
+        assert_eq!(app.table_state.selected(), Some(0));
+        assert_eq!(app.selected_row(), Some(0));
+    }
+
+    #[test]
+    fn a_surviving_selected_row_stays_selected() {
+        let mut app = app_with(&[10, 50, 20, 60, 5], 3);
+        app.filters.min_mapq = Some(30);
+        app.apply_filters();
+        assert_eq!(app.selected_row(), Some(3));
+        assert_eq!(app.table_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn an_empty_view_selects_nothing_until_rows_come_back() {
+        let mut app = app_with(&[10, 50, 20], 1);
+        app.filters.min_mapq = Some(100);
+        app.apply_filters();
+        assert!(app.filtered_indices.is_empty());
+        assert_eq!(app.table_state.selected(), None);
+        assert_eq!(app.selected_row(), None);
+
+        app.filters.min_mapq = Some(15);
+        app.apply_filters();
+        let pos = app.table_state.selected().unwrap();
+        assert!(pos < app.filtered_indices.len());
+    }
+
+    #[test]
+    fn panic_in_a_key_handler_restores_the_terminal() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let out = Arc::clone(&written);
+        let hook = restoring_hook(
+            move || {
+                let _ = leave_screen(&mut *out.lock().unwrap());
+            },
+            Box::new(|_| {}),
+        );
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(hook);
+        MOUSE_CAPTURED.store(true, Ordering::Relaxed);
+        let app = App::default();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            // a key handler indexing past the rows of an empty table
+            let _ = &app.table_rows[app.table_state.selected().unwrap_or(0)];
+        }));
+        std::panic::set_hook(previous);
+        assert!(result.is_err());
+
+        let written = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        assert!(written.contains("\x1b[?1000l"), "mouse capture left on");
+        assert!(written.contains("\x1b[?1049l"), "alternate screen left on");
+        assert!(written.contains("\x1b[?25h"), "cursor left hidden");
+        assert!(!MOUSE_CAPTURED.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,56 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// review notes live next to the data file as FILE.notes, one
+// `offset<TAB>record<TAB>note` line each; the byte offset ties a note to its record
+// across reloads, the record name is only there for people reading the file
+pub fn sidecar(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".notes");
+    PathBuf::from(name)
+}
+
+// notes by record offset; a missing sidecar means no notes
+pub fn load(path: &Path) -> HashMap<u64, String> {
+    let Ok(text) = fs::read_to_string(sidecar(path)) else {
+        return HashMap::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, '\t');
+            let offset = parts.next()?.parse().ok()?;
+            let _record = parts.next()?;
+            Some((offset, parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+// rewrite the sidecar from (offset, record, note) entries, removing it once empty
+pub fn save(path: &Path, notes: &[(u64, String, &str)]) -> io::Result<()> {
+    let sidecar = sidecar(path);
+    if notes.is_empty() {
+        return match fs::remove_file(&sidecar) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let mut out = fs::File::create(sidecar)?;
+    for (offset, record, note) in notes {
+        writeln!(out, "{}\t{}\t{}", offset, clean(record), clean(note))?;
+    }
+    Ok(())
+}
+
+// tabs and line breaks would split a line of the sidecar
+fn clean(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
@@ -0,0 +1,81 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+// contigs of a FASTA file kept in memory; case is preserved so soft-masked
+// (lowercase) repeat regions can be told apart
+#[derive(Default)]
+pub struct Reference {
+    // assembly name, from `--reference NAME=FILE` or the file stem
+    pub name: String,
+    pub contigs: HashMap<String, Vec<u8>>,
+}
+
+impl Reference {
+    pub fn load(name: &str, path: &Path) -> io::Result<Reference> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut reference = Reference {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut name: Option<String> = None;
+        let mut seq = Vec::new();
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            while matches!(buf.last(), Some(b'\n' | b'\r')) {
+                buf.pop();
+            }
+            if let Some(header) = buf.strip_prefix(b">") {
+                if let Some(done) = name.take() {
+                    reference.contigs.insert(done, std::mem::take(&mut seq));
+                }
+                // the name ends at the first whitespace, as in samtools faidx
+                let header = String::from_utf8_lossy(header);
+                name = header.split_whitespace().next().map(|s| s.to_string());
+            } else if name.is_some() {
+                seq.extend(buf.iter().filter(|b| !b.is_ascii_whitespace()));
+            }
+        }
+        if let Some(done) = name {
+            reference.contigs.insert(done, seq);
+        }
+        if reference.contigs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no FASTA records found",
+            ));
+        }
+        Ok(reference)
+    }
+
+    pub fn contig(&self, name: &str) -> Option<&[u8]> {
+        self.contigs.get(name).map(|s| s.as_slice())
+    }
+}
+
+// pick the reference for an RNAME: a namespaced name such as "hg19#chr1" or
+// "hg19:chr1" goes to the assembly called hg19, anything else to the first
+// reference that has the contig
+pub fn find_contig<'a>(refs: &'a [Reference], rname: &str) -> Option<(&'a Reference, &'a [u8])> {
+    for sep in ['#', ':'] {
+        if let Some((asm, contig)) = rname.split_once(sep)
+            && let Some(r) = refs.iter().find(|r| r.name == asm)
+            && let Some(seq) = r.contig(contig).or_else(|| r.contig(rname))
+        {
+            return Some((r, seq));
+        }
+    }
+    refs.iter().find_map(|r| Some((r, r.contig(rname)?)))
+}
@@ -0,0 +1,810 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use memmap2::Mmap;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+const BATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+// files smaller than this are parsed on one thread
+const PARALLEL_MIN_BYTES: u64 = 8 << 20;
+
+// number of mandatory SAM columns, optional TAG:TYPE:VALUE fields follow
+pub const SAM_MANDATORY: usize = 11;
+
+// value of an optional field such as RG:Z:sample1
+pub fn find_tag<'a>(fields: &'a [String], tag: &str) -> Option<&'a str> {
+    fields.iter().skip(SAM_MANDATORY).find_map(|f| {
+        let mut parts = f.splitn(3, ':');
+        let name = parts.next()?;
+        let _kind = parts.next()?;
+        let value = parts.next()?;
+        if name == tag { Some(value) } else { None }
+    })
+}
+
+// QNAME and read1/read2 bits of a primary record, which should be unique in a
+// file; None for secondary and supplementary alignments
+pub fn template_key(fields: &[String]) -> Option<(String, u16)> {
+    let flag = fields.get(1)?.parse::<u16>().ok()?;
+    if flag & 0x900 != 0 {
+        return None;
+    }
+    Some((fields.first()?.clone(), flag & 0xc0))
+}
+
+// FLAG 0x4, or no reference to place the read on
+pub fn is_unmapped(fields: &[String]) -> bool {
+    let flag = fields.get(1).and_then(|f| f.parse::<u16>().ok());
+    flag.is_some_and(|f| f & 0x4 != 0) || fields.get(2).is_none_or(|r| r == "*")
+}
+
+// how decoded FLAG bits, CIGAR operations and alignment kinds are written: in
+// words (`secondary`, `soft clip`) or short codes (`sec`, `S`), from the config's
+// `decode_labels = long|short`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Labels {
+    #[default]
+    Long,
+    Short,
+}
+
+impl Labels {
+    pub fn parse(s: &str) -> Option<Labels> {
+        match s.to_ascii_lowercase().as_str() {
+            "long" | "full" => Some(Labels::Long),
+            "short" => Some(Labels::Short),
+            _ => None,
+        }
+    }
+
+    fn pick(self, long: &'static str, short: &'static str) -> &'static str {
+        match self {
+            Labels::Long => long,
+            Labels::Short => short,
+        }
+    }
+}
+
+// FLAG bits with their long and short labels and the names samtools flags uses
+const FLAG_BITS: [(u16, &str, &str, &str); 12] = [
+    (0x1, "paired", "pair", "PAIRED"),
+    (0x2, "proper pair", "proper", "PROPER_PAIR"),
+    (0x4, "unmapped", "unmap", "UNMAP"),
+    (0x8, "mate unmapped", "munmap", "MUNMAP"),
+    (0x10, "reverse strand", "rev", "REVERSE"),
+    (0x20, "mate reverse strand", "mrev", "MREVERSE"),
+    (0x40, "read1", "r1", "READ1"),
+    (0x80, "read2", "r2", "READ2"),
+    (0x100, "secondary", "sec", "SECONDARY"),
+    (0x200, "QC fail", "qcfail", "QCFAIL"),
+    (0x400, "duplicate", "dup", "DUP"),
+    (0x800, "supplementary", "sup", "SUPPLEMENTARY"),
+];
+
+// the set bits of a FLAG in bit order, e.g. 147 as paired, proper pair, reverse
+// strand, read2
+pub fn flag_labels(flag: u16, labels: Labels) -> Vec<&'static str> {
+    FLAG_BITS
+        .iter()
+        .filter(|(bit, ..)| flag & bit != 0)
+        .map(|&(_, long, short, _)| labels.pick(long, short))
+        .collect()
+}
+
+// a FLAG bit by its samtools name or short label, ignoring case
+pub fn flag_bit(name: &str) -> Option<u16> {
+    FLAG_BITS
+        .iter()
+        .find(|(_, _, short, samtools)| {
+            samtools.eq_ignore_ascii_case(name) || short.eq_ignore_ascii_case(name)
+        })
+        .map(|&(bit, ..)| bit)
+}
+
+// a CIGAR operation by name, or its letter when short
+pub fn cigar_op_label(op: char, labels: Labels) -> &'static str {
+    let (long, short) = match op {
+        'M' => ("match", "M"),
+        'I' => ("insertion", "I"),
+        'D' => ("deletion", "D"),
+        'N' => ("skipped", "N"),
+        'S' => ("soft clip", "S"),
+        'H' => ("hard clip", "H"),
+        'P' => ("padding", "P"),
+        '=' => ("sequence match", "="),
+        'X' => ("mismatch", "X"),
+        _ => ("unknown", "?"),
+    };
+    labels.pick(long, short)
+}
+
+// an alignment_kind as written: primary, secondary, supplementary or pri, sec, sup
+pub fn alignment_kind_label(kind: &str, labels: Labels) -> &'static str {
+    match kind {
+        "supplementary" => labels.pick("supplementary", "sup"),
+        "secondary" => labels.pick("secondary", "sec"),
+        _ => labels.pick("primary", "pri"),
+    }
+}
+
+// primary, secondary (0x100) or supplementary (0x800) alignment
+pub fn alignment_kind(fields: &[String]) -> &'static str {
+    let flag = fields
+        .get(1)
+        .and_then(|f| f.parse::<u16>().ok())
+        .unwrap_or(0);
+    if flag & 0x800 != 0 {
+        "supplementary"
+    } else if flag & 0x100 != 0 {
+        "secondary"
+    } else {
+        "primary"
+    }
+}
+
+// how a pair faces, read from its leftmost mate: FR (the usual inward pair), RF,
+// FF or RR; None unless both reads are mapped to the same contig
+pub fn pair_orientation(fields: &[String]) -> Option<&'static str> {
+    let flag = fields.get(1)?.parse::<u16>().ok()?;
+    if flag & 0x1 == 0 || flag & 0xc != 0 || (fields[6] != "=" && fields[6] != fields[2]) {
+        return None;
+    }
+    let pos = fields[3].parse::<u64>().ok()?;
+    let mate = fields[7].parse::<u64>().ok()?;
+    // TLEN is positive on the leftmost read, which settles mates at the same POS
+    let leftmost = pos < mate || (pos == mate && !fields[8].starts_with('-'));
+    let (reverse, mate_reverse) = (flag & 0x10 != 0, flag & 0x20 != 0);
+    let (left, right) = if leftmost {
+        (reverse, mate_reverse)
+    } else {
+        (mate_reverse, reverse)
+    };
+    Some(match (left, right) {
+        (false, true) => "FR",
+        (true, false) => "RF",
+        (false, false) => "FF",
+        (true, true) => "RR",
+    })
+}
+
+// value of a KEY:VALUE field on a header line, e.g. ID on an @RG line
+pub fn header_field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split('\t')
+        .skip(1)
+        .find_map(|f| f.strip_prefix(key)?.strip_prefix(':'))
+}
+
+pub fn header_read_groups(header: &[String]) -> Vec<String> {
+    header
+        .iter()
+        .filter(|l| l.starts_with("@RG"))
+        .filter_map(|l| header_field(l, "ID"))
+        .map(|id| id.to_string())
+        .collect()
+}
+
+#[derive(Default)]
+pub struct ParsedSam {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    // byte offset of each record's line in the file, parallel to rows
+    pub offsets: Vec<u64>,
+    // short records and lines that were not valid UTF-8
+    pub malformed: usize,
+    // short records kept in lenient mode by filling the missing columns with "*"
+    pub padded: usize,
+}
+
+// how lines of a tab-separated record format are told apart
+#[derive(Clone, Copy)]
+pub struct LineFormat {
+    pub header_prefix: char,
+    pub min_fields: usize,
+    // fill short records with this instead of rejecting them (--lenient)
+    pub pad: Option<&'static str>,
+    // the fields to store, bit i for field i and TAGS_BIT for the optional tags;
+    // the others are left empty (--columns), all are kept when None
+    pub keep: Option<u32>,
+}
+
+pub const TAGS_BIT: u32 = 1 << SAM_MANDATORY;
+
+impl LineFormat {
+    pub fn sam(lenient: bool, keep: Option<u32>) -> LineFormat {
+        LineFormat {
+            header_prefix: '@',
+            min_fields: SAM_MANDATORY,
+            pad: if lenient { Some("*") } else { None },
+            keep,
+        }
+    }
+}
+
+// split a record into fields; short records are rejected unless the format pads them
+pub fn parse_line(line: &str, format: &LineFormat) -> Option<Vec<String>> {
+    let mut fields: Vec<String> = match format.keep {
+        None => line.split('\t').map(|s| s.to_string()).collect(),
+        // empty strings hold no memory, so skipped fields cost only their slot
+        Some(keep) => line
+            .split('\t')
+            .enumerate()
+            .take(if keep & TAGS_BIT != 0 {
+                usize::MAX
+            } else {
+                format.min_fields
+            })
+            .map(|(i, s)| {
+                if keep & (1 << i.min(SAM_MANDATORY)) != 0 {
+                    s.to_string()
+                } else {
+                    String::new()
+                }
+            })
+            .collect(),
+    };
+    if fields.len() < format.min_fields {
+        fields.resize(format.min_fields, format.pad?.to_string());
+    }
+    Some(fields)
+}
+
+// blank the fields `keep` leaves out, as parse_line does for text records
+pub fn drop_unkept(fields: &mut Vec<String>, keep: Option<u32>) {
+    let Some(keep) = keep else {
+        return;
+    };
+    if keep & TAGS_BIT == 0 {
+        fields.truncate(SAM_MANDATORY);
+    }
+    for (i, field) in fields.iter_mut().enumerate().take(SAM_MANDATORY) {
+        if keep & (1 << i) == 0 {
+            *field = String::new();
+        }
+    }
+}
+
+impl ParsedSam {
+    // add one raw line, a header line or a record starting at `offset`
+    pub fn push_line(&mut self, mut buf: &[u8], offset: u64, format: &LineFormat) {
+        while let [rest @ .., b'\n' | b'\r'] = buf {
+            buf = rest;
+        }
+        if buf.is_empty() {
+            return;
+        }
+        // SAM and VCF are ASCII, so a lossy conversion means the file is corrupt:
+        // keep the record visible but count it instead of dropping it silently
+        let line = String::from_utf8_lossy(buf);
+        let mut malformed = matches!(line, Cow::Owned(_));
+        if line.starts_with(format.header_prefix) {
+            self.header.push(line.into_owned());
+        } else if let Some(fields) = parse_line(&line, format) {
+            if format.pad.is_some() && line.split('\t').count() < format.min_fields {
+                self.padded += 1;
+            }
+            self.rows.push(fields);
+            self.offsets.push(offset);
+        } else {
+            malformed = true;
+        }
+        if malformed {
+            self.malformed += 1;
+        }
+    }
+
+    // roughly the heap memory the rows take: the field vectors and their text
+    pub fn heap_bytes(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|fields| {
+                fields.capacity() * std::mem::size_of::<String>()
+                    + fields.iter().map(|f| f.capacity()).sum::<usize>()
+            })
+            .sum::<usize>()
+            + self.rows.capacity() * std::mem::size_of::<Vec<String>>()
+    }
+
+    pub fn append(&mut self, mut other: ParsedSam) {
+        self.header.append(&mut other.header);
+        self.rows.append(&mut other.rows);
+        self.offsets.append(&mut other.offsets);
+        self.malformed += other.malformed;
+        self.padded += other.padded;
+    }
+}
+
+pub fn parse_reader<R: BufRead>(reader: R, format: &LineFormat) -> io::Result<ParsedSam> {
+    let mut all = ParsedSam::default();
+    parse_chunks(reader, usize::MAX, format, |batch| {
+        all.append(batch);
+        true
+    })?;
+    Ok(all)
+}
+
+// an uncompressed file split into `threads` byte ranges at line boundaries,
+// parsed side by side and joined back in file order
+// parsed from a memory map when `mmap` is set and the file can be mapped
+pub fn parse_file(
+    path: &Path,
+    threads: usize,
+    mmap: bool,
+    format: &LineFormat,
+) -> io::Result<ParsedSam> {
+    if mmap && let Some(map) = map_file(path) {
+        return in_ranges(map.len() as u64, threads, |start, end| {
+            Ok(parse_slice(&map, start as usize, end as usize, format))
+        });
+    }
+    let len = std::fs::metadata(path)?.len();
+    in_ranges(len, threads, |start, end| {
+        parse_range(path, start, end, format)
+    })
+}
+
+// None for files that cannot be mapped (devices, procfs entries, a refused
+// mmap), which are read through a buffer instead
+fn map_file(path: &Path) -> Option<Mmap> {
+    let file = File::open(path).ok()?;
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+    // SAFETY: the map is only read; as with any mmap reader, another process
+    // truncating the file while it is parsed can still fault
+    unsafe { Mmap::map(&file) }.ok()
+}
+
+// run `parse` over `threads` byte ranges of a `len` byte file and join the parts
+// in order
+fn in_ranges(
+    len: u64,
+    threads: usize,
+    parse: impl Fn(u64, u64) -> io::Result<ParsedSam> + Sync,
+) -> io::Result<ParsedSam> {
+    let threads = if len < PARALLEL_MIN_BYTES {
+        1
+    } else {
+        threads.max(1) as u64
+    };
+    let step = len.div_ceil(threads).max(1);
+    let parse = &parse;
+    let parts: Vec<io::Result<ParsedSam>> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                let (start, end) = (i * step, ((i + 1) * step).min(len));
+                s.spawn(move || parse(start, end))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| {
+                h.join()
+                    .unwrap_or_else(|_| Err(io::Error::other("parser thread panicked")))
+            })
+            .collect()
+    });
+    let mut all = ParsedSam::default();
+    for part in parts {
+        all.append(part?);
+    }
+    Ok(all)
+}
+
+// the lines starting inside start..end; the last one is read whole even when it
+// runs past `end`, and the one running into `start` is left to the range before
+fn parse_range(path: &Path, start: u64, end: u64, format: &LineFormat) -> io::Result<ParsedSam> {
+    let mut file = File::open(path)?;
+    let mut offset = start.saturating_sub(1);
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::with_capacity(1 << 20, file);
+    let mut parsed = ParsedSam::default();
+    let mut buf = Vec::new();
+    if start > 0 {
+        offset += reader.read_until(b'\n', &mut buf)? as u64;
+    }
+    while offset < end {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        parsed.push_line(&buf, offset, format);
+        offset += n as u64;
+    }
+    Ok(parsed)
+}
+
+// parse_range over a mapped file
+fn parse_slice(data: &[u8], start: usize, end: usize, format: &LineFormat) -> ParsedSam {
+    let line_end = |from: usize| {
+        data[from..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(data.len(), |i| from + i + 1)
+    };
+    let mut parsed = ParsedSam::default();
+    let mut offset = if start > 0 { line_end(start - 1) } else { 0 };
+    while offset < end {
+        let next = line_end(offset);
+        parsed.push_line(&data[offset..next], offset as u64, format);
+        offset = next;
+    }
+    parsed
+}
+
+// hand records to `emit` in batches of new header lines and rows, flushing early
+// when input trickles in (pipes, FIFOs); stops when `emit` returns false
+pub fn parse_chunks<R: BufRead>(
+    mut reader: R,
+    batch_rows: usize,
+    format: &LineFormat,
+    mut emit: impl FnMut(ParsedSam) -> bool,
+) -> io::Result<()> {
+    let mut parsed = ParsedSam::default();
+    let mut last_emit = Instant::now();
+    let mut buf = Vec::new();
+    let mut offset: u64 = 0;
+    loop {
+        buf.clear();
+        let line_start = offset;
+        let n = reader.read_until(b'\n', &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        offset += n as u64;
+        parsed.push_line(&buf, line_start, format);
+        let due = !parsed.rows.is_empty() && last_emit.elapsed() >= BATCH_INTERVAL;
+        if (parsed.rows.len() >= batch_rows || due) && !emit(std::mem::take(&mut parsed)) {
+            return Ok(());
+        }
+        if due {
+            last_emit = Instant::now();
+        }
+    }
+    emit(parsed);
+    Ok(())
+}
+
+// CIGAR operations as (length, op); None for "*" or a malformed string
+pub fn parse_cigar(cigar: &str) -> Option<Vec<(u32, char)>> {
+    if cigar == "*" || cigar.is_empty() {
+        return None;
+    }
+    let mut ops = Vec::new();
+    let mut len: u32 = 0;
+    let mut has_len = false;
+    for c in cigar.chars() {
+        if let Some(d) = c.to_digit(10) {
+            len = len.checked_mul(10)?.checked_add(d)?;
+            has_len = true;
+        } else if "MIDNSHP=X".contains(c) && has_len {
+            ops.push((len, c));
+            len = 0;
+            has_len = false;
+        } else {
+            return None;
+        }
+    }
+    if has_len { None } else { Some(ops) }
+}
+
+// Phred scores from a QUAL string (ASCII - 33); None for "*" or bad characters
+pub fn phred_scores(qual: &str) -> Option<Vec<u8>> {
+    if qual == "*" {
+        return None;
+    }
+    qual.bytes()
+        .map(|b| {
+            if (33..=126).contains(&b) {
+                Some(b - 33)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// the other strand of SEQ, IUPAC codes included and case kept, for reads stored
+// reverse-complemented (FLAG 0x10)
+pub fn reverse_complement(seq: &str) -> String {
+    seq.chars()
+        .rev()
+        .map(|c| {
+            let base = match c.to_ascii_uppercase() {
+                'A' => 'T',
+                'C' => 'G',
+                'G' => 'C',
+                'T' | 'U' => 'A',
+                'R' => 'Y',
+                'Y' => 'R',
+                'K' => 'M',
+                'M' => 'K',
+                'B' => 'V',
+                'V' => 'B',
+                'D' => 'H',
+                'H' => 'D',
+                other => other,
+            };
+            if c.is_ascii_lowercase() {
+                base.to_ascii_lowercase()
+            } else {
+                base
+            }
+        })
+        .collect()
+}
+
+pub fn consumes_reference(op: char) -> bool {
+    matches!(op, 'M' | 'D' | 'N' | '=' | 'X')
+}
+
+// CIGARs of more than 65535 operations are kept in a CG:B:I tag (each value
+// length << 4 | op, as in BAM), leaving `<read length>S<reference length>N` as a
+// placeholder in the CIGAR column; the tag's operations, or None without one
+pub fn cigar_from_tag(fields: &[String]) -> Option<Vec<(u32, char)>> {
+    let placeholder = parse_cigar(fields.get(5)?)?;
+    let [(_, 'S'), (_, 'N')] = placeholder.as_slice() else {
+        return None;
+    };
+    let mut values = find_tag(fields, "CG")?.split(',');
+    if values.next()? != "I" {
+        return None;
+    }
+    values
+        .map(|v| {
+            let v: u32 = v.parse().ok()?;
+            let op = *b"MIDNSHP=X".get((v & 0xf) as usize)?;
+            Some((v >> 4, op as char))
+        })
+        .collect()
+}
+
+// the record's CIGAR operations, from the CG tag when the column is a placeholder
+pub fn record_cigar(fields: &[String]) -> Option<Vec<(u32, char)>> {
+    cigar_from_tag(fields).or_else(|| parse_cigar(fields.get(5)?))
+}
+
+// 1-based inclusive end of the alignment on the reference
+pub fn reference_end(pos: u64, fields: &[String]) -> u64 {
+    let span: u64 = record_cigar(fields)
+        .map(|ops| {
+            ops.iter()
+                .filter(|(_, op)| consumes_reference(*op))
+                .map(|(len, _)| *len as u64)
+                .sum()
+        })
+        .unwrap_or(0);
+    if span == 0 { pos } else { pos + span - 1 }
+}
+
+// the other alignments of a split read from its SA tag
+// (`RNAME,POS,STRAND,CIGAR,MAPQ,NM;` each) as RNAME, POS and CIGAR; entries that
+// do not parse are skipped
+pub fn split_parts(fields: &[String]) -> Vec<(&str, u64, &str)> {
+    let Some(sa) = find_tag(fields, "SA") else {
+        return vec![];
+    };
+    sa.split(';')
+        .filter_map(|part| {
+            let mut items = part.split(',');
+            let rname = items.next().filter(|r| !r.is_empty())?;
+            let pos = items.next()?.parse().ok()?;
+            let cigar = items.nth(1)?;
+            Some((rname, pos, cigar))
+        })
+        .collect()
+}
+
+// RNAME:START-END with 1-based inclusive coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Region {
+    // accepts "chr1", "chr1:1000" and "chr1:1,000-2,000"; contig names may contain ':'
+    pub fn parse(s: &str) -> Option<Region> {
+        let s = s.trim();
+        if s.is_empty() {
+            return None;
+        }
+        let number = |n: &str| n.replace(',', "").parse::<u64>().ok();
+        if let Some((name, range)) = s.rsplit_once(':') {
+            let bounds = match range.split_once('-') {
+                Some((a, b)) => number(a).zip(number(b)),
+                None => number(range).map(|p| (p, p)),
+            };
+            if let Some((start, end)) = bounds {
+                if name.is_empty() || start > end {
+                    return None;
+                }
+                return Some(Region {
+                    name: name.to_string(),
+                    start: start.max(1),
+                    end,
+                });
+            }
+        }
+        Some(Region {
+            name: s.to_string(),
+            start: 1,
+            end: u64::MAX,
+        })
+    }
+
+    pub fn overlaps(&self, fields: &[String]) -> bool {
+        if fields.get(2).map(|r| r.as_str()) != Some(self.name.as_str()) {
+            return false;
+        }
+        let Some(pos) = fields.get(3).and_then(|p| p.parse::<u64>().ok()) else {
+            return false;
+        };
+        let end = reference_end(pos, fields);
+        pos <= self.end && end >= self.start
+    }
+
+    // whether any part of a split read named in its SA tag overlaps the region
+    pub fn overlaps_split(&self, fields: &[String]) -> bool {
+        split_parts(fields).into_iter().any(|(rname, pos, cigar)| {
+            let span: u64 = parse_cigar(cigar)
+                .unwrap_or_default()
+                .iter()
+                .filter(|(_, op)| consumes_reference(*op))
+                .map(|(len, _)| *len as u64)
+                .sum();
+            let end = if span == 0 { pos } else { pos + span - 1 };
+            rname == self.name && pos <= self.end && end >= self.start
+        })
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.end == u64::MAX {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}:{}-{}", self.name, self.start, self.end)
+        }
+    }
+}
+
+// the candidate nearest to a mistyped contig name, if any is reasonably close;
+// a missing or extra "chr" prefix (1 vs chr1) counts as an exact match
+pub fn closest_name<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let bare = |s: &str| {
+        let lower = s.to_ascii_lowercase();
+        lower
+            .strip_prefix("chr")
+            .map(str::to_string)
+            .unwrap_or(lower)
+    };
+    let wanted = bare(name);
+    candidates
+        .iter()
+        .filter_map(|&c| {
+            let other = bare(c);
+            let d = edit_distance(&wanted, &other);
+            (d <= wanted.len().max(other.len()) / 2).then_some((d, c))
+        })
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+// Levenshtein distance over bytes
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.as_bytes().iter().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            row.push(substitute.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+// SO on the @HD line: coordinate, queryname, unsorted or unknown
+pub fn header_sort_order(header: &[String]) -> Option<&str> {
+    header
+        .iter()
+        .find(|l| l.starts_with("@HD"))
+        .and_then(|l| header_field(l, "SO"))
+}
+
+// contig name to length from @SQ SN/LN header lines
+pub fn header_reference_lengths(header: &[String]) -> HashMap<String, u64> {
+    header
+        .iter()
+        .filter(|l| l.starts_with("@SQ"))
+        .filter_map(|l| {
+            let name = header_field(l, "SN")?;
+            let len = header_field(l, "LN")?.parse().ok()?;
+            Some((name.to_string(), len))
+        })
+        .collect()
+}
+
+// contig name to its reference ID (tid): the order of the @SQ lines, as a BAM
+// numbers them
+pub fn header_reference_ids(header: &[String]) -> HashMap<String, usize> {
+    header
+        .iter()
+        .filter(|l| l.starts_with("@SQ"))
+        .filter_map(|l| header_field(l, "SN"))
+        .enumerate()
+        .map(|(tid, name)| (name.to_string(), tid))
+        .collect()
+}
+
+// a record starting or ending past its contig's @SQ length points at a broken file
+pub fn exceeds_reference(fields: &[String], lengths: &HashMap<String, u64>) -> bool {
+    let Some(len) = fields.get(2).and_then(|r| lengths.get(r)) else {
+        return false;
+    };
+    let Some(pos) = fields.get(3).and_then(|p| p.parse::<u64>().ok()) else {
+        return false;
+    };
+    if pos == 0 {
+        return false;
+    }
+    pos > *len || reference_end(pos, fields) > *len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(data: &[u8], format: &LineFormat) -> ParsedSam {
+        let mut all = ParsedSam::default();
+        parse_chunks(data, usize::MAX, format, |batch| {
+            all.append(batch);
+            true
+        })
+        .unwrap();
+        all
+    }
+
+    #[test]
+    fn invalid_utf8_record_is_kept_and_counted() {
+        let data: &[u8] = b"@HD\tVN:1.6\n\
+            r1\t0\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tIIII\n\
+            r\xff2\t0\tchr1\t200\t60\t4M\t*\t0\t0\tACGT\tIIII\n";
+        let parsed = parse_all(data, &LineFormat::sam(false, None));
+        assert_eq!(parsed.header, vec!["@HD\tVN:1.6"]);
+        assert_eq!(parsed.malformed, 1);
+        assert_eq!(parsed.rows.len(), 2);
+        let bad = data.windows(2).position(|w| w == b"r\xff").unwrap() as u64;
+        assert_eq!(parsed.offsets, vec![11, bad]);
+        assert_eq!(parsed.rows[1][0], "r\u{fffd}2");
+        assert_eq!(parsed.rows[1][3], "200");
+    }
+
+    fn record(cigar: &str) -> Vec<String> {
+        format!("r1\t0\tchr1\t100\t60\t{cigar}\t*\t0\t0\t*\t*")
+            .split('\t')
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn reference_end_counts_extended_cigar_operations() {
+        assert_eq!(reference_end(100, &record("10=2X5=")), 116);
+        assert_eq!(reference_end(100, &record("3S4M2I3D4M")), 110);
+        assert_eq!(reference_end(100, &record("*")), 100);
+    }
+}
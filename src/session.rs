@@ -0,0 +1,65 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::config::Config;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// the view over a file (filters, sort, selection, ...) as `key = value` lines in
+// ~/.config/varview/sessions, one file per data file named after its full path:
+// letters, digits and dots are kept, `/` becomes `_` and any other byte `-` and
+// its hex code, so no two paths share a name
+pub fn path_for(file: &Path) -> Option<PathBuf> {
+    let file = fs::canonicalize(file).ok()?;
+    let mut name = String::new();
+    for &b in file.as_os_str().as_encoded_bytes() {
+        match b {
+            b'/' => name.push('_'),
+            b'.' => name.push('.'),
+            b if b.is_ascii_alphanumeric() => name.push(b as char),
+            b => name.push_str(&format!("-{:02x}", b)),
+        }
+    }
+    Some(
+        Config::path()?
+            .parent()?
+            .join("sessions")
+            .join(format!("{}.session", name.trim_start_matches('_'))),
+    )
+}
+
+// the saved entries in order; None when there is no session for the file, or
+// the session's `file =` line names another one
+pub fn load(file: &Path) -> Option<Vec<(String, String)>> {
+    let text = fs::read_to_string(path_for(file)?).ok()?;
+    let entries: Vec<(String, String)> = text
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+    let full = fs::canonicalize(file).ok()?;
+    let saved_for = entries.iter().find(|(key, _)| key == "file")?;
+    (Path::new(&saved_for.1) == full).then_some(entries)
+}
+
+pub fn save(file: &Path, entries: &[(&str, String)]) -> io::Result<()> {
+    let path = path_for(file).ok_or_else(|| io::Error::other("no config directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut out = fs::File::create(path)?;
+    let full = fs::canonicalize(file)?;
+    writeln!(out, "file = {}", full.display())?;
+    for (key, value) in entries {
+        // a value is one line; line breaks would start a new entry
+        writeln!(out, "{} = {}", key, value.replace(['\n', '\r'], " "))?;
+    }
+    Ok(())
+}
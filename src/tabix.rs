@@ -0,0 +1,213 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::bgzf::BgzfReader;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+// a tabix (.tbi) or CSI (.csi) index over a bgzipped, position-sorted text file,
+// or a BAI over a BAM, whose reference names are in the BAM header instead
+pub struct Index {
+    min_shift: u32,
+    depth: u32,
+    names: Vec<String>,
+    refs: Vec<RefIndex>,
+}
+
+#[derive(Default)]
+struct RefIndex {
+    bins: HashMap<u32, Vec<(u64, u64)>>,
+    // smallest virtual offset per 16 kb window, tabix only
+    linear: Vec<u64>,
+}
+
+// the index next to a data file, trying FILE.tbi then FILE.csi
+pub fn index_path(path: &Path) -> Option<PathBuf> {
+    ["tbi", "csi"].iter().find_map(|ext| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(ext);
+        let candidate = PathBuf::from(name);
+        candidate.is_file().then_some(candidate)
+    })
+}
+
+impl Index {
+    pub fn load(path: &Path) -> io::Result<Index> {
+        let mut data = std::fs::read(path)?;
+        // .tbi and .csi are BGZF-compressed, a .bai is not
+        if data.starts_with(&[31, 139]) {
+            let mut text = Vec::new();
+            BgzfReader::new(BufReader::new(File::open(path)?)).read_to_end(&mut text)?;
+            data = text;
+        }
+        let mut r = Cursor {
+            data: &data,
+            pos: 0,
+        };
+        match r.bytes(4)? {
+            b"BAI\x01" => {
+                let n_ref = r.u32()?;
+                Index::empty(14, 5).read_refs(&mut r, n_ref, false)
+            }
+            b"TBI\x01" => {
+                let mut index = Index::empty(14, 5);
+                let n_ref = r.u32()?;
+                // format, col_seq, col_beg, col_end, meta, skip
+                r.bytes(24)?;
+                index.names = read_names(&mut r)?;
+                index.read_refs(&mut r, n_ref, false)
+            }
+            b"CSI\x01" => {
+                let mut index = Index::empty(r.u32()?, r.u32()?);
+                let l_aux = r.u32()? as usize;
+                let aux = r.bytes(l_aux)?;
+                // a tabix-style CSI keeps the .tbi header fields in its aux block
+                if l_aux >= 28 {
+                    index.names = read_names(&mut Cursor { data: aux, pos: 24 })?;
+                }
+                let n_ref = r.u32()?;
+                index.read_refs(&mut r, n_ref, true)
+            }
+            _ => Err(invalid("not a tabix, CSI or BAI index")),
+        }
+    }
+
+    fn empty(min_shift: u32, depth: u32) -> Index {
+        Index {
+            min_shift,
+            depth,
+            names: Vec::new(),
+            refs: Vec::new(),
+        }
+    }
+
+    fn read_refs(mut self, r: &mut Cursor, n_ref: u32, csi: bool) -> io::Result<Index> {
+        for _ in 0..n_ref {
+            let mut index = RefIndex::default();
+            for _ in 0..r.u32()? {
+                let bin = r.u32()?;
+                if csi {
+                    let _loffset = r.u64()?;
+                }
+                let n_chunk = r.u32()?;
+                let mut chunks = Vec::with_capacity(n_chunk as usize);
+                for _ in 0..n_chunk {
+                    chunks.push((r.u64()?, r.u64()?));
+                }
+                index.bins.insert(bin, chunks);
+            }
+            if !csi {
+                for _ in 0..r.u32()? {
+                    index.linear.push(r.u64()?);
+                }
+            }
+            self.refs.push(index);
+        }
+        Ok(self)
+    }
+
+    // virtual offset ranges that may hold records overlapping the 1-based,
+    // inclusive `start..=end` on `name`, sorted and merged
+    pub fn chunks(&self, name: &str, start: u64, end: u64) -> Vec<(u64, u64)> {
+        match self.names.iter().position(|n| n == name) {
+            Some(tid) => self.tid_chunks(tid, start, end),
+            None => vec![],
+        }
+    }
+
+    // the same by reference number, for a BAI
+    pub fn tid_chunks(&self, tid: usize, start: u64, end: u64) -> Vec<(u64, u64)> {
+        let Some(index) = self.refs.get(tid) else {
+            return vec![];
+        };
+        let beg = start.saturating_sub(1);
+        let limit = 1u64 << (self.min_shift + 3 * self.depth);
+        let end = end.min(limit);
+        // records before this offset end before the 16 kb window holding `beg`
+        let min_offset = index
+            .linear
+            .get((beg >> 14) as usize)
+            .or(index.linear.last())
+            .copied()
+            .unwrap_or(0);
+        let mut chunks: Vec<(u64, u64)> = reg2bins(beg, end, self.min_shift, self.depth)
+            .iter()
+            .filter_map(|bin| index.bins.get(bin))
+            .flatten()
+            .filter(|&&(_, e)| e > min_offset)
+            .copied()
+            .collect();
+        chunks.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(chunks.len());
+        for (b, e) in chunks {
+            match merged.last_mut() {
+                Some(last) if b <= last.1 => last.1 = last.1.max(e),
+                _ => merged.push((b, e)),
+            }
+        }
+        merged
+    }
+}
+
+// bins overlapping the 0-based, half-open `beg..end`, as in htslib's hts_reg2bins
+fn reg2bins(beg: u64, end: u64, min_shift: u32, depth: u32) -> Vec<u32> {
+    let mut bins = Vec::new();
+    if beg >= end {
+        return bins;
+    }
+    let end = end - 1;
+    let mut shift = min_shift + 3 * depth;
+    let mut first = 0u64;
+    for level in 0..=depth {
+        for bin in first + (beg >> shift)..=first + (end >> shift) {
+            bins.push(bin as u32);
+        }
+        shift = shift.saturating_sub(3);
+        first += 1 << (3 * level);
+    }
+    bins
+}
+
+fn read_names(r: &mut Cursor) -> io::Result<Vec<String>> {
+    let l_nm = r.u32()? as usize;
+    Ok(r.bytes(l_nm)?
+        .split(|&b| b == 0)
+        .filter(|n| !n.is_empty())
+        .map(|n| String::from_utf8_lossy(n).into_owned())
+        .collect())
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let out = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| invalid("index is truncated"))?;
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,347 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use ratatui::{
+    buffer::Buffer,
+    style::{Color, Modifier, Style},
+};
+
+// how many colors the terminal can show; the UI is drawn with whatever colors
+// read best and fitted to this afterwards
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorDepth {
+    #[default]
+    TrueColor,
+    Indexed256,
+    Ansi16,
+}
+
+// the UI's colors by what they mark rather than what they are. `rich` is drawn in
+// RGB and fitted to 256 colors by fit_colors; 16-color terminals get `basic`, the
+// named colors their own palette sets, instead of the nearest VGA approximations
+#[derive(Clone, Debug)]
+pub struct Theme {
+    // detail labels and the table header
+    pub label: Style,
+    pub muted: Style,
+    pub error: Style,
+    pub warning: Style,
+    // notes and the coordinate ruler
+    pub note: Style,
+    pub structural: Style,
+    pub success: Style,
+    // search matches outside the view
+    pub outside: Style,
+    // popups, the header row and menu selections
+    pub panel: Style,
+    pub highlight: Style,
+    pub tooltip: Style,
+    pub input: Style,
+    // picker entries
+    pub directory: Style,
+    pub fifo: Style,
+    pub variants: Style,
+    // selected row of the focused pane and of the other pane
+    pub focused: Color,
+    pub unfocused: Color,
+    // the focused column's header while reordering (m) and resizing (=)
+    pub reordering: Color,
+    pub resizing: Color,
+    pub search_match: Color,
+    pub stripe: Color,
+    pub canvas: Color,
+    pub dots: Color,
+    // A, C, G, T/U, N and the other IUPAC codes
+    pub bases: [Color; 6],
+    pub read_groups: [Color; 8],
+    // VCF allele frequency bands: rare, low frequency, common
+    pub frequencies: [Color; 3],
+    // base qualities below Q20, below Q30, above
+    pub qualities: [Color; 3],
+    // hom ref, het, hom alt (or haploid), missing
+    pub genotypes: [Color; 4],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::rich()
+    }
+}
+
+impl Theme {
+    pub fn new(depth: ColorDepth) -> Theme {
+        match depth {
+            ColorDepth::Ansi16 => Theme::basic(),
+            _ => Theme::rich(),
+        }
+    }
+
+    pub fn rich() -> Theme {
+        let fg = |r, g, b| Style::default().fg(Color::Rgb(r, g, b));
+        let ink = Color::Rgb(20, 20, 24);
+        Theme {
+            label: fg(240, 196, 90).add_modifier(Modifier::BOLD),
+            muted: fg(112, 116, 128),
+            error: fg(235, 85, 80),
+            warning: fg(240, 190, 70),
+            note: fg(90, 200, 220),
+            structural: fg(200, 125, 225),
+            success: fg(125, 205, 110),
+            outside: fg(150, 150, 156),
+            panel: Style::default().bg(Color::Rgb(44, 48, 58)),
+            highlight: Style::default().fg(ink).bg(Color::Rgb(240, 190, 70)),
+            tooltip: Style::default().fg(ink).bg(Color::Rgb(250, 230, 150)),
+            input: fg(240, 196, 90),
+            directory: fg(105, 160, 255).add_modifier(Modifier::BOLD),
+            fifo: fg(225, 135, 220),
+            variants: fg(135, 215, 225),
+            focused: Color::Rgb(60, 100, 170),
+            unfocused: Color::Rgb(70, 72, 82),
+            reordering: Color::Rgb(140, 70, 165),
+            resizing: Color::Rgb(50, 90, 190),
+            search_match: Color::Rgb(55, 120, 65),
+            stripe: Color::Rgb(38, 40, 46),
+            canvas: Color::Rgb(18, 18, 22),
+            dots: Color::Rgb(90, 200, 220),
+            bases: [
+                Color::Rgb(0, 175, 0),
+                Color::Rgb(70, 130, 255),
+                Color::Rgb(230, 140, 20),
+                Color::Rgb(230, 40, 40),
+                Color::Rgb(140, 140, 140),
+                Color::Rgb(190, 130, 230),
+            ],
+            read_groups: [
+                Color::Rgb(90, 200, 220),
+                Color::Rgb(220, 120, 200),
+                Color::Rgb(235, 200, 90),
+                Color::Rgb(125, 205, 110),
+                Color::Rgb(110, 150, 255),
+                Color::Rgb(240, 125, 110),
+                Color::Rgb(150, 230, 230),
+                Color::Rgb(190, 150, 255),
+            ],
+            frequencies: [
+                Color::Rgb(240, 110, 100),
+                Color::Rgb(240, 190, 70),
+                Color::Rgb(125, 205, 110),
+            ],
+            qualities: [
+                Color::Rgb(230, 70, 70),
+                Color::Rgb(235, 190, 60),
+                Color::Rgb(110, 200, 100),
+            ],
+            genotypes: [
+                Color::Rgb(120, 120, 130),
+                Color::Rgb(240, 190, 70),
+                Color::Rgb(240, 110, 100),
+                Color::Rgb(170, 170, 176),
+            ],
+        }
+    }
+
+    pub fn basic() -> Theme {
+        let fg = |c| Style::default().fg(c);
+        Theme {
+            label: fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            muted: fg(Color::DarkGray),
+            error: fg(Color::Red),
+            warning: fg(Color::Yellow),
+            note: fg(Color::Cyan),
+            structural: fg(Color::Magenta),
+            success: fg(Color::Green),
+            outside: fg(Color::Gray),
+            panel: Style::default().bg(Color::DarkGray),
+            highlight: Style::default().fg(Color::Black).bg(Color::Yellow),
+            tooltip: Style::default().fg(Color::Black).bg(Color::LightYellow),
+            input: fg(Color::Yellow),
+            directory: fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+            fifo: fg(Color::LightMagenta),
+            variants: fg(Color::LightCyan),
+            focused: Color::LightBlue,
+            unfocused: Color::DarkGray,
+            reordering: Color::Magenta,
+            resizing: Color::Blue,
+            search_match: Color::LightGreen,
+            stripe: Color::Black,
+            canvas: Color::Black,
+            dots: Color::Cyan,
+            bases: [
+                Color::Green,
+                Color::LightBlue,
+                Color::Yellow,
+                Color::Red,
+                Color::Gray,
+                Color::Magenta,
+            ],
+            read_groups: [
+                Color::Cyan,
+                Color::Magenta,
+                Color::Yellow,
+                Color::Green,
+                Color::LightBlue,
+                Color::LightRed,
+                Color::LightCyan,
+                Color::LightMagenta,
+            ],
+            frequencies: [Color::LightRed, Color::Yellow, Color::Green],
+            qualities: [Color::Red, Color::Yellow, Color::Green],
+            genotypes: [Color::DarkGray, Color::Yellow, Color::LightRed, Color::Gray],
+        }
+    }
+
+    // the genome browser palette: A green, C blue, G orange, T red, N grey, and a
+    // violet for the other IUPAC codes; lowercase bases share their uppercase color
+    pub fn base(&self, base: char) -> Option<Color> {
+        let i = match base.to_ascii_uppercase() {
+            'A' => 0,
+            'C' => 1,
+            'G' => 2,
+            'T' | 'U' => 3,
+            'N' => 4,
+            'R' | 'Y' | 'K' | 'M' | 'S' | 'W' | 'B' | 'D' | 'H' | 'V' => 5,
+            _ => return None,
+        };
+        Some(self.bases[i])
+    }
+
+    pub fn quality(&self, q: u8) -> Color {
+        self.qualities[match q {
+            0..20 => 0,
+            20..30 => 1,
+            _ => 2,
+        }]
+    }
+
+    pub fn cigar_op(&self, op: char) -> Style {
+        match op {
+            'M' | '=' => Style::default(),
+            'X' => self.error.add_modifier(Modifier::BOLD),
+            'I' => self.structural,
+            'D' => self.error,
+            _ => self.muted,
+        }
+    }
+}
+
+// the 16 named colors with the VGA palette most terminals start from
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (170, 0, 0)),
+    (Color::Green, (0, 170, 0)),
+    (Color::Yellow, (170, 85, 0)),
+    (Color::Blue, (0, 0, 170)),
+    (Color::Magenta, (170, 0, 170)),
+    (Color::Cyan, (0, 170, 170)),
+    (Color::Gray, (170, 170, 170)),
+    (Color::DarkGray, (85, 85, 85)),
+    (Color::LightRed, (255, 85, 85)),
+    (Color::LightGreen, (85, 255, 85)),
+    (Color::LightYellow, (255, 255, 85)),
+    (Color::LightBlue, (85, 85, 255)),
+    (Color::LightMagenta, (255, 85, 255)),
+    (Color::LightCyan, (85, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+impl ColorDepth {
+    // COLORTERM=truecolor/24bit, else a TERM ending in 256color, else 16 colors
+    pub fn detect() -> ColorDepth {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Indexed256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<ColorDepth> {
+        match s.to_ascii_lowercase().as_str() {
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Indexed256),
+            "16" => Some(ColorDepth::Ansi16),
+            _ => None,
+        }
+    }
+
+    fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, c) => c,
+            (ColorDepth::Indexed256, Color::Rgb(r, g, b)) => {
+                Color::Indexed(nearest_indexed(r, g, b))
+            }
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => nearest_ansi(r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(i)) => {
+                let (r, g, b) = indexed_rgb(i);
+                nearest_ansi(r, g, b)
+            }
+            (_, c) => c,
+        }
+    }
+}
+
+// replace the colors of a drawn frame that the terminal cannot show
+pub fn fit_colors(buf: &mut Buffer, depth: ColorDepth) {
+    if depth == ColorDepth::TrueColor {
+        return;
+    }
+    for cell in buf.content.iter_mut() {
+        cell.fg = depth.fit(cell.fg);
+        cell.bg = depth.fit(cell.bg);
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn nearest_ansi(r: u8, g: u8, b: u8) -> Color {
+    ANSI.iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map(|(c, _)| *c)
+        .unwrap_or(Color::Reset)
+}
+
+// levels of the 6x6x6 color cube (16-231); 232-255 are grays from 8 to 238
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn indexed_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => ANSI[i as usize].1,
+        16..=231 => {
+            let i = i - 16;
+            (
+                CUBE[(i / 36) as usize],
+                CUBE[(i / 6 % 6) as usize],
+                CUBE[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let v = 8 + (i - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+// the closer of the nearest cube color and the nearest gray
+fn nearest_indexed(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| {
+        (0..6)
+            .min_by_key(|&l| (CUBE[l] as i32 - v as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+    let mean = (r as u32 + g as u32 + b as u32) / 3;
+    let gray = 232 + (mean.saturating_sub(3) / 10).min(23) as u8;
+    if distance(indexed_rgb(gray), (r, g, b)) < distance(indexed_rgb(cube), (r, g, b)) {
+        gray
+    } else {
+        cube
+    }
+}
@@ -0,0 +1,324 @@
+/*
+Gaurav Sablok
+codeprog@icloud.com
+*/
+
+use crate::{
+    bgzf::{BgzfReader, StopAtTruncation},
+    sam::{LineFormat, ParsedSam, Region},
+    tabix,
+};
+use flate2::read::MultiGzDecoder;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+// the eight fixed VCF columns, FORMAT, all sample columns folded into one, and
+// the derived and byte offset columns shared with SAM mode
+pub const COLUMNS: [&str; 13] = [
+    "CHROM", "POS", "ID", "REF", "ALT", "QUAL", "FILTER", "INFO", "FORMAT", "SAMPLES", "TYPE",
+    "DERIVED", "OFFSET",
+];
+pub const SAMPLES_COLUMN: usize = 9;
+pub const TYPE_COLUMN: usize = 10;
+
+pub const VARIANT_TYPES: [&str; 7] = ["SNV", "MNV", "INS", "DEL", "COMPLEX", "SV", "MIXED"];
+
+pub const FORMAT: LineFormat = LineFormat {
+    header_prefix: '#',
+    min_fields: 8,
+    pad: None,
+    keep: None,
+};
+
+pub fn is_vcf(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name.ends_with(".vcf") || name.ends_with(".vcf.gz") || name.ends_with(".bcf")
+}
+
+pub fn is_gzipped(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gz"))
+}
+
+// sample names from the #CHROM header line
+pub fn sample_names(header: &[String]) -> Vec<String> {
+    header
+        .iter()
+        .rev()
+        .find(|l| l.starts_with("#CHROM"))
+        .map(|l| l.split('\t').skip(9).map(|s| s.to_string()).collect())
+        .unwrap_or_default()
+}
+
+// contig IDs from ##contig=<ID=...> header lines
+pub fn header_contigs(header: &[String]) -> Vec<String> {
+    header
+        .iter()
+        .filter_map(|l| l.strip_prefix("##contig=<"))
+        .filter_map(|l| {
+            l.trim_end_matches('>')
+                .split(',')
+                .find_map(|kv| kv.strip_prefix("ID="))
+        })
+        .map(|id| id.to_string())
+        .collect()
+}
+
+// FORMAT keys paired with the values of the `sample`th sample column
+pub fn sample_fields(fields: &[String], sample: usize) -> Vec<(&str, &str)> {
+    let (Some(format), Some(values)) = (fields.get(8), fields.get(SAMPLES_COLUMN + sample)) else {
+        return vec![];
+    };
+    format.split(':').zip(values.split(':')).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zygosity {
+    HomRef,
+    Het,
+    HomAlt,
+    // a single called allele, e.g. on chrY
+    Haploid,
+    Missing,
+}
+
+impl Zygosity {
+    pub fn parse(s: &str) -> Option<Zygosity> {
+        match s.to_ascii_lowercase().replace([' ', '_', '-'], "").as_str() {
+            "homref" | "ref" => Some(Zygosity::HomRef),
+            "het" => Some(Zygosity::Het),
+            "homalt" | "hom" | "alt" => Some(Zygosity::HomAlt),
+            "haploid" | "hemi" => Some(Zygosity::Haploid),
+            "missing" | "nocall" => Some(Zygosity::Missing),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Zygosity::HomRef => "hom ref",
+            Zygosity::Het => "het",
+            Zygosity::HomAlt => "hom alt",
+            Zygosity::Haploid => "haploid",
+            Zygosity::Missing => "missing",
+        }
+    }
+}
+
+// zygosity of a GT value such as 0/1, 1|1, ./. or 2
+pub fn zygosity(gt: &str) -> Zygosity {
+    let alleles: Vec<&str> = gt.split(['/', '|']).collect();
+    if alleles.iter().any(|a| *a == "." || a.is_empty()) {
+        return Zygosity::Missing;
+    }
+    if alleles.len() == 1 {
+        return Zygosity::Haploid;
+    }
+    if alleles.iter().all(|a| *a == alleles[0]) {
+        if alleles[0] == "0" {
+            Zygosity::HomRef
+        } else {
+            Zygosity::HomAlt
+        }
+    } else {
+        Zygosity::Het
+    }
+}
+
+// zygosity of the `sample`th sample, None when it has no GT
+pub fn sample_zygosity(fields: &[String], sample: usize) -> Option<Zygosity> {
+    sample_fields(fields, sample)
+        .into_iter()
+        .find(|(key, _)| *key == "GT")
+        .map(|(_, gt)| zygosity(gt))
+}
+
+// value of a KEY=VALUE entry in INFO; flags without a value give ""
+pub fn info_field<'a>(fields: &'a [String], key: &str) -> Option<&'a str> {
+    fields
+        .get(7)?
+        .split(';')
+        .find_map(|entry| match entry.split_once('=') {
+            Some((k, v)) => (k == key).then_some(v),
+            None => (entry == key).then_some(""),
+        })
+}
+
+// highest frequency in a per-allele INFO list such as AF=0.01,0.2
+pub fn max_frequency(fields: &[String], key: &str) -> Option<f64> {
+    info_field(fields, key)?
+        .split(',')
+        .filter_map(|v| v.parse::<f64>().ok())
+        .reduce(f64::max)
+}
+
+// SNV, MNV, INS, DEL, COMPLEX or SV (symbolic and breakend ALTs) from REF and ALT;
+// multi-allelic records whose alleles differ in type are MIXED
+pub fn variant_type(fields: &[String]) -> &'static str {
+    let (Some(reference), Some(alt)) = (fields.get(3), fields.get(4)) else {
+        return "";
+    };
+    let mut kinds = alt
+        .split(',')
+        .filter(|a| *a != "*" && *a != ".")
+        .map(|a| allele_type(reference, a));
+    let Some(first) = kinds.next() else {
+        return "";
+    };
+    if kinds.all(|k| k == first) {
+        first
+    } else {
+        "MIXED"
+    }
+}
+
+fn allele_type(reference: &str, alt: &str) -> &'static str {
+    if alt.starts_with('<') || alt.contains('[') || alt.contains(']') {
+        return "SV";
+    }
+    match (reference.len(), alt.len()) {
+        (1, 1) => "SNV",
+        (r, a) if r == a => "MNV",
+        (r, a) if r < a && alt.starts_with(reference) => "INS",
+        (r, a) if r > a && reference.starts_with(alt) => "DEL",
+        _ => "COMPLEX",
+    }
+}
+
+// the mate of a breakend ALT such as N[chr2:321682[ and how the two sides join
+#[derive(Debug, PartialEq)]
+pub struct Breakend {
+    pub chrom: String,
+    pub pos: u64,
+    pub joining: &'static str,
+}
+
+pub fn is_breakend(alt: &str) -> bool {
+    alt.contains('[') || alt.contains(']')
+}
+
+// the four VCF breakend forms t[p[, t]p], ]p]t and [p[t; None when malformed
+pub fn parse_breakend(alt: &str) -> Option<Breakend> {
+    let bracket = alt.chars().find(|&c| c == '[' || c == ']')?;
+    let parts: Vec<&str> = alt.split(bracket).collect();
+    let [before, mate, after] = parts[..] else {
+        return None;
+    };
+    let joining = match (before.is_empty(), after.is_empty(), bracket) {
+        (false, true, '[') => "joined after REF, mate continues to the right",
+        (false, true, _) => "joined after REF, mate reverse-complemented, continues to the left",
+        (true, false, ']') => "joined before REF, mate continues to the left",
+        (true, false, _) => "joined before REF, mate reverse-complemented, continues to the right",
+        _ => return None,
+    };
+    let (chrom, pos) = mate.rsplit_once(':')?;
+    if chrom.is_empty() {
+        return None;
+    }
+    Some(Breakend {
+        chrom: chrom.to_string(),
+        pos: pos.parse().ok()?,
+        joining,
+    })
+}
+
+// type names from a user list; "indel" stands for INS and DEL
+pub fn parse_types(list: &str) -> Option<Vec<&'static str>> {
+    let mut types = Vec::new();
+    for name in list.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if name.eq_ignore_ascii_case("indel") {
+            types.extend(["INS", "DEL"]);
+        } else {
+            types.push(
+                *VARIANT_TYPES
+                    .iter()
+                    .find(|t| t.eq_ignore_ascii_case(name))?,
+            );
+        }
+    }
+    Some(types)
+}
+
+// last reference base covered: INFO END for structural records, else POS + len(REF) - 1
+pub fn record_end(fields: &[String]) -> Option<u64> {
+    let pos: u64 = fields.get(1)?.parse().ok()?;
+    if let Some(end) = info_field(fields, "END").and_then(|e| e.parse().ok()) {
+        return Some(end);
+    }
+    let ref_len = fields.get(3).map(|r| r.len() as u64).unwrap_or(1).max(1);
+    Some(pos + ref_len - 1)
+}
+
+pub fn overlaps(region: &Region, fields: &[String]) -> bool {
+    if fields.first().map(|c| c.as_str()) != Some(region.name.as_str()) {
+        return false;
+    }
+    let Some(pos) = fields.get(1).and_then(|p| p.parse::<u64>().ok()) else {
+        return false;
+    };
+    let end = record_end(fields).unwrap_or(pos);
+    pos <= region.end && end >= region.start
+}
+
+// read only the records of an indexed, bgzipped VCF that may overlap `region`,
+// plus the header; offsets are BGZF virtual offsets. None without an index
+pub fn read_region(path: &Path, region: &Region) -> io::Result<Option<ParsedSam>> {
+    let Some(index_path) = tabix::index_path(path) else {
+        return Ok(None);
+    };
+    let index = tabix::Index::load(&index_path)
+        .map_err(|e| io::Error::new(e.kind(), format!("index {}: {}", index_path.display(), e)))?;
+    let mut reader = BgzfReader::new(BufReader::new(File::open(path)?));
+    let mut parsed = ParsedSam::default();
+    let mut buf = Vec::new();
+    // the header sits at the start of the file, before the first record
+    loop {
+        buf.clear();
+        if reader.fill_buf()?.first() != Some(&b'#') {
+            break;
+        }
+        reader.read_until(b'\n', &mut buf)?;
+        parsed.push_line(&buf, 0, &FORMAT);
+    }
+    for (start, end) in index.chunks(&region.name, region.start, region.end) {
+        reader.seek_virtual(start)?;
+        loop {
+            let offset = reader.virtual_offset();
+            if offset >= end {
+                break;
+            }
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                // a truncated file keeps the records before the cut
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(Some(parsed)),
+                Err(e) => return Err(e),
+            }
+            if buf.first() != Some(&b'#') {
+                parsed.push_line(&buf, offset, &FORMAT);
+            }
+        }
+    }
+    Ok(Some(parsed))
+}
+
+// a reader over the decompressed text of plain or gzipped (including bgzipped) files;
+// a gzipped file cut short reads up to the cut
+pub fn open_text(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    let file = File::open(path)?;
+    if is_gzipped(path) {
+        Ok(Box::new(BufReader::with_capacity(
+            1 << 20,
+            StopAtTruncation(MultiGzDecoder::new(BufReader::new(file))),
+        )))
+    } else {
+        Ok(Box::new(BufReader::with_capacity(1 << 20, file)))
+    }
+}